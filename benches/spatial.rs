@@ -0,0 +1,75 @@
+//! Spatial index benchmarks, run with `cargo bench --bench spatial`.
+//!
+//! Entities are spread over a square world with the same density as a match,
+//! so the number of neighbours per query stays constant across sizes.
+
+use ant_engine::shared::ecs::{Entities, Entity};
+use ant_engine::shared::fixed::{Fixed, FixedVec2};
+use ant_engine::shared::rng::Rng;
+use ant_engine::shared::spatial::SpatialGrid;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn random_points(count: usize, world_size: Fixed) -> Vec<(Entity, FixedVec2)> {
+    let mut entities = Entities::new();
+    let mut rng = Rng::new(count as u64);
+    (0..count)
+        .map(|_| {
+            let x = rng.fixed_range(Fixed::ZERO, world_size);
+            let y = rng.fixed_range(Fixed::ZERO, world_size);
+            (entities.spawn(), FixedVec2::new(x, y))
+        })
+        .collect()
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    for count in [1_000, 10_000, 100_000] {
+        // about one entity per 4x4 area
+        let world_size = Fixed::from_int(((count * 16) as f64).sqrt() as i32);
+        let points = random_points(count, world_size);
+        let mut grid = SpatialGrid::new(FixedVec2::new(world_size, world_size), Fixed::from_int(8));
+
+        let rebuild = time(|| grid.rebuild(black_box(&points).iter().copied()));
+
+        let radius = Fixed::from_int(6);
+        let mut found = 0usize;
+        let radius_queries = time(|| {
+            for (_, position) in &points {
+                grid.for_each_in_radius(*position, radius, |entity, _| {
+                    black_box(entity);
+                    found += 1;
+                });
+            }
+        });
+
+        let extent = FixedVec2::from_int(4, 4);
+        let rect_queries = time(|| {
+            for (_, position) in &points {
+                grid.for_each_in_rect(*position - extent, *position + extent, |entity, _| {
+                    black_box(entity);
+                });
+            }
+        });
+
+        println!(
+            "{:>7} entities: rebuild {:>10.3?}, {} radius queries {:>10.3?} ({:.1} hits each), {} rect queries {:>10.3?}",
+            count,
+            rebuild,
+            count,
+            radius_queries,
+            found as f64 / (count as f64 * ITERATIONS as f64),
+            count,
+            rect_queries,
+        );
+    }
+}
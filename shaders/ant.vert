@@ -0,0 +1,24 @@
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 offset;
+layout(location = 2) in float heading;
+layout(location = 3) in float scale;
+layout(location = 4) in vec4 color;
+layout(location = 5) in uint colony;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view_projection;
+} camera;
+
+void main() {
+    vec2 direction = vec2(cos(heading), sin(heading));
+    vec2 scaled = position * scale;
+    vec2 rotated = vec2(
+        scaled.x * direction.x - scaled.y * direction.y,
+        scaled.x * direction.y + scaled.y * direction.x
+    );
+    gl_Position = camera.view_projection * vec4(offset + rotated, 0.0, 1.0);
+    out_color = color;
+}
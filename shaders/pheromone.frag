@@ -0,0 +1,31 @@
+#version 450
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 f_color;
+
+// one channel per color component
+layout(set = 1, binding = 0) uniform sampler2D intensities;
+// one row per channel, from no pheromone on the left to saturated
+layout(set = 1, binding = 1) uniform sampler2D ramps;
+
+layout(push_constant) uniform Field {
+    vec2 size;
+    float gain;
+} field;
+
+const int CHANNELS = 3;
+
+void main() {
+    vec3 intensity = clamp(texture(intensities, in_uv).rgb * field.gain, 0.0, 1.0);
+    // premultiplied, later channels are laid over earlier ones
+    vec4 color = vec4(0.0);
+    for (int channel = 0; channel < CHANNELS; channel++) {
+        float row = (float(channel) + 0.5) / float(CHANNELS);
+        vec4 ramp = texture(ramps, vec2(intensity[channel], row));
+        color = vec4(ramp.rgb * ramp.a, ramp.a) + color * (1.0 - ramp.a);
+    }
+    if (color.a < 0.01) {
+        discard;
+    }
+    f_color = vec4(color.rgb / color.a, color.a);
+}
@@ -0,0 +1,18 @@
+#version 450
+layout(location = 0) in vec2 corner;
+
+layout(location = 0) out vec2 out_uv;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view_projection;
+} camera;
+
+layout(push_constant) uniform Field {
+    vec2 size;
+    float gain;
+} field;
+
+void main() {
+    gl_Position = camera.view_projection * vec4(corner * field.size, 0.0, 1.0);
+    out_uv = corner;
+}
@@ -0,0 +1,16 @@
+#version 450
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_tint;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform sampler2D atlas;
+
+void main() {
+    vec4 color = texture(atlas, in_uv) * in_tint;
+    // transparent pixels must not hide what is drawn later
+    if (color.a < 0.01) {
+        discard;
+    }
+    f_color = color;
+}
@@ -0,0 +1,27 @@
+#version 450
+layout(location = 0) in vec2 corner;
+layout(location = 1) in vec2 offset;
+layout(location = 2) in float heading;
+layout(location = 3) in vec2 size;
+layout(location = 4) in vec2 uv_min;
+layout(location = 5) in vec2 uv_max;
+layout(location = 6) in vec4 tint;
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out vec4 out_tint;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view_projection;
+} camera;
+
+void main() {
+    vec2 direction = vec2(cos(heading), sin(heading));
+    vec2 scaled = corner * size;
+    vec2 rotated = vec2(
+        scaled.x * direction.x - scaled.y * direction.y,
+        scaled.x * direction.y + scaled.y * direction.x
+    );
+    gl_Position = camera.view_projection * vec4(offset + rotated, 0.0, 1.0);
+    out_uv = mix(uv_min, uv_max, corner + 0.5);
+    out_tint = tint;
+}
@@ -0,0 +1,27 @@
+#version 450
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(push_constant) uniform Screen {
+    vec2 size;
+    uint linear_output;
+} screen;
+
+vec3 linear_from_srgb(vec3 srgb) {
+    vec3 lower = srgb / 12.92;
+    vec3 higher = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return mix(higher, lower, lessThan(srgb, vec3(0.04045)));
+}
+
+void main() {
+    // egui blends in gamma space, sRGB targets encode again on write
+    vec4 color = in_color * texture(tex, in_uv);
+    if (screen.linear_output != 0) {
+        color.rgb = linear_from_srgb(color.rgb);
+    }
+    f_color = color;
+}
@@ -0,0 +1,211 @@
+//! RTS-style camera controls: keyboard and edge scrolling, dragging, scroll
+//! wheel zoom and optional orbiting, driven by the actions of an
+//! [`InputState`] once per frame.
+
+use crate::client::input::{Action, InputState};
+use crate::renderer::camera::Camera;
+use crate::shared::config::Config;
+use nalgebra_glm::{vec2, Vec2};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSettings {
+    /// Keyboard and edge scrolling speed, in screen heights per second.
+    pub pan_speed: f32,
+    pub edge_scroll: bool,
+    /// Width of the edge scrolling border, in pixels.
+    pub edge_margin: f32,
+    pub orbit: bool,
+    /// Keyboard rotation, in radians per second.
+    pub rotate_speed: f32,
+    /// Keyboard zoom, in wheel lines per second.
+    pub zoom_speed: f32,
+    /// Orbit drag rotation, in radians per pixel.
+    pub orbit_sensitivity: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            pan_speed: 1.0,
+            edge_scroll: true,
+            edge_margin: 8.0,
+            orbit: true,
+            rotate_speed: 1.5,
+            zoom_speed: 8.0,
+            orbit_sensitivity: 0.01,
+        }
+    }
+}
+
+impl CameraSettings {
+    /// Reads the `camera` section.
+    pub fn from_config(config: &Config) -> CameraSettings {
+        let default = CameraSettings::default();
+        CameraSettings {
+            pan_speed: config.get_or("camera.pan_speed", default.pan_speed),
+            edge_scroll: config.get_or("camera.edge_scroll", default.edge_scroll),
+            edge_margin: config.get_or("camera.edge_margin", default.edge_margin),
+            orbit: config.get_or("camera.orbit", default.orbit),
+            rotate_speed: config.get_or("camera.rotate_speed", default.rotate_speed),
+            zoom_speed: config.get_or("camera.zoom_speed", default.zoom_speed),
+            orbit_sensitivity: config.get_or("camera.orbit_sensitivity", default.orbit_sensitivity),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    settings: CameraSettings,
+}
+
+impl CameraController {
+    pub fn new(settings: CameraSettings) -> CameraController {
+        CameraController { settings }
+    }
+
+    /// Moves the camera for this frame's input, `elapsed` after the last
+    /// update.
+    pub fn update(&self, input: &InputState, camera: &mut Camera, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32();
+        let orbit = self.settings.orbit;
+
+        if input.held(Action::CameraDrag) {
+            let delta = input.cursor_delta();
+            if orbit && input.held(Action::CameraOrbit) {
+                let sensitivity = self.settings.orbit_sensitivity;
+                camera.orbit(-delta.x * sensitivity, delta.y * sensitivity);
+            } else {
+                camera.drag(delta);
+            }
+        }
+
+        let mut direction = vec2(
+            input.axis(Action::CameraPanLeft, Action::CameraPanRight),
+            input.axis(Action::CameraPanUp, Action::CameraPanDown),
+        );
+        if self.settings.edge_scroll && !input.held(Action::CameraDrag) {
+            if let Some(cursor) = input.cursor() {
+                direction += self.edge_direction(cursor, camera.extent());
+            }
+        }
+        if direction != Vec2::zeros() {
+            let screen_height = camera.world_units_per_pixel() * camera.extent()[1] as f32;
+            let speed = self.settings.pan_speed * screen_height * seconds;
+            camera.pan(direction.normalize() * speed);
+        }
+
+        let rotate = input.axis(Action::CameraRotateLeft, Action::CameraRotateRight);
+        if orbit && rotate != 0.0 {
+            camera.orbit(rotate * self.settings.rotate_speed * seconds, 0.0);
+        }
+
+        let zoom = input.axis(Action::CameraZoomOut, Action::CameraZoomIn);
+        camera.zoom(input.scroll() + zoom * self.settings.zoom_speed * seconds);
+    }
+
+    fn edge_direction(&self, cursor: Vec2, extent: [u32; 2]) -> Vec2 {
+        let margin = self.settings.edge_margin;
+        let (width, height) = (extent[0] as f32, extent[1] as f32);
+        let axis = |position: f32, size: f32| {
+            if position < margin {
+                -1.0
+            } else if position >= size - margin {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        vec2(axis(cursor.x, width), axis(cursor.y, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::input::{Binding, InputEvent};
+    use winit::event::{MouseButton, VirtualKeyCode};
+
+    fn setup() -> (CameraController, InputState, Camera) {
+        let controller = CameraController::new(CameraSettings::default());
+        let mut camera = Camera::new(vec2(64.0, 64.0), [800, 600]);
+        camera.zoom(3.0);
+        (controller, InputState::default(), camera)
+    }
+
+    fn frame(controller: &CameraController, input: &mut InputState, camera: &mut Camera) {
+        controller.update(input, camera, Duration::from_millis(100));
+        input.end_frame();
+    }
+
+    #[test]
+    fn test_keys_pan_while_held() {
+        let (controller, mut input, mut camera) = setup();
+        let start = camera.target();
+        let key = Binding::Key(VirtualKeyCode::D);
+        input.handle(InputEvent::Pressed(key));
+        frame(&controller, &mut input, &mut camera);
+        let moved = camera.target();
+        assert!(moved.x > start.x);
+        assert_eq!(moved.y, start.y);
+
+        input.handle(InputEvent::Released(key));
+        frame(&controller, &mut input, &mut camera);
+        assert_eq!(camera.target(), moved);
+    }
+
+    #[test]
+    fn test_edge_scrolling() {
+        let (controller, mut input, mut camera) = setup();
+        let start = camera.target();
+        input.handle(InputEvent::Cursor([400.0, 2.0]));
+        frame(&controller, &mut input, &mut camera);
+        assert!(camera.target().y < start.y);
+
+        input.handle(InputEvent::CursorLeft);
+        let stopped = camera.target();
+        frame(&controller, &mut input, &mut camera);
+        assert_eq!(camera.target(), stopped);
+    }
+
+    #[test]
+    fn test_drag_pans_and_orbits() {
+        let (controller, mut input, mut camera) = setup();
+        let start = camera.target();
+        input.handle(InputEvent::Cursor([400.0, 300.0]));
+        input.handle(InputEvent::Pressed(Binding::Mouse(MouseButton::Middle)));
+        input.handle(InputEvent::Cursor([300.0, 300.0]));
+        // the ground follows the cursor to the left
+        let expected = start.x + 100.0 * camera.world_units_per_pixel();
+        frame(&controller, &mut input, &mut camera);
+        assert!((camera.target().x - expected).abs() < 1e-3);
+
+        input.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::LAlt)));
+        input.handle(InputEvent::Cursor([300.0, 250.0]));
+        frame(&controller, &mut input, &mut camera);
+        assert!(camera.pitch() < std::f32::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn test_scroll_zooms() {
+        let (controller, mut input, mut camera) = setup();
+        let start = camera.distance();
+        input.handle(InputEvent::Scroll(1.0));
+        frame(&controller, &mut input, &mut camera);
+        assert!(camera.distance() < start);
+        let zoomed = camera.distance();
+        frame(&controller, &mut input, &mut camera);
+        assert_eq!(camera.distance(), zoomed);
+    }
+
+    #[test]
+    fn test_orbit_can_be_disabled() {
+        let config = Config::from_text("[camera]\norbit = false").unwrap();
+        let controller = CameraController::new(CameraSettings::from_config(&config));
+        let mut camera = Camera::new(vec2(64.0, 64.0), [800, 600]);
+        let mut input = InputState::default();
+        input.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::Q)));
+        controller.update(&input, &mut camera, Duration::from_secs(1));
+        assert_eq!(camera.yaw(), 0.0);
+    }
+}
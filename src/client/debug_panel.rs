@@ -0,0 +1,384 @@
+//! Debug overlay with frame timing and the present mode, simulation and
+//! network statistics, an inspector for the selected ants and nest, and the pheromone heat map
+//! settings.
+
+use crate::client::input::{Action, InputState};
+use crate::client::messages::{Frame, NetworkStats};
+use crate::client::selection::Selection;
+use crate::renderer::camera::Camera;
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::renderer::{ColorRamp, PheromoneLayer, PresentMode};
+use crate::shared::config::Config;
+use crate::shared::pheromone::Channel;
+use crate::shared::protocols::PlayerId;
+use crate::shared::timestep::TickStats;
+use egui::{ComboBox, Context, Grid, Slider, Ui, Window};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Frames the timing statistics are taken over.
+const FRAME_HISTORY: usize = 120;
+/// Selected ants listed by the inspector, the rest are only counted.
+const INSPECTED_ANTS: usize = 16;
+
+/// What the panel shows about the current frame.
+pub struct DebugInfo<'a> {
+    /// The latest frame from the game thread, if one arrived yet.
+    pub frame: Option<&'a Frame>,
+    pub snapshot: &'a RenderSnapshot,
+    pub selection: &'a Selection,
+    pub camera: &'a Camera,
+}
+
+#[derive(Debug, Clone)]
+pub struct DebugPanel {
+    visible: bool,
+    inspector: bool,
+    pheromones: PheromoneLayer,
+    present_mode: PresentMode,
+    frame_times: VecDeque<Duration>,
+}
+
+impl DebugPanel {
+    pub fn new(visible: bool) -> DebugPanel {
+        DebugPanel {
+            visible,
+            inspector: false,
+            pheromones: PheromoneLayer::default(),
+            present_mode: PresentMode::default(),
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
+    }
+
+    /// Reads `debug.panel`, whether the panel is open at start,
+    /// `debug.pheromones`, whether the heat map is, and
+    /// `render.present_mode`.
+    pub fn from_config(config: &Config) -> DebugPanel {
+        let mut panel = DebugPanel::new(config.get_or("debug.panel", false));
+        panel.pheromones.visible = config.get_or("debug.pheromones", false);
+        panel.present_mode = config.get_or("render.present_mode", PresentMode::default());
+        panel
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn inspector(&self) -> bool {
+        self.inspector
+    }
+
+    pub fn pheromones(&self) -> &PheromoneLayer {
+        &self.pheromones
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// Shows the field of `colony`, the player's when a match starts.
+    pub fn show_pheromones_of(&mut self, colony: PlayerId) {
+        self.pheromones.colony = colony;
+    }
+
+    /// Records the time since the last frame.
+    pub fn record_frame(&mut self, elapsed: Duration) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(elapsed);
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32
+    }
+
+    pub fn max_frame_time(&self) -> Duration {
+        self.frame_times.iter().max().copied().unwrap_or_default()
+    }
+
+    pub fn fps(&self) -> f32 {
+        let average = self.average_frame_time().as_secs_f32();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    /// Toggles the panel, inspector and heat map from this frame's input.
+    pub fn update(&mut self, input: &InputState) {
+        if input.pressed(Action::ToggleDebugPanel) {
+            self.visible = !self.visible;
+        }
+        if input.pressed(Action::ToggleInspector) {
+            self.inspector = !self.inspector;
+        }
+        if input.pressed(Action::TogglePheromones) {
+            self.pheromones.visible = !self.pheromones.visible;
+        }
+    }
+
+    pub fn show(&mut self, context: &Context, info: &DebugInfo) {
+        if self.visible {
+            let mut visible = self.visible;
+            Window::new("Debug")
+                .open(&mut visible)
+                .resizable(false)
+                .default_pos([8.0, 8.0])
+                .show(context, |ui| {
+                    self.timing(ui);
+                    ui.separator();
+                    simulation(ui, info);
+                    ui.separator();
+                    network(ui, info.frame.and_then(|frame| frame.network.as_ref()));
+                    ui.separator();
+                    pheromones(ui, &mut self.pheromones, info.snapshot);
+                    ui.separator();
+                    ui.checkbox(&mut self.inspector, "Inspector");
+                });
+            self.visible = visible;
+        }
+        if self.inspector {
+            let mut inspector = self.inspector;
+            Window::new("Inspector")
+                .open(&mut inspector)
+                .default_pos([8.0, 360.0])
+                .show(context, |ui| inspect(ui, info));
+            self.inspector = inspector;
+        }
+    }
+
+    fn timing(&mut self, ui: &mut Ui) {
+        ui.heading("Frames");
+        Grid::new("frames").show(ui, |ui| {
+            ui.label("FPS");
+            ui.label(format!("{:.0}", self.fps()));
+            ui.end_row();
+            ui.label("frame time");
+            ui.label(format!(
+                "{} avg, {} max",
+                milliseconds(self.average_frame_time()),
+                milliseconds(self.max_frame_time())
+            ));
+            ui.end_row();
+            ui.label("present mode");
+            ComboBox::from_id_source("present mode")
+                .selected_text(self.present_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in PresentMode::ALL {
+                        ui.selectable_value(&mut self.present_mode, mode, mode.name());
+                    }
+                });
+            ui.end_row();
+        });
+    }
+}
+
+fn simulation(ui: &mut Ui, info: &DebugInfo) {
+    ui.heading("Simulation");
+    let stats = info.frame.map(|frame| frame.tick_stats).unwrap_or_default();
+    let TickStats {
+        dropped, catch_ups, ..
+    } = stats;
+    Grid::new("simulation").show(ui, |ui| {
+        ui.label("tick");
+        ui.label(info.snapshot.tick.to_string());
+        ui.end_row();
+        if let Some(frame) = info.frame {
+            ui.label("tick rate");
+            ui.label(format!("{:.0}/s", 1.0 / frame.tick_duration.as_secs_f32()));
+            ui.end_row();
+        }
+        ui.label("tick time");
+        ui.label(format!(
+            "{} avg, {} max",
+            milliseconds(stats.average_tick_time()),
+            milliseconds(stats.max_tick_time)
+        ));
+        ui.end_row();
+        ui.label("catch-ups");
+        ui.label(format!("{}, {} ticks dropped", catch_ups, dropped));
+        ui.end_row();
+    });
+}
+
+fn network(ui: &mut Ui, stats: Option<&NetworkStats>) {
+    ui.heading("Network");
+    let Some(stats) = stats else {
+        ui.label("offline");
+        return;
+    };
+    Grid::new("network").show(ui, |ui| {
+        ui.label("ping");
+        ui.label(stats.ping.map_or("-".to_string(), milliseconds));
+        ui.end_row();
+        ui.label("sent");
+        ui.label(format!(
+            "{} packets, {} bytes",
+            stats.packets_sent, stats.bytes_sent
+        ));
+        ui.end_row();
+        ui.label("received");
+        ui.label(format!(
+            "{} packets, {} bytes",
+            stats.packets_received, stats.bytes_received
+        ));
+        ui.end_row();
+        ui.label("lost pings");
+        ui.label(stats.pings_lost.to_string());
+        ui.end_row();
+    });
+}
+
+fn pheromones(ui: &mut Ui, layer: &mut PheromoneLayer, snapshot: &RenderSnapshot) {
+    ui.heading("Pheromones");
+    ui.checkbox(&mut layer.visible, "heat map");
+    Grid::new("pheromones").show(ui, |ui| {
+        ui.label("colony");
+        ComboBox::from_id_source("pheromone colony")
+            .selected_text(layer.colony.to_string())
+            .show_ui(ui, |ui| {
+                for pheromones in &snapshot.pheromones {
+                    let colony = pheromones.colony;
+                    ui.selectable_value(&mut layer.colony, colony, colony.to_string());
+                }
+            });
+        ui.end_row();
+        for channel in Channel::ALL {
+            let style = &mut layer.channels[channel.index()];
+            ui.checkbox(&mut style.visible, format!("{:?}", channel));
+            ComboBox::from_id_source(channel)
+                .selected_text(style.ramp.name())
+                .show_ui(ui, |ui| {
+                    for ramp in ColorRamp::ALL {
+                        ui.selectable_value(&mut style.ramp, ramp, ramp.name());
+                    }
+                });
+            ui.end_row();
+        }
+        ui.label("saturation");
+        ui.add(Slider::new(&mut layer.saturation, 256..=u16::MAX).logarithmic(true));
+        ui.end_row();
+    });
+}
+
+fn inspect(ui: &mut Ui, info: &DebugInfo) {
+    let snapshot = info.snapshot;
+    Grid::new("world").show(ui, |ui| {
+        ui.label("ants");
+        ui.label(snapshot.ants.len().to_string());
+        ui.end_row();
+        ui.label("nests");
+        ui.label(snapshot.nests.len().to_string());
+        ui.end_row();
+        ui.label("food sources");
+        ui.label(snapshot.food.len().to_string());
+        ui.end_row();
+        let target = info.camera.target();
+        ui.label("camera");
+        ui.label(format!(
+            "({:.1}, {:.1}), distance {:.1}",
+            target.x,
+            target.y,
+            info.camera.distance()
+        ));
+        ui.end_row();
+    });
+    ui.separator();
+
+    if let Some(nest) = snapshot
+        .nests
+        .iter()
+        .find(|nest| Some(nest.entity) == info.selection.nest())
+    {
+        ui.heading("Selected nest");
+        Grid::new("nest").show(ui, |ui| {
+            ui.label("entity");
+            ui.label(format!("{:?}", nest.entity));
+            ui.end_row();
+            ui.label("colony");
+            ui.label(nest.colony.to_string());
+            ui.end_row();
+            ui.label("position");
+            ui.label(format!("({:.1}, {:.1})", nest.position.x, nest.position.y));
+            ui.end_row();
+            ui.label("food");
+            ui.label(nest.food.to_string());
+            ui.end_row();
+        });
+        return;
+    }
+
+    let selected = info.selection.ants();
+    if selected.is_empty() {
+        ui.label("nothing selected");
+        return;
+    }
+    ui.heading(format!("{} selected ants", selected.len()));
+    Grid::new("ants").striped(true).show(ui, |ui| {
+        for header in ["entity", "position", "heading", "carrying"] {
+            ui.strong(header);
+        }
+        ui.end_row();
+        let ants = snapshot
+            .ants
+            .iter()
+            .filter(|ant| ant.selected)
+            .take(INSPECTED_ANTS);
+        for ant in ants {
+            ui.label(format!("{:?}", ant.entity));
+            ui.label(format!("({:.1}, {:.1})", ant.position.x, ant.position.y));
+            ui.label(format!("{:.0}°", ant.heading.to_degrees()));
+            ui.label(if ant.carrying { "yes" } else { "no" });
+            ui.end_row();
+        }
+    });
+    if selected.len() > INSPECTED_ANTS {
+        ui.label(format!("and {} more", selected.len() - INSPECTED_ANTS));
+    }
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::input::{Binding, InputEvent};
+    use winit::event::VirtualKeyCode;
+
+    #[test]
+    fn test_frame_timing() {
+        let mut panel = DebugPanel::new(true);
+        assert_eq!(panel.fps(), 0.0);
+        for _ in 0..FRAME_HISTORY {
+            panel.record_frame(Duration::from_millis(50));
+        }
+        panel.record_frame(Duration::from_millis(10));
+        assert_eq!(panel.frame_times.len(), FRAME_HISTORY);
+        assert_eq!(panel.max_frame_time(), Duration::from_millis(50));
+        assert!(panel.fps() > 20.0 && panel.fps() < 21.0);
+    }
+
+    #[test]
+    fn test_toggles() {
+        let config =
+            Config::from_text("[debug]\npanel = true\n[render]\npresent_mode = mailbox").unwrap();
+        let mut panel = DebugPanel::from_config(&config);
+        assert!(panel.visible());
+        assert_eq!(panel.present_mode(), PresentMode::Mailbox);
+        let mut input = InputState::default();
+        input.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::F3)));
+        input.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::F4)));
+        input.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::P)));
+        panel.update(&input);
+        assert!(!panel.visible());
+        assert!(panel.inspector());
+        assert!(panel.pheromones().visible);
+    }
+}
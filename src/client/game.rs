@@ -0,0 +1,314 @@
+//! Game thread, the only owner of the client's [`GameState`].
+//!
+//! Offline the thread ticks on its own [`FixedTimestep`] and applies the
+//! local player's commands on the next tick. Online it is driven by the
+//! game server instead: commands go out through the network thread and a
+//! tick only runs once the server has broadcast its commands, so every
+//! peer applies the same commands at the same tick. Ticks lost on the way
+//! are asked for again.
+
+use crate::client::messages::{Frame, GameInput, NetworkEvent, NetworkRequest, NetworkStats};
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::shared::game::{Command, GameState};
+use crate::shared::protocols::{PlayerId, TICK_HISTORY};
+use crate::shared::timestep::{FixedTimestep, TimestepSettings};
+use log::{error, warn};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Upper bound for waiting on input, so network ticks are picked up soon.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// How long to wait for lost ticks before asking for them again.
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Channels to the network thread of an online match.
+pub struct NetworkLink {
+    pub requests: Sender<NetworkRequest>,
+    pub events: Receiver<NetworkEvent>,
+    /// Server tick the match starts after, from the lobby.
+    pub start_tick: u64,
+}
+
+struct GameThread {
+    state: GameState,
+    player: PlayerId,
+    timestep: FixedTimestep,
+    network: Option<NetworkLink>,
+    /// Offline: commands for the next tick. Online: unused.
+    queued: Vec<Command>,
+    /// Online: commands the server confirmed, by server tick.
+    confirmed: BTreeMap<u64, Vec<Command>>,
+    /// Online: when lost ticks were last asked for.
+    last_resend: Option<Instant>,
+    /// Online: the latest from the network thread.
+    network_stats: Option<NetworkStats>,
+    current: Arc<RenderSnapshot>,
+    frames: Sender<Frame>,
+}
+
+pub fn spawn_game(
+    state: GameState,
+    player: PlayerId,
+    settings: TimestepSettings,
+    inputs: Receiver<GameInput>,
+    network: Option<NetworkLink>,
+    frames: Sender<Frame>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let current = Arc::new(RenderSnapshot::new(&state));
+        let mut thread = GameThread {
+            state,
+            player,
+            timestep: FixedTimestep::new(settings),
+            network,
+            queued: Vec::new(),
+            confirmed: BTreeMap::new(),
+            last_resend: None,
+            network_stats: None,
+            current,
+            frames,
+        };
+        thread.run(&inputs);
+        if let Some(network) = thread.network {
+            let _ = network.requests.send(NetworkRequest::Shutdown);
+        }
+    })
+}
+
+impl GameThread {
+    fn run(&mut self, inputs: &Receiver<GameInput>) {
+        loop {
+            let now = Instant::now();
+            let wait = self.timestep.time_until_next_tick(now).min(POLL_INTERVAL);
+            match inputs.recv_timeout(wait) {
+                Ok(GameInput::Command(kind)) => self.command(Command {
+                    player: self.player,
+                    kind,
+                }),
+                Ok(GameInput::Shutdown) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if !self.receive_network() {
+                return;
+            }
+
+            let due = self.timestep.advance(Instant::now());
+            if self.network.is_some() {
+                if !self.run_confirmed() {
+                    return;
+                }
+            } else {
+                for _ in 0..due {
+                    let commands = std::mem::take(&mut self.queued);
+                    self.step(&commands);
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        match &self.network {
+            Some(network) => {
+                let _ = network.requests.send(NetworkRequest::Send(command));
+            }
+            None => self.queued.push(command),
+        }
+    }
+
+    /// Collects confirmed ticks, false once the connection is gone.
+    fn receive_network(&mut self) -> bool {
+        let Some(network) = &self.network else {
+            return true;
+        };
+        while let Ok(event) = network.events.try_recv() {
+            match event {
+                // resent ticks may arrive twice
+                NetworkEvent::Tick { tick, commands } => {
+                    if tick > self.server_tick() {
+                        self.confirmed.insert(tick, commands);
+                    }
+                }
+                NetworkEvent::Stats(stats) => self.network_stats = Some(stats),
+                NetworkEvent::Disconnected(reason) => {
+                    error!("Lost connection to the game server: {}", reason);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// The server tick the state is at.
+    fn server_tick(&self) -> u64 {
+        let start_tick = self
+            .network
+            .as_ref()
+            .map_or(0, |network| network.start_tick);
+        start_tick + self.state.tick()
+    }
+
+    /// Runs every tick the server confirmed, in order, stopping at the first
+    /// gap. The server paces the simulation, so there is nothing to cap.
+    /// Ticks missing in front of later ones were lost and are asked for
+    /// again, false once the server no longer has them.
+    fn run_confirmed(&mut self) -> bool {
+        while let Some(commands) = self.confirmed.remove(&(self.server_tick() + 1)) {
+            self.step(&commands);
+        }
+        let (Some(&next), Some(&newest)) = (
+            self.confirmed.keys().next(),
+            self.confirmed.keys().next_back(),
+        ) else {
+            return true;
+        };
+        let missing = self.server_tick() + 1;
+        if newest - missing >= TICK_HISTORY {
+            error!("Tick {} was lost for good", missing);
+            return false;
+        }
+        if self
+            .last_resend
+            .is_none_or(|last| last.elapsed() >= RESEND_INTERVAL)
+        {
+            warn!("Asking for lost ticks {} to {}", missing, next - 1);
+            if let Some(network) = &self.network {
+                let _ = network.requests.send(NetworkRequest::Resend {
+                    first: missing,
+                    last: next - 1,
+                });
+            }
+            self.last_resend = Some(Instant::now());
+        }
+        true
+    }
+
+    fn step(&mut self, commands: &[Command]) {
+        let started = Instant::now();
+        self.state.step(commands);
+        self.timestep.record_tick(started.elapsed());
+
+        let current = Arc::new(RenderSnapshot::new(&self.state));
+        let previous = std::mem::replace(&mut self.current, current.clone());
+        // the render thread may already be gone during shutdown
+        let _ = self.frames.send(Frame {
+            previous,
+            current,
+            produced: Instant::now(),
+            tick_duration: self.timestep.tick_duration(),
+            tick_stats: *self.timestep.stats(),
+            network: self.network_stats,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::components::Priority;
+    use crate::shared::game::CommandKind;
+    use std::sync::mpsc::channel;
+
+    fn settings() -> TimestepSettings {
+        TimestepSettings {
+            tick_rate: 100,
+            max_catch_up: 5,
+        }
+    }
+
+    #[test]
+    fn test_offline_ticks_on_its_own() {
+        let (inputs, input_rx) = channel();
+        let (frame_tx, frames) = channel();
+        let handle = spawn_game(
+            GameState::new_match(1, &[0]),
+            0,
+            settings(),
+            input_rx,
+            None,
+            frame_tx,
+        );
+        inputs
+            .send(GameInput::Command(CommandKind::SetPriority(Priority::Dig)))
+            .unwrap();
+        let first = frames.recv_timeout(Duration::from_secs(1)).unwrap();
+        let second = frames.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(Arc::ptr_eq(&first.current, &second.previous));
+        assert_eq!(second.current.tick, first.current.tick + 1);
+        inputs.send(GameInput::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_online_waits_for_confirmed_ticks() {
+        let (inputs, input_rx) = channel();
+        let (frame_tx, frames) = channel();
+        let (request_tx, requests) = channel();
+        let (events, event_rx) = channel();
+        let handle = spawn_game(
+            GameState::new_match(1, &[0]),
+            0,
+            settings(),
+            input_rx,
+            Some(NetworkLink {
+                requests: request_tx,
+                events: event_rx,
+                start_tick: 40,
+            }),
+            frame_tx,
+        );
+
+        let kind = CommandKind::SetPriority(Priority::Explore);
+        inputs.send(GameInput::Command(kind.clone())).unwrap();
+        let command = Command { player: 0, kind };
+        assert_eq!(
+            requests.recv_timeout(Duration::from_secs(1)).unwrap(),
+            NetworkRequest::Send(command.clone())
+        );
+        assert!(frames.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // the server was at tick 40 when the match started, and 44 arrives
+        // before 43
+        let receive_ticks = |ticks: [u64; 2]| {
+            for tick in ticks {
+                let commands = if tick == 43 {
+                    vec![command.clone()]
+                } else {
+                    Vec::new()
+                };
+                events.send(NetworkEvent::Tick { tick, commands }).unwrap();
+            }
+            (0..2)
+                .map(|_| frames.recv_timeout(Duration::from_secs(1)).unwrap())
+                .map(|frame| frame.current.tick)
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(receive_ticks([41, 42]), vec![1, 2]);
+        assert_eq!(receive_ticks([44, 43]), vec![3, 4]);
+
+        // 45 was lost, it is asked for until it arrives
+        events
+            .send(NetworkEvent::Tick {
+                tick: 46,
+                commands: Vec::new(),
+            })
+            .unwrap();
+        let resend = NetworkRequest::Resend {
+            first: 45,
+            last: 45,
+        };
+        let asked = std::iter::from_fn(|| requests.recv_timeout(Duration::from_secs(1)).ok())
+            .find(|request| *request == resend);
+        assert!(asked.is_some(), "lost tick not asked for");
+        assert!(frames.try_recv().is_err());
+        assert_eq!(receive_ticks([45, 46]), vec![5, 6]);
+
+        events
+            .send(NetworkEvent::Disconnected("test".into()))
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(requests.try_iter().last(), Some(NetworkRequest::Shutdown));
+    }
+}
@@ -0,0 +1,506 @@
+//! Maps raw keyboard and mouse input to named actions.
+//!
+//! Bindings come from the `bindings` section of the config file, one action
+//! per key with a comma separated list of keys and mouse buttons:
+//!
+//! ```text
+//! [bindings]
+//! select = MouseLeft
+//! camera_pan_up = W, Up
+//! ```
+//!
+//! Keys use the names of winit's `VirtualKeyCode`. Actions that are not
+//! listed keep their default bindings.
+
+use crate::renderer::camera::Camera;
+use crate::shared::config::Config;
+use log::warn;
+use nalgebra_glm::Vec2;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+/// Touchpads scroll in pixels, wheels in lines.
+const PIXELS_PER_LINE: f32 = 40.0;
+
+macro_rules! actions {
+    ($($(#[$meta:meta])* $action:ident => $name:literal: [$($binding:literal),*],)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($(#[$meta])* $action,)*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$action,)*];
+
+            /// Name used in the config file.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Action::$action => $name,)*
+                }
+            }
+
+            fn default_bindings(self) -> &'static [&'static str] {
+                match self {
+                    $(Action::$action => &[$($binding),*],)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Select => "select": ["MouseLeft"],
+    CommandMove => "command_move": ["MouseRight"],
+    CameraPanUp => "camera_pan_up": ["W", "Up"],
+    CameraPanDown => "camera_pan_down": ["S", "Down"],
+    CameraPanLeft => "camera_pan_left": ["A", "Left"],
+    CameraPanRight => "camera_pan_right": ["D", "Right"],
+    CameraRotateLeft => "camera_rotate_left": ["Q"],
+    CameraRotateRight => "camera_rotate_right": ["E"],
+    CameraZoomIn => "camera_zoom_in": ["PageUp"],
+    CameraZoomOut => "camera_zoom_out": ["PageDown"],
+    CameraDrag => "camera_drag": ["MouseMiddle"],
+    /// Held while dragging to orbit instead of pan.
+    CameraOrbit => "camera_orbit": ["LAlt", "RAlt"],
+    ToggleDebugPanel => "toggle_debug_panel": ["F3"],
+    ToggleInspector => "toggle_inspector": ["F4"],
+    TogglePheromones => "toggle_pheromones": ["P"],
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Action, ()> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, VirtualKeyCode)] = &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+key_names! {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Up, Down, Left, Right, PageUp, PageDown, Home, End, Insert, Delete,
+    Escape, Tab, Space, Return, Back,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Minus, Equals, Comma, Period, Slash, Grave,
+}
+
+const MOUSE_NAMES: &[(&str, MouseButton)] = &[
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl FromStr for Binding {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Binding, ()> {
+        let key = KEY_NAMES.iter().find(|(n, _)| *n == name);
+        let mouse = MOUSE_NAMES.iter().find(|(n, _)| *n == name);
+        match (key, mouse) {
+            (Some(&(_, key)), _) => Ok(Binding::Key(key)),
+            (_, Some(&(_, button))) => Ok(Binding::Mouse(button)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Binding::Key(key) => KEY_NAMES.iter().find(|(_, k)| k == key).map(|(n, _)| *n),
+            Binding::Mouse(button) => MOUSE_NAMES
+                .iter()
+                .find(|(_, b)| b == button)
+                .map(|(n, _)| *n),
+        };
+        match name {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bindings {
+    actions: HashMap<Binding, Vec<Action>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let mut bindings = Bindings {
+            actions: HashMap::new(),
+        };
+        for &action in Action::ALL {
+            for name in action.default_bindings() {
+                bindings.bind(action, name.parse().unwrap());
+            }
+        }
+        bindings
+    }
+}
+
+impl Bindings {
+    /// The defaults with the `bindings` section of `config` applied. Unknown
+    /// actions and keys are warned about and ignored.
+    pub fn from_config(config: &Config) -> Bindings {
+        let mut bindings = Bindings::default();
+        for (name, value) in config.section("bindings") {
+            let Ok(action) = name.parse::<Action>() else {
+                warn!("Ignoring binding for unknown action {:?}", name);
+                continue;
+            };
+            let parsed: Result<Vec<Binding>, ()> = value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::parse)
+                .collect();
+            match parsed {
+                Ok(parsed) => {
+                    bindings.unbind(action);
+                    for binding in parsed {
+                        bindings.bind(action, binding);
+                    }
+                }
+                Err(()) => warn!(
+                    "Invalid bindings {:?} for {}, keeping the defaults",
+                    value, action
+                ),
+            }
+        }
+        bindings
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let actions = self.actions.entry(binding).or_default();
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+
+    /// Removes every binding of `action`.
+    pub fn unbind(&mut self, action: Action) {
+        for actions in self.actions.values_mut() {
+            actions.retain(|&a| a != action);
+        }
+        self.actions.retain(|_, actions| !actions.is_empty());
+    }
+
+    pub fn actions(&self, binding: Binding) -> &[Action] {
+        self.actions.get(&binding).map_or(&[], |actions| actions)
+    }
+
+    /// Every binding of `action`, sorted by name.
+    pub fn bindings(&self, action: Action) -> Vec<Binding> {
+        let mut bindings: Vec<Binding> = self
+            .actions
+            .iter()
+            .filter(|(_, actions)| actions.contains(&action))
+            .map(|(&binding, _)| binding)
+            .collect();
+        bindings.sort_by_key(|binding| binding.to_string());
+        bindings
+    }
+}
+
+/// Window input in a form that can be sent to other threads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Pressed(Binding),
+    Released(Binding),
+    /// Cursor position in pixels from the top left of the window.
+    Cursor([f32; 2]),
+    CursorLeft,
+    /// Scroll wheel lines, positive away from the user.
+    Scroll(f32),
+    /// Focus was lost, so releases will not arrive.
+    FocusLost,
+    /// A typed character, for text fields.
+    Text(char),
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<InputEvent> {
+        let binding = |binding, state: &ElementState| match state {
+            ElementState::Pressed => InputEvent::Pressed(binding),
+            ElementState::Released => InputEvent::Released(binding),
+        };
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => Some(binding(Binding::Key(*key), state)),
+            WindowEvent::MouseInput { state, button, .. } => {
+                Some(binding(Binding::Mouse(*button), state))
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                Some(InputEvent::Cursor([position.x as f32, position.y as f32]))
+            }
+            WindowEvent::CursorLeft { .. } => Some(InputEvent::CursorLeft),
+            WindowEvent::MouseWheel { delta, .. } => Some(InputEvent::Scroll(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => *lines,
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+            })),
+            WindowEvent::Focused(false) => Some(InputEvent::FocusLost),
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => Some(InputEvent::Text(*c)),
+            _ => None,
+        }
+    }
+}
+
+/// Action state for the current frame. Feed it events with `handle`, query
+/// it, then call `end_frame`.
+#[derive(Debug, Clone)]
+pub struct InputState {
+    bindings: Bindings,
+    held: HashSet<Binding>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+    cursor: Option<Vec2>,
+    cursor_delta: Vec2,
+    scroll: f32,
+}
+
+impl InputState {
+    pub fn new(bindings: Bindings) -> InputState {
+        InputState {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            cursor: None,
+            cursor_delta: Vec2::zeros(),
+            scroll: 0.0,
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn handle(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(binding) => {
+                // key repeat sends presses without releases
+                if self.held.insert(binding) {
+                    self.pressed.extend(self.bindings.actions(binding));
+                }
+            }
+            InputEvent::Released(binding) => self.release(binding),
+            InputEvent::Cursor(position) => {
+                let position = Vec2::from(position);
+                if let Some(previous) = self.cursor {
+                    self.cursor_delta += position - previous;
+                }
+                self.cursor = Some(position);
+            }
+            InputEvent::CursorLeft => self.cursor = None,
+            InputEvent::Scroll(lines) => self.scroll += lines,
+            InputEvent::FocusLost => {
+                for binding in self.held.clone() {
+                    self.release(binding);
+                }
+            }
+            InputEvent::Text(_) => {}
+        }
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.released.extend(self.bindings.actions(binding));
+        }
+    }
+
+    /// Clears what only lasts a frame: presses, releases, cursor movement
+    /// and scrolling.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.cursor_delta = Vec2::zeros();
+        self.scroll = 0.0;
+    }
+
+    /// Whether any binding of `action` is down.
+    pub fn held(&self, action: Action) -> bool {
+        self.held
+            .iter()
+            .any(|&binding| self.bindings.actions(binding).contains(&action))
+    }
+
+    /// Whether `action` went down this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Whether `action` went up this frame.
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+
+    /// 1 for `positive`, -1 for `negative`, 0 for neither or both.
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+    }
+
+    /// `None` while the cursor is outside the window.
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// Cursor movement this frame, in pixels.
+    pub fn cursor_delta(&self) -> Vec2 {
+        self.cursor_delta
+    }
+
+    /// Scroll wheel lines this frame.
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    /// The point on the ground under the cursor.
+    pub fn cursor_world(&self, camera: &Camera) -> Option<Vec2> {
+        camera.screen_to_world(self.cursor?)
+    }
+}
+
+impl Default for InputState {
+    fn default() -> InputState {
+        InputState::new(Bindings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::vec2;
+
+    fn key(key: VirtualKeyCode) -> Binding {
+        Binding::Key(key)
+    }
+
+    #[test]
+    fn test_pressed_held_released() {
+        let mut input = InputState::default();
+        input.handle(InputEvent::Pressed(key(VirtualKeyCode::W)));
+        input.handle(InputEvent::Pressed(key(VirtualKeyCode::W)));
+        assert!(input.pressed(Action::CameraPanUp));
+        assert!(input.held(Action::CameraPanUp));
+        assert_eq!(input.axis(Action::CameraPanUp, Action::CameraPanDown), -1.0);
+
+        input.end_frame();
+        assert!(!input.pressed(Action::CameraPanUp));
+        assert!(input.held(Action::CameraPanUp));
+
+        // still held through the second binding
+        input.handle(InputEvent::Pressed(key(VirtualKeyCode::Up)));
+        input.handle(InputEvent::Released(key(VirtualKeyCode::W)));
+        assert!(input.held(Action::CameraPanUp));
+        input.handle(InputEvent::FocusLost);
+        assert!(!input.held(Action::CameraPanUp));
+        assert!(input.released(Action::CameraPanUp));
+    }
+
+    #[test]
+    fn test_cursor_and_scroll() {
+        let mut input = InputState::default();
+        input.handle(InputEvent::Cursor([10.0, 10.0]));
+        input.handle(InputEvent::Cursor([15.0, 8.0]));
+        input.handle(InputEvent::Scroll(1.0));
+        input.handle(InputEvent::Scroll(0.5));
+        assert_eq!(input.cursor(), Some(vec2(15.0, 8.0)));
+        assert_eq!(input.cursor_delta(), vec2(5.0, -2.0));
+        assert_eq!(input.scroll(), 1.5);
+        input.end_frame();
+        assert_eq!(input.cursor_delta(), Vec2::zeros());
+        input.handle(InputEvent::CursorLeft);
+        assert_eq!(input.cursor(), None);
+    }
+
+    #[test]
+    fn test_bindings_from_config() {
+        let config = Config::from_text(
+            "[bindings]\n\
+             select = Space, MouseLeft\n\
+             camera_pan_up = I\n\
+             camera_pan_down = NotAKey\n\
+             jump = J",
+        )
+        .unwrap();
+        let bindings = Bindings::from_config(&config);
+        assert_eq!(
+            bindings.bindings(Action::Select),
+            vec![
+                Binding::Mouse(MouseButton::Left),
+                key(VirtualKeyCode::Space)
+            ]
+        );
+        assert_eq!(bindings.actions(key(VirtualKeyCode::W)), &[]);
+        assert_eq!(
+            bindings.actions(key(VirtualKeyCode::I)),
+            &[Action::CameraPanUp]
+        );
+        // invalid lines keep the defaults
+        assert_eq!(
+            bindings.bindings(Action::CameraPanDown),
+            vec![key(VirtualKeyCode::Down), key(VirtualKeyCode::S)]
+        );
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for &action in Action::ALL {
+            assert_eq!(action.name().parse::<Action>(), Ok(action));
+            for binding in Bindings::default().bindings(action) {
+                assert_eq!(binding.to_string().parse::<Binding>(), Ok(binding));
+            }
+        }
+        assert_eq!("Key1".parse::<Binding>(), Ok(key(VirtualKeyCode::Key1)));
+        assert_eq!(
+            "MouseMiddle".parse::<Binding>(),
+            Ok(Binding::Mouse(MouseButton::Middle))
+        );
+        assert!("Mouse7".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn test_cursor_world() {
+        let camera = Camera::new(vec2(64.0, 64.0), [800, 600]);
+        let mut input = InputState::default();
+        assert_eq!(input.cursor_world(&camera), None);
+        input.handle(InputEvent::Cursor([400.0, 300.0]));
+        let center = input.cursor_world(&camera).unwrap();
+        assert!((center - vec2(32.0, 32.0)).norm() < 1e-2);
+    }
+}
@@ -0,0 +1,79 @@
+//! Text the client draws during a match: colony names over the nests and a
+//! counter of the player's colony.
+
+use crate::renderer::ants::colony_color;
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::renderer::{Label, LabelSpace};
+use crate::shared::protocols::PlayerId;
+
+/// Nest labels float this far north of the nest, in world units.
+const NEST_LABEL_OFFSET: f32 = 3.0;
+const NEST_LABEL_SIZE: f32 = 1.5;
+/// In pixels.
+const HUD_SIZE: f32 = 18.0;
+const HUD_MARGIN: f32 = 12.0;
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// The labels of `snapshot` for `player`, in a window of `extent` pixels.
+pub fn labels(snapshot: &RenderSnapshot, player: PlayerId, extent: [u32; 2]) -> Vec<Label> {
+    let mut labels: Vec<Label> = snapshot
+        .nests
+        .iter()
+        .map(|nest| Label {
+            text: format!("Colony {}", nest.colony),
+            position: [nest.position.x, nest.position.y - NEST_LABEL_OFFSET],
+            size: NEST_LABEL_SIZE,
+            color: colony_color(nest.colony),
+            space: LabelSpace::World,
+        })
+        .collect();
+
+    let ants = snapshot
+        .ants
+        .iter()
+        .filter(|ant| ant.colony == player)
+        .count();
+    let food: u32 = snapshot
+        .nests
+        .iter()
+        .filter(|nest| nest.colony == player)
+        .map(|nest| nest.food)
+        .sum();
+    labels.push(Label {
+        text: format!("Ants {}   Food {}", ants, food),
+        position: [HUD_MARGIN, extent[1] as f32 - HUD_MARGIN - HUD_SIZE],
+        size: HUD_SIZE,
+        color: HUD_COLOR,
+        space: LabelSpace::Screen,
+    });
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::fixed::FixedVec2;
+    use crate::shared::game::GameState;
+
+    #[test]
+    fn test_labels() {
+        let mut state = GameState::new(1);
+        let nest = state.add_colony(0, FixedVec2::from_int(10, 10));
+        state.add_colony(1, FixedVec2::from_int(30, 30));
+        for _ in 0..3 {
+            state.spawn_ant(nest);
+        }
+        let snapshot = RenderSnapshot::new(&state);
+        let labels = labels(&snapshot, 0, [800, 600]);
+        assert_eq!(labels.len(), snapshot.nests.len() + 1);
+        assert!(labels[..2]
+            .iter()
+            .all(|label| label.space == LabelSpace::World));
+        let ants = snapshot.ants.iter().filter(|ant| ant.colony == 0).count();
+        assert!(ants >= 3);
+        let hud = labels.last().unwrap();
+        assert!(hud.text.starts_with(&format!("Ants {} ", ants)));
+        assert_eq!(hud.space, LabelSpace::Screen);
+        assert!(hud.position[1] < 600.0);
+    }
+}
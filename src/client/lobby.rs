@@ -0,0 +1,351 @@
+//! Lobby thread, talks to the distributor and to the lobby the player joined
+//! until a match starts.
+//!
+//! Every distributor request uses its own short tcp connection. A joined
+//! lobby keeps one connection, read by a second thread that answers the
+//! lobby's map check and forwards everything else as [`LobbyEvent`]s.
+
+use crate::client::messages::{LobbyEvent, LobbyRequest, MatchServer, MatchSettings};
+use crate::shared::map::Map;
+use crate::shared::protocols::{
+    DistributorClientMessages, DistributorServerMessages, LobbyClientMessages, LobbyServerMessages,
+};
+use log::{info, warn};
+use std::io::Write;
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How long connecting to and waiting on the distributor may take.
+const DISTRIBUTOR_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Starts the thread, which ends on [`LobbyRequest::Shutdown`] or when the
+/// render thread hangs up. Maps are looked up in `map_directory`.
+pub fn spawn_lobby(
+    map_directory: impl Into<PathBuf>,
+    requests: Receiver<LobbyRequest>,
+    events: Sender<LobbyEvent>,
+) -> JoinHandle<()> {
+    let map_directory = map_directory.into();
+    std::thread::spawn(move || {
+        let mut room: Option<Room> = None;
+        for request in requests.iter() {
+            match request {
+                LobbyRequest::ListLobbies(distributor) => {
+                    let event = match ask_distributor(
+                        distributor,
+                        &DistributorClientMessages::AskForLobbies,
+                    ) {
+                        Ok(DistributorServerMessages::Lobbies(lobbies)) => LobbyEvent::Lobbies(
+                            lobbies
+                                .into_iter()
+                                .map(|lobby| reachable(lobby, distributor.ip()))
+                                .collect(),
+                        ),
+                        Ok(other) => LobbyEvent::Error(format!("unexpected answer {:?}", other)),
+                        Err(e) => LobbyEvent::Error(format!("{}: {}", distributor, e)),
+                    };
+                    let _ = events.send(event);
+                }
+                LobbyRequest::OpenLobby { distributor, map } => {
+                    let event = match ask_distributor(
+                        distributor,
+                        &DistributorClientMessages::OpenLobby { map },
+                    ) {
+                        Ok(DistributorServerMessages::LobbyOpened(lobby)) => {
+                            LobbyEvent::LobbyOpened(reachable(lobby, distributor.ip()))
+                        }
+                        Ok(DistributorServerMessages::LobbyRefused(reason)) => {
+                            LobbyEvent::Error(reason)
+                        }
+                        Ok(other) => LobbyEvent::Error(format!("unexpected answer {:?}", other)),
+                        Err(e) => LobbyEvent::Error(format!("{}: {}", distributor, e)),
+                    };
+                    let _ = events.send(event);
+                }
+                LobbyRequest::Join { lobby, name } => {
+                    if let Some(room) = room.take() {
+                        room.leave();
+                    }
+                    match Room::join(lobby, name, &map_directory, events.clone()) {
+                        Ok(joined) => room = Some(joined),
+                        Err(e) => {
+                            let _ = events.send(LobbyEvent::Error(format!("{}: {}", lobby, e)));
+                        }
+                    }
+                }
+                LobbyRequest::Ready(ready) => {
+                    send_to_room(&mut room, &LobbyClientMessages::Ready(ready), &events)
+                }
+                LobbyRequest::StartMatch => {
+                    send_to_room(&mut room, &LobbyClientMessages::StartMatch, &events)
+                }
+                LobbyRequest::Leave => {
+                    if let Some(room) = room.take() {
+                        room.leave();
+                    }
+                }
+                LobbyRequest::Shutdown => break,
+            }
+        }
+        if let Some(room) = room {
+            room.leave();
+        }
+    })
+}
+
+/// One request and its answer on a fresh connection to the distributor.
+fn ask_distributor(
+    distributor: SocketAddr,
+    message: &DistributorClientMessages,
+) -> Result<DistributorServerMessages, Box<dyn std::error::Error>> {
+    let mut stream = TcpStream::connect_timeout(&distributor, DISTRIBUTOR_TIMEOUT)?;
+    stream.set_read_timeout(Some(DISTRIBUTOR_TIMEOUT))?;
+    stream.write_all(&bincode::serialize(message)?)?;
+    Ok(bincode::deserialize_from(&stream)?)
+}
+
+/// Servers bind to unspecified addresses, those are reached on the host the
+/// address came from.
+fn reachable(address: SocketAddr, host: IpAddr) -> SocketAddr {
+    if address.ip().is_unspecified() {
+        SocketAddr::new(host, address.port())
+    } else {
+        address
+    }
+}
+
+fn send_to_room(
+    room: &mut Option<Room>,
+    message: &LobbyClientMessages,
+    events: &Sender<LobbyEvent>,
+) {
+    let Some(joined) = room else {
+        return;
+    };
+    if let Err(e) = joined.send(message) {
+        let _ = events.send(LobbyEvent::Error(format!("lost the lobby: {}", e)));
+        if let Some(room) = room.take() {
+            room.leave();
+        }
+    }
+}
+
+/// The connection to a joined lobby.
+struct Room {
+    stream: TcpStream,
+    /// Set when the player leaves, so the reader does not report it.
+    left: Arc<AtomicBool>,
+    reader: JoinHandle<()>,
+}
+
+impl Room {
+    fn join(
+        lobby: SocketAddr,
+        name: String,
+        map_directory: &Path,
+        events: Sender<LobbyEvent>,
+    ) -> std::io::Result<Room> {
+        let mut stream = TcpStream::connect_timeout(&lobby, DISTRIBUTOR_TIMEOUT)?;
+        stream.write_all(&bincode::serialize(&LobbyClientMessages::Join { name }).unwrap())?;
+        info!("Joining lobby {}", lobby);
+        let left = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stream = stream.try_clone()?;
+            let left = left.clone();
+            let map_directory = map_directory.to_path_buf();
+            std::thread::spawn(move || read_lobby(stream, lobby, &map_directory, &left, &events))
+        };
+        Ok(Room {
+            stream,
+            left,
+            reader,
+        })
+    }
+
+    fn send(&mut self, message: &LobbyClientMessages) -> std::io::Result<()> {
+        self.stream.write_all(&bincode::serialize(message).unwrap())
+    }
+
+    fn leave(self) {
+        self.left.store(true, Ordering::Relaxed);
+        let _ = self.stream.shutdown(Shutdown::Both);
+        let _ = self.reader.join();
+    }
+}
+
+/// Forwards the lobby's messages until the match starts or the connection
+/// ends.
+fn read_lobby(
+    mut stream: TcpStream,
+    lobby: SocketAddr,
+    map_directory: &Path,
+    left: &AtomicBool,
+    events: &Sender<LobbyEvent>,
+) {
+    let mut player = None;
+    let mut map: Option<Map> = None;
+    let reason = loop {
+        let message = match bincode::deserialize_from(&stream) {
+            Ok(message) => message,
+            Err(e) => break format!("lost the lobby: {}", e),
+        };
+        let event = match message {
+            LobbyServerMessages::Joined(id) => {
+                player = Some(id);
+                LobbyEvent::Joined(id)
+            }
+            LobbyServerMessages::Refused(reason) => break reason,
+            LobbyServerMessages::Map { name, hash } => {
+                map = match Map::load_named(map_directory, &name) {
+                    Ok(local) if local.hash() == hash => Some(local),
+                    Ok(_) => {
+                        warn!("The local copy of map {} differs from the lobby's", name);
+                        None
+                    }
+                    Err(e) => {
+                        warn!("The lobby's map {} is not available: {}", name, e);
+                        None
+                    }
+                };
+                if map.is_some() {
+                    let has_map = LobbyClientMessages::HasMap { hash };
+                    if let Err(e) = stream.write_all(&bincode::serialize(&has_map).unwrap()) {
+                        break format!("lost the lobby: {}", e);
+                    }
+                }
+                LobbyEvent::Map {
+                    name,
+                    available: map.is_some(),
+                }
+            }
+            LobbyServerMessages::Roster(roster) => LobbyEvent::Roster(roster),
+            LobbyServerMessages::MatchStarting {
+                seed,
+                map_hash,
+                roster,
+                game_port,
+                start_tick,
+                token,
+            } => {
+                let (Some(player), Some(map)) = (player, map.take()) else {
+                    break "the match started without this player".to_string();
+                };
+                if map.hash() != map_hash {
+                    break "the match started on a different map".to_string();
+                }
+                let settings = MatchSettings {
+                    player,
+                    seed,
+                    roster: roster.iter().map(|player| player.id).collect(),
+                    map,
+                    server: Some(MatchServer {
+                        address: SocketAddr::new(lobby.ip(), game_port),
+                        token,
+                        start_tick,
+                    }),
+                };
+                let _ = events.send(LobbyEvent::MatchStarting(Box::new(settings)));
+                return;
+            }
+        };
+        let _ = events.send(event);
+    };
+    if !left.load(Ordering::Relaxed) {
+        let _ = events.send(LobbyEvent::Left(reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::messages::LobbyEvent;
+    use crate::server::Distributer;
+    use crate::shared::map::MAP_DIRECTORY;
+    use crate::shared::protocols::PlayerInfo;
+    use crate::utils::ADDRESSES;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_reachable() {
+        let host: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            reachable("0.0.0.0:3001".parse().unwrap(), host),
+            "10.0.0.2:3001".parse().unwrap()
+        );
+        assert_eq!(
+            reachable("10.0.0.3:3001".parse().unwrap(), host),
+            "10.0.0.3:3001".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lobby_thread() {
+        let addresses = ADDRESSES[7..10].iter().map(|v| v.parse().unwrap());
+        let distributer = Distributer::new(addresses.collect());
+        std::thread::spawn(move || distributer.run());
+        std::thread::sleep(Duration::from_secs(1));
+
+        let (request_sender, requests) = channel();
+        let (event_sender, events) = channel();
+        let handle = spawn_lobby(MAP_DIRECTORY, requests, event_sender);
+        let receive = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+        let distributor: SocketAddr = ADDRESSES[7].parse().unwrap();
+
+        request_sender
+            .send(LobbyRequest::ListLobbies(distributor))
+            .unwrap();
+        assert_eq!(receive(), LobbyEvent::Lobbies(vec![]));
+        request_sender
+            .send(LobbyRequest::OpenLobby {
+                distributor,
+                map: "meadow".into(),
+            })
+            .unwrap();
+        let lobby: SocketAddr = ADDRESSES[9].parse().unwrap();
+        assert_eq!(receive(), LobbyEvent::LobbyOpened(lobby));
+        std::thread::sleep(Duration::from_millis(200));
+
+        request_sender
+            .send(LobbyRequest::Join {
+                lobby,
+                name: "host".into(),
+            })
+            .unwrap();
+        assert_eq!(receive(), LobbyEvent::Joined(0));
+        assert_eq!(
+            receive(),
+            LobbyEvent::Map {
+                name: "meadow".into(),
+                available: true
+            }
+        );
+        let mut host = PlayerInfo {
+            id: 0,
+            name: "host".into(),
+            ready: false,
+        };
+        assert_eq!(receive(), LobbyEvent::Roster(vec![host.clone()]));
+        request_sender.send(LobbyRequest::Ready(true)).unwrap();
+        host.ready = true;
+        assert_eq!(receive(), LobbyEvent::Roster(vec![host]));
+
+        request_sender.send(LobbyRequest::StartMatch).unwrap();
+        let LobbyEvent::MatchStarting(settings) = receive() else {
+            panic!("expected the match to start");
+        };
+        assert_eq!(settings.player, 0);
+        assert_eq!(settings.roster, vec![0]);
+        assert_eq!(settings.map.name(), "Meadow");
+        let server = settings.server.unwrap();
+        assert_eq!(server.address, ADDRESSES[8].parse().unwrap());
+        assert_eq!(server.start_tick, 0);
+
+        request_sender.send(LobbyRequest::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+}
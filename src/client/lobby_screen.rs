@@ -0,0 +1,416 @@
+//! Lobby browser and lobby room screens, shown until a match starts.
+//!
+//! The browser lists the lobbies of a distributor and opens new ones, the
+//! room shows who joined and who is ready and lets the host start the match.
+//! All networking happens on the [lobby thread](crate::client::lobby).
+
+use crate::client::messages::{LobbyEvent, LobbyRequest, MatchSettings};
+use crate::shared::config::Config;
+use crate::shared::map::{Map, MAP_DIRECTORY};
+use crate::shared::protocols::{PlayerId, PlayerInfo};
+use egui::{Align2, Color32, Context, Grid, Ui, Window};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::Sender;
+
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 90, 80);
+
+/// The lobby the player joined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Room {
+    lobby: SocketAddr,
+    /// Known once the lobby accepted the player.
+    player: Option<PlayerId>,
+    /// Name of the lobby's map and whether the local copy matches.
+    map: Option<(String, bool)>,
+    roster: Vec<PlayerInfo>,
+}
+
+impl Room {
+    fn me(&self) -> Option<&PlayerInfo> {
+        self.roster.iter().find(|info| Some(info.id) == self.player)
+    }
+
+    fn is_host(&self) -> bool {
+        self.player.is_some() && self.roster.first().map(|info| info.id) == self.player
+    }
+
+    fn everyone_ready(&self) -> bool {
+        self.roster.iter().all(|info| info.ready)
+    }
+}
+
+pub struct LobbyScreen {
+    requests: Sender<LobbyRequest>,
+    distributor: String,
+    name: String,
+    map: String,
+    /// Seed of offline matches.
+    seed: u64,
+    lobbies: Vec<SocketAddr>,
+    room: Option<Room>,
+    /// The last error, shown until the next one or a successful answer.
+    status: Option<String>,
+}
+
+impl LobbyScreen {
+    pub fn new(requests: Sender<LobbyRequest>) -> LobbyScreen {
+        LobbyScreen {
+            requests,
+            distributor: "127.0.0.1:3000".into(),
+            name: "player".into(),
+            map: "meadow".into(),
+            seed: 0,
+            lobbies: Vec::new(),
+            room: None,
+            status: None,
+        }
+    }
+
+    /// Reads `client.distributor`, `client.name`, `client.map` and
+    /// `client.seed`, the defaults of the browser's fields.
+    pub fn from_config(config: &Config, requests: Sender<LobbyRequest>) -> LobbyScreen {
+        let mut screen = LobbyScreen::new(requests);
+        screen.distributor = config.get_or("client.distributor", screen.distributor);
+        screen.name = config.get_or("client.name", screen.name);
+        screen.map = config.get_or("client.map", screen.map);
+        screen.seed = config.get_or("client.seed", screen.seed);
+        screen
+    }
+
+    pub fn in_room(&self) -> bool {
+        self.room.is_some()
+    }
+
+    /// Shows why the last match ended or couldn't start.
+    pub fn match_ended(&mut self, reason: impl Into<String>) {
+        self.status = Some(reason.into());
+    }
+
+    /// Applies an event from the lobby thread, returns the match once it
+    /// starts.
+    pub fn handle(&mut self, event: LobbyEvent) -> Option<MatchSettings> {
+        match event {
+            LobbyEvent::Lobbies(lobbies) => {
+                self.lobbies = lobbies;
+                self.status = None;
+            }
+            LobbyEvent::LobbyOpened(lobby) => {
+                if !self.lobbies.contains(&lobby) {
+                    self.lobbies.push(lobby);
+                }
+                self.status = None;
+                self.join(lobby);
+            }
+            LobbyEvent::Joined(player) => {
+                if let Some(room) = &mut self.room {
+                    room.player = Some(player);
+                }
+            }
+            LobbyEvent::Map { name, available } => {
+                if !available {
+                    self.status = Some(format!("map {} is missing or differs", name));
+                }
+                if let Some(room) = &mut self.room {
+                    room.map = Some((name, available));
+                }
+            }
+            LobbyEvent::Roster(roster) => {
+                if let Some(room) = &mut self.room {
+                    room.roster = roster;
+                }
+            }
+            LobbyEvent::MatchStarting(settings) => {
+                self.room = None;
+                self.status = None;
+                let _ = self.requests.send(LobbyRequest::Leave);
+                return Some(*settings);
+            }
+            LobbyEvent::Error(error) => self.status = Some(error),
+            LobbyEvent::Left(reason) => {
+                self.room = None;
+                self.status = Some(reason);
+            }
+        }
+        None
+    }
+
+    fn distributor(&mut self) -> Option<SocketAddr> {
+        let address = self
+            .distributor
+            .trim()
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next());
+        if address.is_none() {
+            self.status = Some(format!("invalid distributor address {}", self.distributor));
+        }
+        address
+    }
+
+    fn refresh(&mut self) {
+        if let Some(distributor) = self.distributor() {
+            let _ = self.requests.send(LobbyRequest::ListLobbies(distributor));
+        }
+    }
+
+    fn open(&mut self) {
+        if let Some(distributor) = self.distributor() {
+            let _ = self.requests.send(LobbyRequest::OpenLobby {
+                distributor,
+                map: self.map.trim().to_string(),
+            });
+        }
+    }
+
+    fn join(&mut self, lobby: SocketAddr) {
+        self.room = Some(Room {
+            lobby,
+            player: None,
+            map: None,
+            roster: Vec::new(),
+        });
+        let _ = self.requests.send(LobbyRequest::Join {
+            lobby,
+            name: self.name.trim().to_string(),
+        });
+    }
+
+    fn leave(&mut self) {
+        self.room = None;
+        let _ = self.requests.send(LobbyRequest::Leave);
+    }
+
+    /// A match on the entered map without a server.
+    fn offline(&mut self) -> Option<MatchSettings> {
+        match Map::load_named(MAP_DIRECTORY, self.map.trim()) {
+            Ok(map) => Some(MatchSettings {
+                player: 0,
+                seed: self.seed,
+                roster: vec![0],
+                map,
+                server: None,
+            }),
+            Err(e) => {
+                self.status = Some(format!("map {}: {}", self.map, e));
+                None
+            }
+        }
+    }
+
+    /// Draws the browser or the room, returns an offline match the player
+    /// started.
+    pub fn show(&mut self, context: &Context) -> Option<MatchSettings> {
+        let mut started = None;
+        let title = if self.room.is_some() {
+            "Lobby"
+        } else {
+            "Lobbies"
+        };
+        Window::new(title)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(context, |ui| {
+                if self.room.is_some() {
+                    self.room_ui(ui);
+                } else {
+                    started = self.browser_ui(ui);
+                }
+                if let Some(status) = &self.status {
+                    ui.separator();
+                    ui.colored_label(ERROR_COLOR, status);
+                }
+            });
+        started
+    }
+
+    fn browser_ui(&mut self, ui: &mut Ui) -> Option<MatchSettings> {
+        Grid::new("lobby_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Distributor");
+            ui.text_edit_singleline(&mut self.distributor);
+            ui.end_row();
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.name);
+            ui.end_row();
+            ui.label("Map");
+            ui.text_edit_singleline(&mut self.map);
+            ui.end_row();
+        });
+        let mut started = None;
+        ui.horizontal(|ui| {
+            if ui.button("Refresh").clicked() {
+                self.refresh();
+            }
+            if ui.button("Create lobby").clicked() {
+                self.open();
+            }
+            if ui.button("Play offline").clicked() {
+                started = self.offline();
+            }
+        });
+        ui.separator();
+        if self.lobbies.is_empty() {
+            ui.label("no lobbies");
+        }
+        let mut join = None;
+        Grid::new("lobbies").striped(true).show(ui, |ui| {
+            for lobby in &self.lobbies {
+                ui.label(lobby.to_string());
+                if ui.button("Join").clicked() {
+                    join = Some(*lobby);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(lobby) = join {
+            self.join(lobby);
+        }
+        started
+    }
+
+    fn room_ui(&mut self, ui: &mut Ui) {
+        let Some(room) = &self.room else {
+            return;
+        };
+        ui.label(format!("Lobby {}", room.lobby));
+        match &room.map {
+            Some((name, true)) => ui.label(format!("Map {}", name)),
+            Some((name, false)) => ui.colored_label(ERROR_COLOR, format!("Map {} (missing)", name)),
+            None => ui.label("Joining..."),
+        };
+        ui.separator();
+        Grid::new("roster").striped(true).show(ui, |ui| {
+            for (slot, info) in room.roster.iter().enumerate() {
+                let mut name = info.name.clone();
+                if slot == 0 {
+                    name.push_str(" (host)");
+                }
+                if Some(info.id) == room.player {
+                    ui.strong(name);
+                } else {
+                    ui.label(name);
+                }
+                ui.label(if info.ready { "ready" } else { "not ready" });
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        let can_ready = matches!(room.map, Some((_, true)));
+        let ready = room.me().is_some_and(|info| info.ready);
+        let can_start = room.is_host() && room.everyone_ready();
+        let is_host = room.is_host();
+        let mut leave = false;
+        ui.horizontal(|ui| {
+            let mut checked = ready;
+            let checkbox = ui.add_enabled(can_ready, egui::Checkbox::new(&mut checked, "Ready"));
+            if checkbox.changed() {
+                let _ = self.requests.send(LobbyRequest::Ready(checked));
+            }
+            if is_host
+                && ui
+                    .add_enabled(can_start, egui::Button::new("Start"))
+                    .clicked()
+            {
+                let _ = self.requests.send(LobbyRequest::StartMatch);
+            }
+            leave = ui.button("Leave").clicked();
+        });
+        if leave {
+            self.leave();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::messages::MatchServer;
+    use std::sync::mpsc::channel;
+
+    fn player(id: PlayerId, ready: bool) -> PlayerInfo {
+        PlayerInfo {
+            id,
+            name: format!("player {}", id),
+            ready,
+        }
+    }
+
+    #[test]
+    fn test_browser() {
+        let (sender, requests) = channel();
+        let config = Config::from_text("[client]\ndistributor = 10.0.0.2:3000").unwrap();
+        let mut screen = LobbyScreen::from_config(&config, sender);
+        screen.refresh();
+        let distributor = "10.0.0.2:3000".parse().unwrap();
+        assert_eq!(
+            requests.try_recv(),
+            Ok(LobbyRequest::ListLobbies(distributor))
+        );
+
+        screen.distributor = "not an address".into();
+        screen.refresh();
+        assert!(requests.try_recv().is_err());
+        assert!(screen.status.is_some());
+
+        // opening a lobby joins it
+        let lobby: SocketAddr = "10.0.0.2:3009".parse().unwrap();
+        assert_eq!(screen.handle(LobbyEvent::LobbyOpened(lobby)), None);
+        assert_eq!(screen.lobbies, vec![lobby]);
+        assert!(screen.in_room());
+        assert_eq!(screen.status, None);
+        assert_eq!(
+            requests.try_recv(),
+            Ok(LobbyRequest::Join {
+                lobby,
+                name: "player".into()
+            })
+        );
+
+        screen.map = "no_such_map".into();
+        assert_eq!(screen.offline(), None);
+        screen.map = "meadow".into();
+        let settings = screen.offline().unwrap();
+        assert_eq!((settings.roster, settings.server), (vec![0], None));
+    }
+
+    #[test]
+    fn test_room() {
+        let (sender, requests) = channel();
+        let mut screen = LobbyScreen::new(sender);
+        let lobby: SocketAddr = "10.0.0.2:3009".parse().unwrap();
+        screen.join(lobby);
+        screen.handle(LobbyEvent::Joined(1));
+        screen.handle(LobbyEvent::Roster(vec![player(0, true), player(1, false)]));
+        let room = screen.room.clone().unwrap();
+        assert!(!room.is_host());
+        assert!(!room.everyone_ready());
+        assert_eq!(room.me(), Some(&player(1, false)));
+
+        // the host left
+        screen.handle(LobbyEvent::Roster(vec![player(1, true)]));
+        let room = screen.room.clone().unwrap();
+        assert!(room.is_host() && room.everyone_ready());
+
+        let settings = MatchSettings {
+            player: 1,
+            seed: 3,
+            roster: vec![1],
+            map: Map::load_named(MAP_DIRECTORY, "meadow").unwrap(),
+            server: Some(MatchServer {
+                address: "10.0.0.2:3008".parse().unwrap(),
+                token: 5,
+                start_tick: 0,
+            }),
+        };
+        let started = screen.handle(LobbyEvent::MatchStarting(Box::new(settings.clone())));
+        assert_eq!(started, Some(settings));
+        assert!(!screen.in_room());
+        assert_eq!(requests.try_iter().last(), Some(LobbyRequest::Leave));
+
+        screen.join(lobby);
+        screen.handle(LobbyEvent::Left("the lobby is full".into()));
+        assert!(!screen.in_room());
+        assert_eq!(screen.status.as_deref(), Some("the lobby is full"));
+    }
+}
@@ -1,424 +1,85 @@
-use bytemuck::{Pod, Zeroable};
-use log::debug;
-use log::info;
-use nalgebra_glm::rotate_x;
-use nalgebra_glm::{
-    half_pi, identity, look_at, perspective, pi, rotate_normalized_axis, translate, vec3, TMat4,
+use ant_engine::client::camera::{CameraController, CameraSettings};
+use ant_engine::client::debug_panel::{DebugInfo, DebugPanel};
+use ant_engine::client::game::{spawn_game, NetworkLink};
+use ant_engine::client::input::{Bindings, InputEvent, InputState};
+use ant_engine::client::labels;
+use ant_engine::client::lobby::spawn_lobby;
+use ant_engine::client::lobby_screen::LobbyScreen;
+use ant_engine::client::messages::{
+    Frame, GameInput, LobbyEvent, LobbyRequest, MatchSettings, RenderEvent,
 };
+use ant_engine::client::network::spawn_network;
+use ant_engine::client::selection::Selection;
+use ant_engine::client::ui::Ui;
+use ant_engine::renderer::camera::Camera;
+use ant_engine::renderer::{create_instance, Renderer, RendererSettings, SpriteAtlas};
+use ant_engine::shared::config::{Config, CONFIG_FILE};
+use ant_engine::shared::game::GameState;
+use ant_engine::shared::map::MAP_DIRECTORY;
+use ant_engine::shared::timestep::TimestepSettings;
+use log::{error, warn};
+use nalgebra_glm::Vec2;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::sync::Arc;
-use std::time::Duration;
-use std::{default, thread};
-use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
-use vulkano::command_buffer::allocator::{
-    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
-};
-use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo,
-    SubpassContents,
-};
-use vulkano::descriptor_set::allocator::{
-    StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo,
-};
-use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
-use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
-use vulkano::device::{
-    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
-};
-use vulkano::image::view::ImageView;
-use vulkano::image::Image;
-use vulkano::instance::{Instance, InstanceCreateInfo};
-use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
-use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
-use vulkano::pipeline::graphics::multisample::MultisampleState;
-use vulkano::pipeline::graphics::rasterization::RasterizationState;
-use vulkano::pipeline::graphics::vertex_input::{
-    BuffersDefinition, VertexDefinition, VertexInputState,
-};
-use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
-use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
-use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
-use vulkano::pipeline::{
-    DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
-};
-use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
-use vulkano::shader::ShaderModule;
-use vulkano::swapchain::{self, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo};
-use vulkano::sync::GpuFuture;
-use vulkano::{sync, Validated, Version, VulkanError, VulkanLibrary};
-use vulkano_win::create_surface_from_winit;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use vulkano::swapchain::Surface;
 use winit::event::{Event, WindowEvent};
-use winit::event_loop::{self, ControlFlow};
-use winit::window::{Window, WindowAttributes};
-
-#[derive(Debug, Clone)]
-struct MVP {
-    model: TMat4<f32>,
-    view: TMat4<f32>,
-    projection: TMat4<f32>,
-}
-
-impl MVP {
-    fn new() -> Self {
-        Self {
-            model: TMat4::identity(),
-            view: TMat4::identity(),
-            projection: TMat4::identity(),
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
-struct Vertex {
-    position: [f32; 3],
-    color: [f32; 3],
-}
-vulkano::impl_vertex!(Vertex, position, color);
-
-mod vs {
-    vulkano_shaders::shader! {
-        ty: "vertex",
-        src: "
-            #version 450
-            layout(location = 0) in vec3 position;
-            layout(location = 1) in vec3 color;
-
-            layout(location = 0) out vec3 out_color;
-
-            layout(set = 0, binding = 0) uniform MVP {
-                mat4 model;
-                mat4 view;
-                mat4 projection;
-            } uniforms;
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
 
-            void main() {
-                mat4 worldview = uniforms.view * uniforms.model;
-                gl_Position = uniforms.projection * worldview * vec4(position, 1.0);
-                out_color = color;
-            }
-        "
-    }
-}
-
-mod fs {
-    vulkano_shaders::shader! {
-        ty: "fragment",
-        src: "
-            #version 450
-            layout(location = 0) in vec3 in_color;
-
-            layout(location = 0) out vec4 f_color;
-
-            void main() {
-                f_color = vec4(in_color, 1.0);
-            }
-        "
-    }
-}
-
-fn get_instance() -> Arc<Instance> {
-    let library = VulkanLibrary::new().expect("no local Vulkan library/DLL");
-    let extensions = vulkano_win::required_extensions(&library);
-    Instance::new(
-        library,
-        InstanceCreateInfo {
-            enabled_extensions: extensions,
-            application_name: Some("Hello Triangle".into()),
-            engine_name: Some("Ant Engine".into()),
-            engine_version: 0.into(),
-            max_api_version: Some(Version::V1_1),
-            ..Default::default()
-        },
-    )
-    .expect("failed to create Vulkan instance")
-}
-
-fn create_surface(instance: Arc<Instance>) -> (Arc<Surface>, event_loop::EventLoop<()>) {
-    let event_loop = winit::event_loop::EventLoop::new();
-    let window = Window::new(&event_loop).expect("failed to create window");
-    let surface =
-        create_surface_from_winit(Arc::new(window), instance).expect("failed to create surface");
-    (surface, event_loop)
-}
-
-fn get_physical_device(
-    instance: Arc<Instance>,
-    surface: Arc<Surface>,
-    device_extensions: DeviceExtensions,
-) -> (Arc<PhysicalDevice>, u32) {
-    let (physical_device, queue_family_index) = instance
-        .enumerate_physical_devices()
-        .expect("failed to enumerate physical devices")
-        .filter(|p| p.supported_extensions().contains(&device_extensions))
-        .filter_map(|p| {
-            p.queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    // TODO: choose the best queue family
-                    q.queue_flags.contains(QueueFlags::GRAPHICS)
-                        && p.surface_support(i as u32, &surface).unwrap_or(false)
-                })
-                .map(|i| (p, i as u32))
-        })
-        .min_by_key(|(p, _)| match p.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            PhysicalDeviceType::Other => 4,
-            _ => 5,
-        })
-        .expect("no suitable physical device found");
-    (physical_device, queue_family_index)
-}
-
-fn create_device(
-    physical_device: Arc<PhysicalDevice>,
-    queue_family_index: u32,
-    device_extensions: DeviceExtensions,
-) -> (
-    Arc<Device>,
-    impl Iterator<Item = Arc<Queue>> + ExactSizeIterator,
-) {
-    Device::new(
-        physical_device,
-        DeviceCreateInfo {
-            enabled_extensions: device_extensions,
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index,
-                ..Default::default()
-            }],
-            ..Default::default()
-        },
-    )
-    .expect("failed to create device")
-}
-
-fn create_swapchain(
-    device: Arc<Device>,
-    surface: Arc<Surface>,
-    queue: Arc<Queue>,
-) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
-    let capabilities = device
-        .physical_device()
-        .surface_capabilities(&surface, Default::default())
-        .expect("failed to get surface capabilities");
-
-    let usage = capabilities.supported_usage_flags;
-    let alpha = capabilities
-        .supported_composite_alpha
-        .into_iter()
-        .nth(0) // TODO: choose the best alpha mode
-        .unwrap();
-
-    let image_format = device
-        .physical_device()
-        .surface_formats(&surface, Default::default())
-        .expect("failed to get surface formats")
-        .iter()
-        .nth(0) //TODO: choose the best format
-        .unwrap()
-        .0;
-
-    let window = surface
-        .object()
-        .expect("failed to get surface handle")
-        .downcast_ref::<Window>()
-        .expect("failed to get window handle");
-    let image_extent: [u32; 2] = window.inner_size().into();
-
-    Swapchain::new(
-        device.clone(),
-        surface.clone(),
-        SwapchainCreateInfo {
-            min_image_count: capabilities.min_image_count,
-            image_format,
-            image_extent,
-            image_usage: usage,
-            composite_alpha: alpha,
-            ..Default::default()
-        },
-    )
-    .expect("failed to create swapchain")
-}
-
-fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
-    vulkano::single_pass_renderpass!(
-        device,
-        attachments: {
-            color: {
-                format: swapchain.image_format(),
-                samples: 1,
-                load_op: Clear,
-                store_op: Store,
-            },
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {},
-        },
-    )
-    .expect("failed to create render pass")
-}
-
-fn window_size_dependent_setup(
-    images: &[Arc<Image>],
-    render_pass: Arc<RenderPass>,
-    viewport: &mut Viewport,
-) -> Vec<Arc<Framebuffer>> {
-    let extent = images[0].extent();
-    viewport.extent = [extent[0] as f32, extent[1] as f32];
-
-    images
-        .iter()
-        .map(|image| {
-            let view = ImageView::new_default(image.clone()).expect("failed to create image view");
-            Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![view],
-                    ..Default::default()
-                },
-            )
-            .expect("failed to create framebuffer")
-        })
-        .collect::<Vec<_>>()
-}
+/// How often the main thread checks whether the other threads are alive.
+const THREAD_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Frame time while the window is minimized and nothing is drawn, so the
+/// render thread does not spin.
+const MINIMIZED_FRAME_TIME: Duration = Duration::from_millis(50);
 
 fn main() {
     env_logger::init();
-    let mut mvp = MVP::new();
-    mvp.view = look_at(
-        &vec3(0.0, 0.0, 0.1),
-        &vec3(0.0, 0.0, 0.0),
-        &vec3(0.0, 1.0, 0.0),
-    );
-    mvp.model = translate(&identity(), &vec3(0.0, 0.0, -1.0));
-
-    let device_extensions = DeviceExtensions {
-        khr_swapchain: true,
-        ..DeviceExtensions::empty()
-    };
-    let instance = get_instance();
-    let (surface, event_loop) = create_surface(instance.clone());
-    let (physical_device, queue_family_index) =
-        get_physical_device(instance.clone(), surface.clone(), device_extensions);
-    let (device, mut queues) =
-        create_device(physical_device, queue_family_index, device_extensions);
-    let queue = queues.next().expect("no queue found");
-    let (mut swapchain, images) = create_swapchain(device.clone(), surface.clone(), queue.clone());
-    let command_buffer_allocator =
-        StandardCommandBufferAllocator::new(device.clone(), Default::default());
-
-    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
-    let describtor_set_allocator = StandardDescriptorSetAllocator::new(
-        device.clone(),
-        StandardDescriptorSetAllocatorCreateInfo::default(),
-    );
-
-    let vertices = [
-        Vertex {
-            position: [-0.5, 0.5, 0.0],
-            color: [1.0, 0.0, 0.0],
-        },
-        Vertex {
-            position: [0.5, 0.5, 0.0],
-            color: [0.0, 1.0, 0.0],
-        },
-        Vertex {
-            position: [0.0, -0.5, 0.0],
-            color: [0.0, 0.0, 1.0],
-        },
-    ];
-
-    let vertex_buffer = Buffer::from_iter(
-        memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::VERTEX_BUFFER,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        vertices,
-    )
-    .expect("failed to create vertex buffer");
-
-    let render_pass = get_render_pass(device.clone(), swapchain.clone());
-    let subpass = Subpass::from(render_pass.clone(), 0).expect("failed to get subpass");
-
-    let vs = vs::load(device.clone()).expect("failed to create shader module");
-    let fs = fs::load(device.clone()).expect("failed to create shader module");
-    let vs_entry_point = vs.entry_point("main").expect("failed to get entry point");
-    let fs_entry_point = fs.entry_point("main").expect("failed to get entry point");
-
-    let uniform_buffer = SubbufferAllocator::new(
-        memory_allocator.clone(),
-        SubbufferAllocatorCreateInfo {
-            buffer_usage: BufferUsage::UNIFORM_BUFFER,
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
+    let config = Config::load_or_default(CONFIG_FILE);
+    let settings = TimestepSettings::from_config(&config);
+
+    let event_loop = EventLoop::new();
+    let window = Arc::new(
+        WindowBuilder::new()
+            .with_title("Ant Engine")
+            .build(&event_loop)
+            .expect("failed to create window"),
     );
-
-    let vertex_input_state =
-        <Vertex as vulkano::pipeline::graphics::vertex_input::Vertex>::per_vertex()
-            .definition(&vs_entry_point.info().input_interface)
-            .expect("failed to get vertex input state");
-
-    let stages = [
-        PipelineShaderStageCreateInfo::new(vs_entry_point),
-        PipelineShaderStageCreateInfo::new(fs_entry_point),
-    ];
-
-    let layout = PipelineLayout::new(
-        device.clone(),
-        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-            .into_pipeline_layout_create_info(device.clone())
-            .expect("failed to create pipeline layout"),
-    )
-    .expect("failed to create pipeline layout");
-    let pipeline = GraphicsPipeline::new(
-        device.clone(),
-        None,
-        GraphicsPipelineCreateInfo {
-            stages: stages.into_iter().collect(),
-            vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(ViewportState::default()),
-            rasterization_state: Some(RasterizationState::default()),
-            multisample_state: Some(MultisampleState::default()),
-            color_blend_state: Some(ColorBlendState::with_attachment_states(
-                subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
-            )),
-            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
-            subpass: Some(subpass.into()),
-            ..GraphicsPipelineCreateInfo::layout(layout)
-        },
-    )
-    .expect("failed to create pipeline");
-
-    let mut viewport = Viewport {
-        offset: [0.0, 0.0],
-        extent: [0.0, 0.0],
-        depth_range: (0.0..=1.0).into(),
+    let instance = create_instance("Ant Engine", Surface::required_extensions(&event_loop))
+        .expect("failed to create Vulkan instance");
+    let surface = Surface::from_window(instance, window.clone()).expect("failed to create surface");
+
+    let (render_sender, render_events) = channel();
+    let (lobby_sender, lobby_requests) = channel();
+    let (lobby_event_sender, lobby_events) = channel();
+    let mut lobby = Some(spawn_lobby(
+        MAP_DIRECTORY,
+        lobby_requests,
+        lobby_event_sender,
+    ));
+
+    let extent: [u32; 2] = window.inner_size().into();
+    let controls = RenderControls {
+        camera: Camera::new(Vec2::zeros(), extent),
+        controller: CameraController::new(CameraSettings::from_config(&config)),
+        input: InputState::new(Bindings::from_config(&config)),
+        ui: Ui::new(window.scale_factor() as f32),
+        debug_panel: DebugPanel::from_config(&config),
+        lobby_screen: LobbyScreen::from_config(&config, lobby_sender.clone()),
+        renderer_settings: RendererSettings::from_config(&config),
+        sprites: load_sprites(&config),
+        shader_directory: config.get("render.shader_directory").ok().flatten(),
     };
-    let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
-
-    let mut recreate_swapchain = false;
-    let mut previous_frame_end =
-        Some(Box::new(sync::now(device.clone())) as Box<dyn sync::GpuFuture>);
-
-    let start_time = std::time::Instant::now();
+    let mut render = Some(thread::spawn(move || {
+        render_thread(surface, controls, settings, render_events, lobby_events)
+    }));
 
     event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + THREAD_CHECK_INTERVAL);
+        let finished = lobby.as_ref().is_none_or(|lobby| lobby.is_finished())
+            || render.as_ref().is_none_or(|render| render.is_finished());
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
@@ -427,181 +88,299 @@ fn main() {
                 *control_flow = ControlFlow::Exit;
             }
             Event::WindowEvent {
-                event: WindowEvent::Resized(_),
+                event: WindowEvent::Resized(size),
                 ..
             } => {
-                recreate_swapchain = true;
+                let _ = render_sender.send(RenderEvent::Resized(size.into()));
             }
-            Event::RedrawEventsCleared => {
-                // release unused resources
-                previous_frame_end
-                    .as_mut()
-                    .take()
-                    .unwrap()
-                    .cleanup_finished();
-                // check if swapchain needs to be recreated
-                if recreate_swapchain {
-                    let window = surface
-                        .object()
-                        .expect("failed to get surface handle")
-                        .downcast_ref::<Window>()
-                        .expect("failed to get window handle");
-                    let image_extent: [u32; 2] = window.inner_size().into();
-
-                    let aspect_ratio = image_extent[0] as f32 / image_extent[1] as f32;
-                    mvp.projection = perspective(aspect_ratio, half_pi(), 0.01, 100.0);
-
-                    let (new_swapchain, new_images) =
-                        match swapchain.recreate(SwapchainCreateInfo {
-                            image_extent,
-                            ..swapchain.create_info()
-                        }) {
-                            Ok(r) => r,
-                            Err(e) => {
-                                panic!("failed to recreate swapchain: {:?}", e);
-                            }
-                        };
-
-                    swapchain = new_swapchain;
-                    framebuffers = window_size_dependent_setup(
-                        &new_images,
-                        render_pass.clone(),
-                        &mut viewport,
-                    );
-                    recreate_swapchain = false;
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                ..
+            } => {
+                let _ = render_sender.send(RenderEvent::ScaleFactor(scale_factor as f32));
+            }
+            Event::WindowEvent { event, .. } => {
+                if let Some(input) = InputEvent::from_window_event(&event) {
+                    let _ = render_sender.send(RenderEvent::Input(input));
                 }
+            }
+            Event::MainEventsCleared if finished => {
+                warn!("A client thread stopped, shutting down");
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::LoopDestroyed => {
+                // the render thread stops the match on its way out
+                let _ = render_sender.send(RenderEvent::Shutdown);
+                let _ = lobby_sender.send(LobbyRequest::Shutdown);
+                for handle in [render.take(), lobby.take()].into_iter().flatten() {
+                    if handle.join().is_err() {
+                        error!("A client thread panicked");
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+}
 
-                let uniform_subbuffer = {
-                    let elapsed = start_time.elapsed().as_secs_f64()
-                        + start_time.elapsed().subsec_nanos() as f64 * 1e-9;
+/// What the render thread does with the player's input.
+struct RenderControls {
+    camera: Camera,
+    controller: CameraController,
+    input: InputState,
+    ui: Ui,
+    debug_panel: DebugPanel,
+    lobby_screen: LobbyScreen,
+    renderer_settings: RendererSettings,
+    sprites: Option<SpriteAtlas>,
+    shader_directory: Option<PathBuf>,
+}
 
-                    let elapsed_as_radians = elapsed * pi::<f64>() / 180.0 * 30.0;
-                    debug!("elapsed: {}", elapsed_as_radians);
-                    let model = rotate_x(
-                        &mvp.model,
-                        2.5,
-                        // elapsed_as_radians as f32,
-                        // &vec3(0.0, 10.0, 1.0),
-                    );
-                    let model = [
-                        [1.0, 0.0, 0.0, 0.0],
-                        [0.0, 1.0, 0.0, 0.0],
-                        [0.0, 0.0, 1.0, 0.0],
-                        [0.0, 0.0, 0.0, 1.0],
-                    ];
-                    debug!("model: {:?}", model);
+/// Rebuilds the pipelines whenever the GLSL in `directory` changes.
+#[cfg(feature = "shader-reload")]
+fn watch_shaders(renderer: &mut Renderer, directory: &Path) {
+    if let Err(e) = renderer.watch_shaders(directory) {
+        error!(
+            "Failed to watch the shaders in {}: {}",
+            directory.display(),
+            e
+        );
+    }
+}
 
-                    let uniform_data = vs::MVP {
-                        model: mvp.model.into(),
-                        view: mvp.view.into(),
-                        projection: mvp.projection.into(),
-                    };
+#[cfg(not(feature = "shader-reload"))]
+fn watch_shaders(_: &mut Renderer, directory: &Path) {
+    warn!(
+        "Not watching the shaders in {}, the client was built without the shader-reload feature",
+        directory.display()
+    );
+}
 
-                    let uniform_subbuffer = uniform_buffer
-                        .allocate_sized()
-                        .expect("failed to allocate uniform buffer");
-                    *uniform_subbuffer.write().unwrap() = uniform_data;
+/// The atlas of the `render.sprites` directory, ants and nests keep their
+/// meshes without one.
+fn load_sprites(config: &Config) -> Option<SpriteAtlas> {
+    let directory: PathBuf = config.get("render.sprites").ok().flatten()?;
+    match SpriteAtlas::load_directory(&directory) {
+        Ok(atlas) => Some(atlas),
+        Err(e) => {
+            error!("Failed to load sprites from {}: {}", directory.display(), e);
+            None
+        }
+    }
+}
 
-                    uniform_subbuffer
-                };
-                let layout = pipeline
-                    .layout()
-                    .set_layouts()
-                    .get(0)
-                    .expect("failed to get layout 0");
-                let set = PersistentDescriptorSet::new(
-                    &describtor_set_allocator,
-                    layout.clone(),
-                    [WriteDescriptorSet::buffer(0, uniform_subbuffer)],
-                    [],
-                )
-                .expect("failed to create descriptor set");
+/// The threads of a running match.
+struct Session {
+    game: JoinHandle<()>,
+    network: Option<JoinHandle<()>>,
+    commands: Sender<GameInput>,
+    frames: Receiver<Frame>,
+}
 
-                let (image_index, suboptimal, acquire_future) =
-                    match swapchain::acquire_next_image(swapchain.clone(), None) {
-                        Ok(r) => r,
-                        Err(Validated::Error(VulkanError::OutOfDate)) => {
-                            recreate_swapchain = true;
-                            return;
-                        }
-                        Err(e) => {
-                            panic!("failed to acquire next image: {:?}", e);
-                        }
-                    };
-                if suboptimal {
-                    recreate_swapchain = true;
-                }
-                let clear_values = vec![Some([0.0, 0.0, 1.0, 1.0].into())];
-                let mut cmd_buffer_builder = AutoCommandBufferBuilder::primary(
-                    &command_buffer_allocator,
-                    queue.queue_family_index(),
-                    CommandBufferUsage::OneTimeSubmit,
-                )
-                .unwrap();
+impl Session {
+    /// Starts the game thread, and the network thread for online matches.
+    /// Returns the world size with it, or why the match server couldn't be
+    /// reached.
+    fn start(
+        settings: &MatchSettings,
+        timestep: TimestepSettings,
+    ) -> std::io::Result<(Session, Vec2)> {
+        let (network_link, network) = match settings.server {
+            Some(server) => {
+                let (request_sender, requests) = channel();
+                let (event_sender, events) = channel();
+                let handle = spawn_network(server, settings.player, requests, event_sender)?;
+                let link = NetworkLink {
+                    requests: request_sender,
+                    events,
+                    start_tick: server.start_tick,
+                };
+                (Some(link), Some(handle))
+            }
+            None => (None, None),
+        };
+        let state = GameState::from_map(settings.seed, &settings.map, &settings.roster);
+        let world_size = state.world_size().to_glm();
+        let (frame_sender, frames) = channel();
+        let (commands, game_inputs) = channel();
+        let game = spawn_game(
+            state,
+            settings.player,
+            timestep,
+            game_inputs,
+            network_link,
+            frame_sender,
+        );
+        let session = Session {
+            game,
+            network,
+            commands,
+            frames,
+        };
+        Ok((session, world_size))
+    }
 
-                cmd_buffer_builder
-                    .begin_render_pass(
-                        RenderPassBeginInfo {
-                            clear_values,
-                            ..RenderPassBeginInfo::framebuffer(
-                                framebuffers[image_index as usize].clone(),
-                            )
-                        },
-                        SubpassBeginInfo {
-                            contents: SubpassContents::Inline,
-                            ..Default::default()
-                        },
-                    )
-                    .unwrap()
-                    .set_viewport(0, vec![viewport.clone()].into())
-                    .expect("failed to set viewport")
-                    .bind_pipeline_graphics(pipeline.clone())
-                    .expect("failed to bind pipeline")
-                    .bind_descriptor_sets(
-                        vulkano::pipeline::PipelineBindPoint::Graphics,
-                        pipeline.layout().clone(),
-                        0,
-                        set.clone(),
-                    )
-                    .expect("failed to bind descriptor set")
-                    .bind_vertex_buffers(0, vertex_buffer.clone())
-                    .expect("failed to bind vertex buffer")
-                    .draw(vertex_buffer.len() as u32, 1, 0, 0)
-                    .expect("failed to draw")
-                    .end_render_pass(vulkano::command_buffer::SubpassEndInfo {
-                        ..Default::default()
-                    })
-                    .unwrap();
+    /// Stops the game thread, which stops the network thread on its way out.
+    fn stop(self) {
+        let _ = self.commands.send(GameInput::Shutdown);
+        for handle in [Some(self.game), self.network].into_iter().flatten() {
+            if handle.join().is_err() {
+                error!("A match thread panicked");
+            }
+        }
+    }
+}
 
-                let command_buffer = cmd_buffer_builder.build().unwrap();
+/// Shows the lobby screens until a match starts, then draws the latest
+/// [`Frame`] from the game thread, paced by presentation, and sends the
+/// player's commands back to it.
+fn render_thread(
+    surface: Arc<Surface>,
+    controls: RenderControls,
+    timestep: TimestepSettings,
+    events: Receiver<RenderEvent>,
+    lobby_events: Receiver<LobbyEvent>,
+) {
+    let RenderControls {
+        mut camera,
+        controller,
+        mut input,
+        mut ui,
+        mut debug_panel,
+        mut lobby_screen,
+        renderer_settings,
+        sprites,
+        shader_directory,
+    } = controls;
+    let mut renderer = match Renderer::new(surface, camera.extent(), &renderer_settings) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            error!("Failed to create the renderer: {}", e);
+            return;
+        }
+    };
+    if let Some(directory) = shader_directory {
+        watch_shaders(&mut renderer, &directory);
+    }
+    if let Some(atlas) = sprites {
+        if let Err(e) = renderer.set_sprites(&atlas) {
+            error!("Failed to upload the sprites: {}", e);
+        }
+    }
+    let mut session: Option<Session> = None;
+    let mut player = 0;
+    let mut selection = Selection::new(player);
+    let mut frame: Option<Frame> = None;
+    let mut last_frame = Instant::now();
+    'frames: loop {
+        loop {
+            match events.try_recv() {
+                Ok(RenderEvent::Resized(extent)) => {
+                    renderer.resize(extent);
+                    camera.set_extent(extent);
+                }
+                Ok(RenderEvent::ScaleFactor(scale_factor)) => ui.set_pixels_per_point(scale_factor),
+                Ok(RenderEvent::Input(event)) => {
+                    input.handle(event);
+                    ui.handle(event);
+                }
+                Ok(RenderEvent::Shutdown) | Err(TryRecvError::Disconnected) => break 'frames,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if session
+            .as_ref()
+            .is_some_and(|session| session.game.is_finished())
+        {
+            warn!("The match ended");
+            session.take().unwrap().stop();
+            frame = None;
+            lobby_screen.match_ended("the match ended");
+        }
+        let mut starting = None;
+        for event in lobby_events.try_iter() {
+            starting = lobby_screen.handle(event).or(starting);
+        }
 
-                let future = previous_frame_end
-                    .take()
-                    .unwrap()
-                    .join(acquire_future)
-                    .then_execute(queue.clone(), command_buffer)
-                    .unwrap()
-                    .then_swapchain_present(
-                        queue.clone(),
-                        SwapchainPresentInfo::swapchain_image_index(swapchain.clone(), image_index),
-                    )
-                    .then_signal_fence_and_flush();
+        let now = Instant::now();
+        debug_panel.record_frame(now - last_frame);
+        if !ui.wants_keyboard() {
+            debug_panel.update(&input);
+        }
+        if session.is_some() && !ui.wants_pointer() && !ui.wants_keyboard() {
+            controller.update(&input, &mut camera, now - last_frame);
+        }
+        last_frame = now;
 
-                match future {
-                    Ok(future) => {
-                        previous_frame_end = Some(Box::new(future) as Box<_>);
-                    }
-                    Err(Validated::Error(VulkanError::OutOfDate)) => {
-                        recreate_swapchain = true;
-                        previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<_>);
-                    }
-                    Err(e) => {
-                        println!("Failed to flush future: {:?}", e);
-                        previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<_>);
-                    }
+        if let Some(latest) = session
+            .as_ref()
+            .and_then(|session| session.frames.try_iter().last())
+        {
+            frame = Some(latest);
+        }
+        let mut snapshot = frame
+            .as_ref()
+            .map(|frame| frame.interpolate(now))
+            .unwrap_or_default();
+        if let Some(session) = session.as_ref().filter(|_| !ui.wants_pointer()) {
+            if let Some(command) = selection.update(&input, &camera, &snapshot) {
+                let _ = session.commands.send(GameInput::Command(command));
+            }
+        }
+        selection.mark(&mut snapshot);
+        let info = DebugInfo {
+            frame: frame.as_ref(),
+            snapshot: &snapshot,
+            selection: &selection,
+            camera: &camera,
+        };
+        let in_match = session.is_some();
+        let ui_frame = ui.run(camera.extent(), |context| {
+            if !in_match {
+                starting = lobby_screen.show(context).or(starting.take());
+            }
+            debug_panel.show(context, &info);
+        });
+        let labels = match session {
+            Some(_) => labels::labels(&snapshot, player, camera.extent()),
+            None => Vec::new(),
+        };
+        renderer.set_pheromone_layer(debug_panel.pheromones());
+        renderer.set_present_mode(debug_panel.present_mode());
+        if let Err(e) = renderer.set_labels(&labels) {
+            error!("Failed to upload the labels: {}", e);
+            break;
+        }
+        if let Err(e) = renderer.set_ui(ui_frame) {
+            error!("Failed to upload the UI: {}", e);
+            break;
+        }
+        if let Err(e) = renderer.draw_frame(&snapshot, &camera) {
+            error!("Failed to draw a frame: {}", e);
+            break;
+        }
+        input.end_frame();
+
+        if let (Some(settings), None) = (starting, &session) {
+            match Session::start(&settings, timestep) {
+                Ok((started, world_size)) => {
+                    camera = Camera::new(world_size, camera.extent());
+                    player = settings.player;
+                    selection = Selection::new(player);
+                    debug_panel.show_pheromones_of(player);
+                    session = Some(started);
+                }
+                Err(e) => {
+                    error!("Failed to connect to the match server: {}", e);
+                    lobby_screen.match_ended(format!("couldn't reach the match server: {}", e));
                 }
             }
-            _ => {}
         }
-    });
+        if renderer.is_minimized() {
+            thread::sleep(MINIMIZED_FRAME_TIME);
+        }
+    }
+    if let Some(session) = session {
+        session.stop();
+    }
 }
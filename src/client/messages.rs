@@ -0,0 +1,158 @@
+//! Messages passed between the client threads.
+
+use crate::client::input::InputEvent;
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::shared::game::{Command, CommandKind};
+use crate::shared::map::Map;
+use crate::shared::protocols::{PlayerId, PlayerInfo};
+use crate::shared::timestep::TickStats;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Main and render thread to game thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameInput {
+    /// A command of the local player, applied once the server confirmed it.
+    Command(CommandKind),
+    Shutdown,
+}
+
+/// Game thread to network thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkRequest {
+    Send(Command),
+    /// Asks the server for the ticks from `first` to `last` again.
+    Resend {
+        first: u64,
+        last: u64,
+    },
+    Shutdown,
+}
+
+/// Network thread to game thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkEvent {
+    /// The server's commands for `tick`.
+    Tick {
+        tick: u64,
+        commands: Vec<Command>,
+    },
+    /// Sent with every ping.
+    Stats(NetworkStats),
+    Disconnected(String),
+}
+
+/// Traffic with the game server since connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    /// Round trip time of the last answered ping.
+    pub ping: Option<Duration>,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Pings that were not answered before the next one went out.
+    pub pings_lost: u64,
+}
+
+/// Game thread to render thread, sent after every tick.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub previous: Arc<RenderSnapshot>,
+    pub current: Arc<RenderSnapshot>,
+    /// When `current` was produced.
+    pub produced: Instant,
+    pub tick_duration: Duration,
+    pub tick_stats: TickStats,
+    /// `None` when playing offline.
+    pub network: Option<NetworkStats>,
+}
+
+impl Frame {
+    /// Progress from `previous` to `current` at `now`, in `[0, 1]`. The
+    /// renderer stays one tick behind the simulation so it always has two
+    /// states to draw between.
+    pub fn alpha(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.produced);
+        (elapsed.as_secs_f32() / self.tick_duration.as_secs_f32()).min(1.0)
+    }
+
+    pub fn interpolate(&self, now: Instant) -> RenderSnapshot {
+        RenderSnapshot::interpolate(&self.previous, &self.current, self.alpha(now))
+    }
+}
+
+/// Main thread to render thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderEvent {
+    /// The window's new inner size in pixels.
+    Resized([u32; 2]),
+    /// Physical pixels per logical pixel.
+    ScaleFactor(f32),
+    Input(InputEvent),
+    Shutdown,
+}
+
+/// Render thread to lobby thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyRequest {
+    /// Asks the distributor at this address for its lobbies.
+    ListLobbies(SocketAddr),
+    /// Asks the distributor to open a lobby for the named map.
+    OpenLobby {
+        distributor: SocketAddr,
+        map: String,
+    },
+    /// Leaves the current lobby, if any, and joins this one.
+    Join {
+        lobby: SocketAddr,
+        name: String,
+    },
+    Ready(bool),
+    /// Only the host's request is followed.
+    StartMatch,
+    Leave,
+    Shutdown,
+}
+
+/// Lobby thread to render thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyEvent {
+    Lobbies(Vec<SocketAddr>),
+    LobbyOpened(SocketAddr),
+    Joined(PlayerId),
+    /// The lobby's map, `available` when the local copy matches it.
+    Map {
+        name: String,
+        available: bool,
+    },
+    Roster(Vec<PlayerInfo>),
+    MatchStarting(Box<MatchSettings>),
+    /// A request failed, the lobby connection is unaffected.
+    Error(String),
+    /// The lobby connection ended before the match started.
+    Left(String),
+}
+
+/// Everything needed to start the game thread of a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchSettings {
+    pub player: PlayerId,
+    pub seed: u64,
+    /// In slot order.
+    pub roster: Vec<PlayerId>,
+    pub map: Map,
+    /// The game server, `None` when playing offline.
+    pub server: Option<MatchServer>,
+}
+
+/// The game server of an online match and how to join it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchServer {
+    pub address: SocketAddr,
+    /// The player's secret from the lobby.
+    pub token: u64,
+    /// Server tick the match starts after.
+    pub start_tick: u64,
+}
@@ -0,0 +1,25 @@
+//! Client side of the engine.
+//!
+//! The client runs in threads that only talk through the typed channels in
+//! [`messages`]:
+//! - the main thread runs the window event loop and ties everything together,
+//! - the [`lobby`] thread talks to the distributor and lobby until a match
+//!   starts,
+//! - the network thread owns the socket to the game server,
+//! - the game thread owns the [`GameState`](crate::shared::game::GameState),
+//! - the render thread draws interpolated
+//!   [`snapshot`](crate::renderer::snapshot)s of it with the
+//!   [`labels`] and the [`ui`] on top, shows the [`lobby_screen`] until a
+//!   match starts and turns input into camera movement and [`selection`]
+//!   commands.
+pub mod camera;
+pub mod debug_panel;
+pub mod game;
+pub mod input;
+pub mod labels;
+pub mod lobby;
+pub mod lobby_screen;
+pub mod messages;
+pub mod network;
+pub mod selection;
+pub mod ui;
@@ -0,0 +1,154 @@
+//! Network thread, the only owner of the socket to the game server.
+
+use crate::client::messages::{MatchServer, NetworkEvent, NetworkRequest, NetworkStats};
+use crate::shared::protocols::{GameClientMessages, GameServerMessages, PlayerId};
+use log::{info, warn};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The server drops clients it has not heard from in a while, so a ping
+/// goes out this often.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a receive blocks before requests are checked again.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Connects to the game server as `player` and starts forwarding in both
+/// directions. The thread ends on [`NetworkRequest::Shutdown`], when the
+/// game thread hangs up, or after sending [`NetworkEvent::Disconnected`].
+pub fn spawn_network(
+    server: MatchServer,
+    player: PlayerId,
+    requests: Receiver<NetworkRequest>,
+    events: Sender<NetworkEvent>,
+) -> std::io::Result<JoinHandle<()>> {
+    let join = GameClientMessages::Join {
+        player,
+        token: server.token,
+    };
+    let server = server.address;
+    let local: SocketAddr = if server.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    info!("Connecting to game server {}", server);
+    Ok(std::thread::spawn(move || {
+        if let Err(e) = run(&socket, join, &requests, &events) {
+            let _ = events.send(NetworkEvent::Disconnected(e.to_string()));
+        }
+    }))
+}
+
+/// The socket with counters for the statistics.
+struct Connection<'a> {
+    socket: &'a UdpSocket,
+    stats: NetworkStats,
+    /// Number and send time of the ping waiting for its pong.
+    ping: Option<(u64, Instant)>,
+    next_ping: u64,
+    last_ping: Instant,
+    /// Sent until a ping is answered, and again when one is lost, in case
+    /// the server dropped the client.
+    join: GameClientMessages,
+    joined: bool,
+}
+
+impl Connection<'_> {
+    fn send(&mut self, message: &GameClientMessages) -> std::io::Result<()> {
+        let size = self.socket.send(&bincode::serialize(message).unwrap())?;
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += size as u64;
+        Ok(())
+    }
+
+    fn ping(&mut self) -> std::io::Result<()> {
+        if self.ping.is_some() {
+            self.stats.pings_lost += 1;
+            self.joined = false;
+        }
+        if !self.joined {
+            let join = self.join.clone();
+            self.send(&join)?;
+        }
+        let number = self.next_ping;
+        self.next_ping += 1;
+        self.last_ping = Instant::now();
+        self.ping = Some((number, self.last_ping));
+        self.send(&GameClientMessages::Ping(number))
+    }
+
+    fn pong(&mut self, number: u64) {
+        match self.ping {
+            Some((sent, at)) if sent == number => {
+                self.stats.ping = Some(at.elapsed());
+                self.ping = None;
+                self.joined = true;
+            }
+            _ => warn!("Ignoring unexpected pong {}", number),
+        }
+    }
+}
+
+fn run(
+    socket: &UdpSocket,
+    join: GameClientMessages,
+    requests: &Receiver<NetworkRequest>,
+    events: &Sender<NetworkEvent>,
+) -> std::io::Result<()> {
+    let mut buf = [0; 65536];
+    let mut connection = Connection {
+        socket,
+        stats: NetworkStats::default(),
+        ping: None,
+        next_ping: 0,
+        last_ping: Instant::now(),
+        join,
+        joined: false,
+    };
+    connection.ping()?;
+    loop {
+        loop {
+            match requests.try_recv() {
+                Ok(NetworkRequest::Send(command)) => {
+                    connection.send(&GameClientMessages::Command(command))?;
+                }
+                Ok(NetworkRequest::Resend { first, last }) => {
+                    connection.send(&GameClientMessages::Resend { first, last })?;
+                }
+                Ok(NetworkRequest::Shutdown) | Err(TryRecvError::Disconnected) => return Ok(()),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if connection.last_ping.elapsed() >= KEEP_ALIVE_INTERVAL {
+            connection.ping()?;
+            if events.send(NetworkEvent::Stats(connection.stats)).is_err() {
+                return Ok(());
+            }
+        }
+
+        match socket.recv(&mut buf) {
+            Ok(size) => {
+                connection.stats.packets_received += 1;
+                connection.stats.bytes_received += size as u64;
+                match bincode::deserialize(&buf[..size]) {
+                    Ok(GameServerMessages::Tick { tick, commands }) => {
+                        if events.send(NetworkEvent::Tick { tick, commands }).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Ok(GameServerMessages::Pong(number)) => connection.pong(number),
+                    Err(e) => warn!("Invalid message from the game server: {}", e),
+                }
+            }
+            Err(ref e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Click and box selection of the local player's ants and nests, and the
+//! commands given to the selected ants.
+//!
+//! Selection works on the interpolated [`RenderSnapshot`] the render thread
+//! is about to draw, so the player picks what they see. Commands are only
+//! returned, the render thread hands them to the game thread which sends
+//! them to the server.
+
+use crate::client::input::{Action, InputState};
+use crate::renderer::camera::Camera;
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::shared::ecs::Entity;
+use crate::shared::fixed::FixedVec2;
+use crate::shared::game::CommandKind;
+use crate::shared::protocols::PlayerId;
+use nalgebra_glm::{distance, Vec2};
+
+/// How far from an ant or food source a click still hits it, in pixels.
+const CLICK_RADIUS: f32 = 8.0;
+/// Nests are hit anywhere on their mound, in world units.
+const NEST_RADIUS: f32 = 2.0;
+/// Cursor movement, in pixels, that turns a click into a box selection.
+const BOX_THRESHOLD: f32 = 4.0;
+
+/// Either some ants or a single nest of `player`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    player: PlayerId,
+    /// Sorted.
+    ants: Vec<Entity>,
+    nest: Option<Entity>,
+    /// Where the select button went down, in pixels.
+    box_start: Option<Vec2>,
+}
+
+impl Selection {
+    pub fn new(player: PlayerId) -> Selection {
+        Selection {
+            player,
+            ants: Vec::new(),
+            nest: None,
+            box_start: None,
+        }
+    }
+
+    pub fn ants(&self) -> &[Entity] {
+        &self.ants
+    }
+
+    pub fn nest(&self) -> Option<Entity> {
+        self.nest
+    }
+
+    pub fn clear(&mut self) {
+        self.ants.clear();
+        self.nest = None;
+    }
+
+    /// Selects from this frame's input and returns the command the player
+    /// gave, if any.
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        camera: &Camera,
+        snapshot: &RenderSnapshot,
+    ) -> Option<CommandKind> {
+        self.forget_missing(snapshot);
+        if input.pressed(Action::Select) {
+            self.box_start = input.cursor();
+        }
+        if input.released(Action::Select) {
+            if let (Some(start), Some(end)) = (self.box_start.take(), input.cursor()) {
+                if distance(&start, &end) > BOX_THRESHOLD {
+                    self.select_box(start, end, camera, snapshot);
+                } else {
+                    self.click(end, camera, snapshot);
+                }
+            }
+        }
+        if input.pressed(Action::CommandMove) && !self.ants.is_empty() {
+            return self.command(input.cursor()?, camera, snapshot);
+        }
+        None
+    }
+
+    /// Flags the selected entities of `snapshot` for the renderer.
+    pub fn mark(&self, snapshot: &mut RenderSnapshot) {
+        for ant in &mut snapshot.ants {
+            ant.selected = self.ants.binary_search(&ant.entity).is_ok();
+        }
+        for nest in &mut snapshot.nests {
+            nest.selected = self.nest == Some(nest.entity);
+        }
+    }
+
+    fn click(&mut self, pixel: Vec2, camera: &Camera, snapshot: &RenderSnapshot) {
+        self.clear();
+        let Some(point) = camera.screen_to_world(pixel) else {
+            return;
+        };
+        let radius = CLICK_RADIUS * camera.world_units_per_pixel();
+        let own = |colony| colony == self.player;
+        let ants = snapshot
+            .ants
+            .iter()
+            .filter(|ant| own(ant.colony))
+            .map(|ant| (ant.entity, ant.position, radius));
+        if let Some(ant) = nearest(ants, point) {
+            self.ants.push(ant);
+            return;
+        }
+        let nests = snapshot
+            .nests
+            .iter()
+            .filter(|nest| own(nest.colony))
+            .map(|nest| (nest.entity, nest.position, radius.max(NEST_RADIUS)));
+        self.nest = nearest(nests, point);
+    }
+
+    fn select_box(&mut self, start: Vec2, end: Vec2, camera: &Camera, snapshot: &RenderSnapshot) {
+        self.clear();
+        let (min, max) = (start.inf(&end), start.sup(&end));
+        self.ants = snapshot
+            .ants
+            .iter()
+            .filter(|ant| ant.colony == self.player)
+            .filter(|ant| {
+                camera.world_to_screen(ant.position).is_some_and(|pixel| {
+                    pixel.x >= min.x && pixel.y >= min.y && pixel.x <= max.x && pixel.y <= max.y
+                })
+            })
+            .map(|ant| ant.entity)
+            .collect();
+        self.ants.sort();
+    }
+
+    /// Rivals under the cursor are attacked, food is gathered, anywhere else
+    /// on the ground is moved to.
+    fn command(
+        &self,
+        pixel: Vec2,
+        camera: &Camera,
+        snapshot: &RenderSnapshot,
+    ) -> Option<CommandKind> {
+        let point = camera.screen_to_world(pixel)?;
+        let radius = CLICK_RADIUS * camera.world_units_per_pixel();
+        let ants = self.ants.clone();
+
+        let rival_ants = snapshot
+            .ants
+            .iter()
+            .filter(|ant| ant.colony != self.player)
+            .map(|ant| (ant.entity, ant.position, radius));
+        let rival_nests = snapshot
+            .nests
+            .iter()
+            .filter(|nest| nest.colony != self.player)
+            .map(|nest| (nest.entity, nest.position, radius.max(NEST_RADIUS)));
+        if let Some(target) = nearest(rival_ants.chain(rival_nests), point) {
+            return Some(CommandKind::Attack { ants, target });
+        }
+        let food = snapshot
+            .food
+            .iter()
+            .map(|food| (food.entity, food.position, radius));
+        if let Some(food) = nearest(food, point) {
+            return Some(CommandKind::Gather { ants, food });
+        }
+        Some(CommandKind::Move {
+            ants,
+            target: FixedVec2::from_glm(&point),
+        })
+    }
+
+    /// Dead ants and lost nests drop out of the selection.
+    fn forget_missing(&mut self, snapshot: &RenderSnapshot) {
+        self.ants.retain(|entity| {
+            snapshot
+                .ants
+                .binary_search_by_key(&entity.index(), |ant| ant.entity.index())
+                .is_ok_and(|i| snapshot.ants[i].entity == *entity)
+        });
+        if let Some(nest) = self.nest {
+            if !snapshot.nests.iter().any(|n| n.entity == nest) {
+                self.nest = None;
+            }
+        }
+    }
+}
+
+/// The closest candidate whose radius covers `point`.
+fn nearest(candidates: impl Iterator<Item = (Entity, Vec2, f32)>, point: Vec2) -> Option<Entity> {
+    candidates
+        .map(|(entity, position, radius)| (entity, distance(&position, &point), radius))
+        .filter(|(_, distance, radius)| distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _, _)| entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::input::{Binding, InputEvent};
+    use crate::renderer::snapshot::{AntSnapshot, FoodSnapshot, NestSnapshot};
+    use crate::shared::ecs::Entities;
+    use nalgebra_glm::vec2;
+    use winit::event::MouseButton;
+
+    /// Own ants at (10, 10) and (12, 10), a rival ant at (30, 30), nests at
+    /// (20, 20) and (50, 50) and food at (40, 10). The camera shows the
+    /// world at 10 pixels per unit.
+    fn world() -> (RenderSnapshot, Camera) {
+        let mut entities = Entities::new();
+        let mut ant = |colony, x, y| AntSnapshot {
+            entity: entities.spawn(),
+            colony,
+            position: vec2(x, y),
+            heading: 0.0,
+            carrying: false,
+            selected: false,
+        };
+        let ants = vec![ant(0, 10.0, 10.0), ant(0, 12.0, 10.0), ant(1, 30.0, 30.0)];
+        let mut nest = |colony, x, y| NestSnapshot {
+            entity: entities.spawn(),
+            colony,
+            position: vec2(x, y),
+            food: 0,
+            selected: false,
+        };
+        let nests = vec![nest(0, 20.0, 20.0), nest(1, 50.0, 50.0)];
+        let food = vec![FoodSnapshot {
+            entity: entities.spawn(),
+            position: vec2(40.0, 10.0),
+            amount: 10,
+        }];
+        let snapshot = RenderSnapshot {
+            world_size: vec2(64.0, 64.0),
+            ants,
+            nests,
+            food,
+            ..RenderSnapshot::default()
+        };
+        let camera = Camera::new(snapshot.world_size, [640, 640]);
+        (snapshot, camera)
+    }
+
+    fn pixel(camera: &Camera, x: f32, y: f32) -> [f32; 2] {
+        camera.world_to_screen(vec2(x, y)).unwrap().into()
+    }
+
+    /// Presses and releases `button`, moving from `from` to `to` in between.
+    fn click(
+        selection: &mut Selection,
+        input: &mut InputState,
+        button: MouseButton,
+        from: [f32; 2],
+        to: [f32; 2],
+        world: &(RenderSnapshot, Camera),
+    ) -> Option<CommandKind> {
+        let (snapshot, camera) = world;
+        input.handle(InputEvent::Cursor(from));
+        input.handle(InputEvent::Pressed(Binding::Mouse(button)));
+        let command = selection.update(input, camera, snapshot);
+        input.end_frame();
+        input.handle(InputEvent::Cursor(to));
+        input.handle(InputEvent::Released(Binding::Mouse(button)));
+        selection.update(input, camera, snapshot);
+        input.end_frame();
+        command
+    }
+
+    #[test]
+    fn test_click_and_box_select() {
+        let world = world();
+        let (snapshot, camera) = &world;
+        let mut selection = Selection::new(0);
+        let mut input = InputState::default();
+        let left = MouseButton::Left;
+
+        let ant = pixel(camera, 12.2, 10.0);
+        click(&mut selection, &mut input, left, ant, ant, &world);
+        assert_eq!(selection.ants(), &[snapshot.ants[1].entity]);
+
+        // rival ants can't be selected, clicking them clears the selection
+        let rival = pixel(camera, 30.0, 30.0);
+        click(&mut selection, &mut input, left, rival, rival, &world);
+        assert!(selection.ants().is_empty());
+
+        let nest = pixel(camera, 21.0, 21.0);
+        click(&mut selection, &mut input, left, nest, nest, &world);
+        assert_eq!(selection.nest(), Some(snapshot.nests[0].entity));
+        assert!(selection.ants().is_empty());
+
+        let (from, to) = (pixel(camera, 40.0, 40.0), pixel(camera, 5.0, 5.0));
+        click(&mut selection, &mut input, left, from, to, &world);
+        let own: Vec<Entity> = snapshot.ants[..2].iter().map(|ant| ant.entity).collect();
+        assert_eq!(selection.ants(), own.as_slice());
+        assert_eq!(selection.nest(), None);
+
+        let mut marked = snapshot.clone();
+        selection.mark(&mut marked);
+        let flags: Vec<bool> = marked.ants.iter().map(|ant| ant.selected).collect();
+        assert_eq!(flags, [true, true, false]);
+        assert!(marked.nests.iter().all(|nest| !nest.selected));
+
+        let mut fewer = snapshot.clone();
+        fewer.ants.remove(0);
+        selection.update(&input, camera, &fewer);
+        assert_eq!(selection.ants(), &own[1..]);
+    }
+
+    #[test]
+    fn test_commands() {
+        let world = world();
+        let (snapshot, camera) = &world;
+        let mut selection = Selection::new(0);
+        let mut input = InputState::default();
+        let right = MouseButton::Right;
+
+        let ground = pixel(camera, 30.0, 5.0);
+        let command = click(&mut selection, &mut input, right, ground, ground, &world);
+        assert_eq!(command, None);
+
+        let (from, to) = (pixel(camera, 5.0, 5.0), pixel(camera, 15.0, 15.0));
+        click(
+            &mut selection,
+            &mut input,
+            MouseButton::Left,
+            from,
+            to,
+            &world,
+        );
+        let ants = selection.ants().to_vec();
+        assert_eq!(ants.len(), 2);
+
+        match click(&mut selection, &mut input, right, ground, ground, &world) {
+            Some(CommandKind::Move {
+                ants: moved,
+                target,
+            }) => {
+                assert_eq!(moved, ants);
+                assert!((target.to_glm() - vec2(30.0, 5.0)).norm() < 0.1);
+            }
+            other => panic!("expected a move, got {:?}", other),
+        }
+        let food = pixel(camera, 40.0, 10.0);
+        assert_eq!(
+            click(&mut selection, &mut input, right, food, food, &world),
+            Some(CommandKind::Gather {
+                ants: ants.clone(),
+                food: snapshot.food[0].entity,
+            })
+        );
+        let nest = pixel(camera, 51.0, 50.0);
+        assert_eq!(
+            click(&mut selection, &mut input, right, nest, nest, &world),
+            Some(CommandKind::Attack {
+                ants,
+                target: snapshot.nests[1].entity,
+            })
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Immediate-mode UI with egui, drawn by the renderer over the world.
+//!
+//! [`Ui`] lives on the render thread. It is fed the same [`InputEvent`]s as
+//! the [`InputState`](crate::client::input::InputState), translated into
+//! egui's input, and once per frame runs the screens that want to show
+//! something, producing a [`UiFrame`] for the renderer.
+
+use crate::client::input::{Binding, InputEvent};
+use crate::renderer::UiFrame;
+use egui::{Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect};
+use std::collections::HashSet;
+use std::time::Instant;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+/// Scroll distance of a wheel line, as egui-winit uses it.
+const POINTS_PER_LINE: f32 = 50.0;
+
+pub struct Ui {
+    context: egui::Context,
+    /// Input since the last frame.
+    events: Vec<Event>,
+    /// Modifier keys that are down.
+    modifiers: HashSet<VirtualKeyCode>,
+    /// In points.
+    pointer: Option<Pos2>,
+    pixels_per_point: f32,
+    focused: bool,
+    started: Instant,
+}
+
+impl Ui {
+    pub fn new(pixels_per_point: f32) -> Ui {
+        Ui {
+            context: egui::Context::default(),
+            events: Vec::new(),
+            modifiers: HashSet::new(),
+            pointer: None,
+            pixels_per_point,
+            focused: true,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        &self.context
+    }
+
+    /// The window's scale factor changed.
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: f32) {
+        self.pixels_per_point = pixels_per_point;
+    }
+
+    pub fn handle(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(binding) => self.binding(binding, true),
+            InputEvent::Released(binding) => self.binding(binding, false),
+            InputEvent::Cursor([x, y]) => {
+                let position = Pos2::new(x / self.pixels_per_point, y / self.pixels_per_point);
+                self.pointer = Some(position);
+                self.focused = true;
+                self.events.push(Event::PointerMoved(position));
+            }
+            InputEvent::CursorLeft => {
+                self.pointer = None;
+                self.events.push(Event::PointerGone);
+            }
+            InputEvent::Scroll(lines) => self
+                .events
+                .push(Event::Scroll(egui::vec2(0.0, lines * POINTS_PER_LINE))),
+            InputEvent::FocusLost => {
+                self.modifiers.clear();
+                self.focused = false;
+                self.events.push(Event::WindowFocused(false));
+            }
+            InputEvent::Text(c) => self.events.push(Event::Text(c.to_string())),
+        }
+    }
+
+    fn binding(&mut self, binding: Binding, pressed: bool) {
+        let modifiers = self.modifiers();
+        match binding {
+            Binding::Key(key) => {
+                if is_modifier(key) {
+                    if pressed {
+                        self.modifiers.insert(key);
+                    } else {
+                        self.modifiers.remove(&key);
+                    }
+                }
+                if let Some(key) = egui_key(key) {
+                    self.events.push(Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        modifiers,
+                    });
+                }
+            }
+            Binding::Mouse(button) => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return,
+                };
+                if let Some(pos) = self.pointer {
+                    self.events.push(Event::PointerButton {
+                        pos,
+                        button,
+                        pressed,
+                        modifiers,
+                    });
+                }
+            }
+        }
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        let held = |keys: [VirtualKeyCode; 2]| keys.iter().any(|key| self.modifiers.contains(key));
+        let ctrl = held([VirtualKeyCode::LControl, VirtualKeyCode::RControl]);
+        Modifiers {
+            alt: held([VirtualKeyCode::LAlt, VirtualKeyCode::RAlt]),
+            ctrl,
+            shift: held([VirtualKeyCode::LShift, VirtualKeyCode::RShift]),
+            mac_cmd: false,
+            command: ctrl,
+        }
+    }
+
+    /// Runs `show` for a window of `extent` pixels and returns what it drew.
+    pub fn run(&mut self, extent: [u32; 2], show: impl FnOnce(&egui::Context)) -> UiFrame {
+        let size = egui::vec2(extent[0] as f32, extent[1] as f32) / self.pixels_per_point;
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: Some(self.started.elapsed().as_secs_f64()),
+            modifiers: self.modifiers(),
+            events: std::mem::take(&mut self.events),
+            focused: self.focused,
+            ..Default::default()
+        };
+        let output = self.context.run(input, show);
+        UiFrame {
+            primitives: self.context.tessellate(output.shapes),
+            textures: output.textures_delta,
+            pixels_per_point: self.pixels_per_point,
+        }
+    }
+
+    /// Whether the pointer is over the UI, so clicks are not meant for the
+    /// world.
+    pub fn wants_pointer(&self) -> bool {
+        self.context.wants_pointer_input() || self.context.is_pointer_over_area()
+    }
+
+    /// Whether a text field has focus, so keys are not meant for the world.
+    pub fn wants_keyboard(&self) -> bool {
+        self.context.wants_keyboard_input()
+    }
+}
+
+fn is_modifier(key: VirtualKeyCode) -> bool {
+    use VirtualKeyCode::*;
+    matches!(key, LShift | RShift | LControl | RControl | LAlt | RAlt)
+}
+
+/// The keys egui's widgets react to.
+fn egui_key(key: VirtualKeyCode) -> Option<Key> {
+    use VirtualKeyCode as V;
+    Some(match key {
+        V::Up => Key::ArrowUp,
+        V::Down => Key::ArrowDown,
+        V::Left => Key::ArrowLeft,
+        V::Right => Key::ArrowRight,
+        V::Escape => Key::Escape,
+        V::Tab => Key::Tab,
+        V::Back => Key::Backspace,
+        V::Return | V::NumpadEnter => Key::Enter,
+        V::Space => Key::Space,
+        V::Insert => Key::Insert,
+        V::Delete => Key::Delete,
+        V::Home => Key::Home,
+        V::End => Key::End,
+        V::PageUp => Key::PageUp,
+        V::PageDown => Key::PageDown,
+        V::A => Key::A,
+        V::C => Key::C,
+        V::K => Key::K,
+        V::U => Key::U,
+        V::V => Key::V,
+        V::W => Key::W,
+        V::X => Key::X,
+        V::Y => Key::Y,
+        V::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_input() {
+        let mut ui = Ui::new(2.0);
+        ui.handle(InputEvent::Pressed(Binding::Mouse(MouseButton::Left)));
+        assert!(ui.events.is_empty(), "clicks need a pointer position");
+        ui.handle(InputEvent::Cursor([20.0, 40.0]));
+        ui.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::LShift)));
+        ui.handle(InputEvent::Pressed(Binding::Mouse(MouseButton::Left)));
+        ui.handle(InputEvent::Text('a'));
+        ui.handle(InputEvent::Pressed(Binding::Key(VirtualKeyCode::Back)));
+        assert_eq!(
+            ui.events,
+            vec![
+                Event::PointerMoved(Pos2::new(10.0, 20.0)),
+                Event::PointerButton {
+                    pos: Pos2::new(10.0, 20.0),
+                    button: PointerButton::Primary,
+                    pressed: true,
+                    modifiers: Modifiers::SHIFT,
+                },
+                Event::Text("a".into()),
+                Event::Key {
+                    key: Key::Backspace,
+                    pressed: true,
+                    repeat: false,
+                    modifiers: Modifiers::SHIFT,
+                },
+            ]
+        );
+        ui.handle(InputEvent::FocusLost);
+        assert_eq!(ui.modifiers(), Modifiers::NONE);
+    }
+
+    #[test]
+    fn test_run_draws_and_takes_pointer() {
+        let mut ui = Ui::new(1.0);
+        ui.handle(InputEvent::Cursor([20.0, 20.0]));
+        let show = |context: &egui::Context| {
+            egui::Window::new("test")
+                .fixed_pos(Pos2::ZERO)
+                .show(context, |ui| ui.label("hello"));
+        };
+        // the font atlas comes with the first frame, windows are only sized
+        // in it
+        let frame = ui.run([800, 600], show);
+        assert!(!frame.textures.set.is_empty());
+        let frame = ui.run([800, 600], show);
+        assert!(!frame.primitives.is_empty());
+        assert!(frame.textures.set.is_empty());
+        assert!(ui.wants_pointer());
+
+        ui.handle(InputEvent::Cursor([700.0, 500.0]));
+        ui.run([800, 600], show);
+        assert!(!ui.wants_pointer());
+    }
+}
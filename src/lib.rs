@@ -1,4 +1,5 @@
 pub mod server;
 pub mod client;
+pub mod renderer;
 pub mod utils;
-mod shared;
+pub mod shared;
@@ -0,0 +1,188 @@
+//! Per-instance data for drawing every ant and nest with a single instanced
+//! draw.
+
+use crate::renderer::snapshot::{AntSnapshot, NestSnapshot, RenderSnapshot};
+use crate::renderer::sprites::EntitySprites;
+use crate::shared::protocols::PlayerId;
+use vulkano::buffer::BufferContents;
+use vulkano::pipeline::graphics::vertex_input::Vertex;
+
+/// Colony colors, repeated for larger matches.
+pub const COLONY_COLORS: [[f32; 4]; 8] = [
+    [0.85, 0.20, 0.15, 1.0],
+    [0.20, 0.45, 0.90, 1.0],
+    [0.95, 0.80, 0.20, 1.0],
+    [0.35, 0.75, 0.30, 1.0],
+    [0.70, 0.35, 0.85, 1.0],
+    [0.95, 0.55, 0.15, 1.0],
+    [0.25, 0.80, 0.80, 1.0],
+    [0.90, 0.45, 0.65, 1.0],
+];
+/// How far carrying ants are blended towards white.
+const CARRYING_HIGHLIGHT: f32 = 0.4;
+/// How far selected ants and nests are blended towards white.
+const SELECTED_HIGHLIGHT: f32 = 0.7;
+/// Selected ants and nests are drawn this much larger.
+const SELECTED_SCALE: f32 = 1.5;
+/// Nests use the ant mesh at this size, pointing north.
+const NEST_SCALE: f32 = 4.0;
+const NEST_HEADING: f32 = -std::f32::consts::FRAC_PI_2;
+
+/// A vertex of the ant mesh, in world units around the ant's center and
+/// pointing along +x.
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+pub(super) struct AntVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+}
+
+pub(super) const ANT_MESH: [AntVertex; 3] = [
+    AntVertex {
+        position: [0.4, 0.0],
+    },
+    AntVertex {
+        position: [-0.4, 0.25],
+    },
+    AntVertex {
+        position: [-0.4, -0.25],
+    },
+];
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub(super) struct AntInstance {
+    #[format(R32G32_SFLOAT)]
+    offset: [f32; 2],
+    #[format(R32_SFLOAT)]
+    heading: f32,
+    #[format(R32_SFLOAT)]
+    scale: f32,
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+    #[format(R32_UINT)]
+    colony: u32,
+}
+
+impl AntInstance {
+    fn ant(ant: &AntSnapshot) -> AntInstance {
+        let mut color = colony_color(ant.colony);
+        if ant.carrying {
+            lighten(&mut color, CARRYING_HIGHLIGHT);
+        }
+        AntInstance::new(
+            ant.position.into(),
+            ant.heading,
+            1.0,
+            color,
+            ant.colony,
+            ant.selected,
+        )
+    }
+
+    fn nest(nest: &NestSnapshot) -> AntInstance {
+        let color = colony_color(nest.colony);
+        AntInstance::new(
+            nest.position.into(),
+            NEST_HEADING,
+            NEST_SCALE,
+            color,
+            nest.colony,
+            nest.selected,
+        )
+    }
+
+    fn new(
+        offset: [f32; 2],
+        heading: f32,
+        mut scale: f32,
+        mut color: [f32; 4],
+        colony: PlayerId,
+        selected: bool,
+    ) -> AntInstance {
+        if selected {
+            lighten(&mut color, SELECTED_HIGHLIGHT);
+            scale *= SELECTED_SCALE;
+        }
+        AntInstance {
+            offset,
+            heading,
+            scale,
+            color,
+            colony: colony as u32,
+        }
+    }
+}
+
+fn lighten(color: &mut [f32; 4], amount: f32) {
+    for channel in &mut color[..3] {
+        *channel += (1.0 - *channel) * amount;
+    }
+}
+
+pub fn colony_color(colony: PlayerId) -> [f32; 4] {
+    COLONY_COLORS[colony as usize % COLONY_COLORS.len()]
+}
+
+/// Nests come first so ants walking over them stay visible. Kinds drawn as
+/// sprites are left out.
+pub(super) fn instances(snapshot: &RenderSnapshot, sprites: &EntitySprites) -> Vec<AntInstance> {
+    let mut instances = Vec::new();
+    if sprites.nest.is_none() {
+        instances.extend(snapshot.nests.iter().map(AntInstance::nest));
+    }
+    if sprites.ant.is_none() {
+        instances.extend(snapshot.ants.iter().map(AntInstance::ant));
+    }
+    instances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ecs::Entities;
+    use nalgebra_glm::vec2;
+
+    #[test]
+    fn test_instances() {
+        let entity = Entities::new().spawn();
+        let ant = |colony, carrying, selected| AntSnapshot {
+            entity,
+            colony,
+            position: vec2(3.0, 4.0),
+            heading: 1.5,
+            carrying,
+            selected,
+        };
+        let nest = NestSnapshot {
+            entity,
+            colony: 2,
+            position: vec2(8.0, 8.0),
+            food: 0,
+            selected: true,
+        };
+        let snapshot = RenderSnapshot {
+            ants: vec![
+                ant(1, false, false),
+                ant(9, true, false),
+                ant(1, false, true),
+            ],
+            nests: vec![nest],
+            ..RenderSnapshot::default()
+        };
+        let instances = instances(&snapshot, &EntitySprites::default());
+        assert_eq!(instances.len(), 4);
+        assert_eq!(instances[0].scale, NEST_SCALE * SELECTED_SCALE);
+        assert_eq!(instances[0].offset, [8.0, 8.0]);
+        assert_eq!(instances[1].offset, [3.0, 4.0]);
+        assert_eq!(instances[1].heading, 1.5);
+        assert_eq!(instances[1].scale, 1.0);
+        assert_eq!(instances[1].color, COLONY_COLORS[1]);
+        assert_eq!(instances[2].colony, 9);
+        // colony 9 wraps around to the second color, lightened
+        assert!(instances[2].color[0] > COLONY_COLORS[1][0]);
+        assert_eq!(instances[2].color[3], 1.0);
+        assert!(instances[3].color[0] > instances[2].color[0]);
+        assert_eq!(instances[3].scale, SELECTED_SCALE);
+    }
+}
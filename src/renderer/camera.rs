@@ -0,0 +1,235 @@
+//! Perspective camera looking down at the ground plane.
+//!
+//! The world lies in the z = 0 plane with x to the east and y to the south,
+//! like the rows of a map, so "up" towards the camera is -z. The camera
+//! orbits a target point on the ground: `yaw` turns it around the vertical
+//! axis and `pitch` tilts it from the horizon down to straight top-down.
+
+use nalgebra_glm::{inverse, look_at_rh, perspective_rh_zo, vec2, vec3, vec4, Mat4, Vec2, Vec3};
+use std::f32::consts::FRAC_PI_2;
+
+const FIELD_OF_VIEW: f32 = FRAC_PI_2 * 0.5;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1000.0;
+/// Closest zoom, in world units from the target.
+pub const MIN_DISTANCE: f32 = 4.0;
+/// Flattest tilt, keeps the horizon out of view.
+pub const MIN_PITCH: f32 = 0.6;
+/// How much one step of the scroll wheel zooms.
+const ZOOM_STEP: f32 = 1.15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    target: Vec2,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    world_size: Vec2,
+    max_distance: f32,
+    extent: [u32; 2],
+}
+
+impl Camera {
+    /// A top-down camera showing the whole world in a viewport of `extent`
+    /// pixels.
+    pub fn new(world_size: Vec2, extent: [u32; 2]) -> Camera {
+        let aspect = extent[0].max(1) as f32 / extent[1].max(1) as f32;
+        let half_height = (world_size.y / 2.0).max(world_size.x / 2.0 / aspect);
+        let distance = (half_height / (FIELD_OF_VIEW / 2.0).tan()).max(MIN_DISTANCE);
+        Camera {
+            target: world_size / 2.0,
+            distance,
+            yaw: 0.0,
+            pitch: FRAC_PI_2,
+            world_size,
+            max_distance: distance * 2.0,
+            extent,
+        }
+    }
+
+    pub fn target(&self) -> Vec2 {
+        self.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// Call on every resize, the aspect ratio follows the viewport.
+    pub fn set_extent(&mut self, extent: [u32; 2]) {
+        self.extent = extent;
+    }
+
+    /// Moves the target by `delta` world units along the screen axes, x to
+    /// the right and y down. The target stays inside the world.
+    pub fn pan(&mut self, delta: Vec2) {
+        let (right, down) = self.ground_axes();
+        self.target += right * delta.x + down * delta.y;
+        self.target = self.target.sup(&vec2(0.0, 0.0)).inf(&self.world_size);
+    }
+
+    /// Moves the target so the ground follows a cursor moved by `delta`
+    /// pixels.
+    pub fn drag(&mut self, delta: Vec2) {
+        self.pan(-delta * self.world_units_per_pixel());
+    }
+
+    /// Positive steps zoom in.
+    pub fn zoom(&mut self, steps: f32) {
+        self.distance =
+            (self.distance / ZOOM_STEP.powf(steps)).clamp(MIN_DISTANCE, self.max_distance);
+    }
+
+    /// Turns around the target by `yaw` and tilts by `pitch`, in radians.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw).rem_euclid(std::f32::consts::TAU);
+        self.pitch = (self.pitch + pitch).clamp(MIN_PITCH, FRAC_PI_2);
+    }
+
+    /// Size of a pixel on the ground at the target.
+    pub fn world_units_per_pixel(&self) -> f32 {
+        2.0 * self.distance * (FIELD_OF_VIEW / 2.0).tan() / self.extent[1].max(1) as f32
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        let (_, down) = self.ground_axes();
+        let back = vec3(down.x, down.y, 0.0) * self.pitch.cos();
+        let up = vec3(0.0, 0.0, -self.pitch.sin());
+        vec3(self.target.x, self.target.y, 0.0) + (back + up) * self.distance
+    }
+
+    pub fn view(&self) -> Mat4 {
+        let (_, down) = self.ground_axes();
+        let target = vec3(self.target.x, self.target.y, 0.0);
+        // screen up is away from the camera along the ground, which is
+        // never parallel to the view direction since the pitch is limited
+        look_at_rh(&self.eye(), &target, &vec3(-down.x, -down.y, 0.0))
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        let aspect = self.extent[0].max(1) as f32 / self.extent[1].max(1) as f32;
+        let mut projection = perspective_rh_zo(aspect, FIELD_OF_VIEW, NEAR, FAR);
+        // Vulkan's clip space y points down
+        projection[(1, 1)] *= -1.0;
+        projection
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+
+    /// The point on the ground under `pixel`, if the ray hits the ground.
+    pub fn screen_to_world(&self, pixel: Vec2) -> Option<Vec2> {
+        let extent = vec2(self.extent[0].max(1) as f32, self.extent[1].max(1) as f32);
+        let ndc = vec2(pixel.x / extent.x, pixel.y / extent.y) * 2.0 - vec2(1.0, 1.0);
+        let inverse = inverse(&self.view_projection());
+        let unproject = |depth: f32| {
+            let point = inverse * vec4(ndc.x, ndc.y, depth, 1.0);
+            point.xyz() / point.w
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        let direction = far - near;
+        if direction.z.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = -near.z / direction.z;
+        (t >= 0.0).then(|| (near + direction * t).xy())
+    }
+
+    /// The pixel a point on the ground is drawn at, `None` behind the
+    /// camera.
+    pub fn world_to_screen(&self, point: Vec2) -> Option<Vec2> {
+        let clip = self.view_projection() * vec4(point.x, point.y, 0.0, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.xy() / clip.w;
+        let extent = vec2(self.extent[0] as f32, self.extent[1] as f32);
+        Some((ndc + vec2(1.0, 1.0)).component_mul(&extent) / 2.0)
+    }
+
+    /// Unit vectors on the ground pointing right and down on screen.
+    fn ground_axes(&self) -> (Vec2, Vec2) {
+        let (sin, cos) = self.yaw.sin_cos();
+        (vec2(cos, -sin), vec2(sin, cos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(vec2(64.0, 64.0), [800, 600])
+    }
+
+    fn project(camera: &Camera, point: Vec2) -> Vec2 {
+        let clip = camera.view_projection() * vec4(point.x, point.y, 0.0, 1.0);
+        clip.xy() / clip.w
+    }
+
+    #[test]
+    fn test_top_down_orientation() {
+        let camera = camera();
+        assert!(project(&camera, vec2(32.0, 32.0)).norm() < 1e-4);
+        // north is up on screen, which is -y in Vulkan clip space
+        let north = project(&camera, vec2(32.0, 0.0));
+        assert!(north.x.abs() < 1e-4 && north.y < -0.5);
+        let east = project(&camera, vec2(64.0, 32.0));
+        assert!(east.x > 0.5 && east.y.abs() < 1e-4);
+        // the whole world fits
+        assert!(north.y >= -1.0 && east.x <= 1.0);
+    }
+
+    #[test]
+    fn test_screen_to_world() {
+        let mut camera = camera();
+        camera.orbit(0.7, -0.5);
+        camera.zoom(2.0);
+        let center = camera.screen_to_world(vec2(400.0, 300.0)).unwrap();
+        assert!((center - camera.target()).norm() < 1e-2);
+        let point = vec2(30.0, 35.0);
+        let clip = project(&camera, point);
+        let pixel = vec2((clip.x + 1.0) * 400.0, (clip.y + 1.0) * 300.0);
+        assert!((camera.world_to_screen(point).unwrap() - pixel).norm() < 1e-3);
+        assert!((camera.screen_to_world(pixel).unwrap() - point).norm() < 1e-2);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut camera = camera();
+        let fit = camera.distance();
+        camera.zoom(-100.0);
+        assert_eq!(camera.distance(), fit * 2.0);
+        camera.zoom(100.0);
+        assert_eq!(camera.distance(), MIN_DISTANCE);
+        camera.orbit(0.0, -10.0);
+        assert_eq!(camera.pitch(), MIN_PITCH);
+        camera.pan(vec2(-1000.0, 0.0));
+        assert_eq!(camera.target().x, 0.0);
+    }
+
+    #[test]
+    fn test_pan_follows_yaw() {
+        let mut camera = camera();
+        camera.orbit(FRAC_PI_2, 0.0);
+        let before = project(&camera, vec2(20.0, 20.0));
+        camera.pan(vec2(1.0, 0.0));
+        let after = project(&camera, vec2(20.0, 20.0));
+        // panning right moves the world left on screen
+        assert!(after.x < before.x);
+        assert!((after.y - before.y).abs() < 1e-4);
+    }
+}
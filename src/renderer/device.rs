@@ -0,0 +1,313 @@
+//! Instance and device creation, and which GPU and queues are used.
+
+use crate::renderer::RendererError;
+use log::{info, warn};
+use smallvec::SmallVec;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::device::{
+    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+};
+use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
+use vulkano::swapchain::Surface;
+use vulkano::sync::Sharing;
+use vulkano::{Version, VulkanLibrary};
+
+/// Creates a Vulkan instance with `extensions` enabled, usually
+/// `Surface::required_extensions(&event_loop)`.
+pub fn create_instance(
+    application_name: &str,
+    extensions: InstanceExtensions,
+) -> Result<Arc<Instance>, RendererError> {
+    let library = VulkanLibrary::new()?;
+    Ok(Instance::new(
+        library,
+        InstanceCreateInfo {
+            enabled_extensions: extensions,
+            application_name: Some(application_name.into()),
+            engine_name: Some("Ant Engine".into()),
+            engine_version: 0.into(),
+            max_api_version: Some(Version::V1_1),
+            ..Default::default()
+        },
+    )?)
+}
+
+/// A GPU forced with `render.device`, by its index in the list of devices
+/// the renderer logs or by a case-insensitive part of its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl DeviceSelector {
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            DeviceSelector::Index(selected) => *selected == index,
+            DeviceSelector::Name(part) => name.to_lowercase().contains(&part.to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<DeviceSelector, Infallible> {
+        Ok(match value.parse() {
+            Ok(index) => DeviceSelector::Index(index),
+            Err(_) => DeviceSelector::Name(value.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceSelector::Index(index) => write!(f, "device {}", index),
+            DeviceSelector::Name(name) => write!(f, "{:?}", name),
+        }
+    }
+}
+
+/// The queue families frames are drawn, presented and uploaded on, which
+/// may all be the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct QueueFamilies {
+    graphics: u32,
+    present: u32,
+    transfer: u32,
+}
+
+/// Prefers a graphics family that can also present, and for uploads a
+/// transfer family that does nothing else, as DMA engines do. `None` if no
+/// family can draw or none can present.
+fn choose_queue_families(
+    flags: &[QueueFlags],
+    presents: impl Fn(u32) -> bool,
+) -> Option<QueueFamilies> {
+    let families = || (0..flags.len() as u32).zip(flags.iter().copied());
+    let graphics: Vec<u32> = families()
+        .filter(|(_, flags)| flags.intersects(QueueFlags::GRAPHICS))
+        .map(|(i, _)| i)
+        .collect();
+    let present = families().map(|(i, _)| i).filter(|&i| presents(i));
+    let (graphics, present) = match graphics.iter().copied().find(|&i| presents(i)) {
+        Some(family) => (family, family),
+        None => (*graphics.first()?, present.min()?),
+    };
+    let transfer_only = |other: QueueFlags| {
+        families()
+            .find(|(_, flags)| flags.intersects(QueueFlags::TRANSFER) && !flags.intersects(other))
+            .map(|(i, _)| i)
+    };
+    let transfer = transfer_only(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+        .or_else(|| transfer_only(QueueFlags::GRAPHICS))
+        .unwrap_or(graphics);
+    Some(QueueFamilies {
+        graphics,
+        present,
+        transfer,
+    })
+}
+
+/// Picks a device with queues that can draw and present to `surface`, if
+/// any. The one `selector` names if it is suitable, otherwise discrete GPUs
+/// are preferred.
+pub(super) fn select_physical_device(
+    instance: &Arc<Instance>,
+    surface: Option<&Surface>,
+    extensions: &DeviceExtensions,
+    selector: Option<&DeviceSelector>,
+) -> Result<(Arc<PhysicalDevice>, QueueFamilies), RendererError> {
+    let mut suitable = Vec::new();
+    for (index, p) in instance.enumerate_physical_devices()?.enumerate() {
+        let properties = p.properties();
+        info!(
+            "Found device {}: {} ({:?})",
+            index, properties.device_name, properties.device_type
+        );
+        if !p.supported_extensions().contains(extensions) {
+            continue;
+        }
+        let flags: Vec<QueueFlags> = p
+            .queue_family_properties()
+            .iter()
+            .map(|q| q.queue_flags)
+            .collect();
+        let families = choose_queue_families(&flags, |i| {
+            surface.is_none_or(|surface| p.surface_support(i, surface).unwrap_or(false))
+        });
+        if let Some(families) = families {
+            suitable.push((index, p, families));
+        }
+    }
+
+    let selected = selector.and_then(|selector| {
+        let position = suitable
+            .iter()
+            .position(|(index, p, _)| selector.matches(*index, &p.properties().device_name));
+        if position.is_none() {
+            warn!(
+                "No suitable device matches {}, choosing one instead",
+                selector
+            );
+        }
+        position
+    });
+    let position = selected
+        .or_else(|| {
+            (0..suitable.len())
+                .min_by_key(|&i| device_type_rank(suitable[i].1.properties().device_type))
+        })
+        .ok_or(RendererError::NoSuitableDevice)?;
+    let (index, physical_device, families) = suitable.swap_remove(position);
+    let properties = physical_device.properties();
+    info!(
+        "Using device {}: {} ({:?}, Vulkan {}), queue families {} for graphics, {} for \
+         presenting and {} for transfers",
+        index,
+        properties.device_name,
+        properties.device_type,
+        properties.api_version,
+        families.graphics,
+        families.present,
+        families.transfer
+    );
+    Ok((physical_device, families))
+}
+
+fn device_type_rank(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        PhysicalDeviceType::Other => 4,
+        _ => 5,
+    }
+}
+
+/// How a resource used by two queues is shared between their families.
+pub(super) type QueueSharing = Sharing<SmallVec<[u32; 4]>>;
+
+/// One queue of each of the [`QueueFamilies`], the same queue where the
+/// families are.
+pub(super) struct Queues {
+    pub(super) graphics: Arc<Queue>,
+    pub(super) present: Arc<Queue>,
+    pub(super) transfer: Arc<Queue>,
+}
+
+impl Queues {
+    /// For images written by uploads and sampled while drawing.
+    pub(super) fn upload_sharing(&self) -> QueueSharing {
+        sharing(&self.graphics, &self.transfer)
+    }
+
+    /// For swapchain images drawn to and presented.
+    pub(super) fn swapchain_sharing(&self) -> QueueSharing {
+        sharing(&self.graphics, &self.present)
+    }
+}
+
+fn sharing(first: &Queue, second: &Queue) -> QueueSharing {
+    let families = [first.queue_family_index(), second.queue_family_index()];
+    if families[0] == families[1] {
+        Sharing::Exclusive
+    } else {
+        Sharing::Concurrent(families.into_iter().collect())
+    }
+}
+
+pub(super) fn create_device(
+    physical_device: Arc<PhysicalDevice>,
+    families: QueueFamilies,
+    extensions: DeviceExtensions,
+) -> Result<(Arc<Device>, Queues), RendererError> {
+    let mut unique = vec![families.graphics, families.present, families.transfer];
+    unique.sort_unstable();
+    unique.dedup();
+    let (device, queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            enabled_extensions: extensions,
+            queue_create_infos: unique
+                .iter()
+                .map(|&queue_family_index| QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        },
+    )?;
+    // one queue was requested per family
+    let queues: Vec<Arc<Queue>> = queues.collect();
+    let queue = |family: u32| {
+        queues
+            .iter()
+            .find(|queue| queue.queue_family_index() == family)
+            .unwrap()
+            .clone()
+    };
+    let queues = Queues {
+        graphics: queue(families.graphics),
+        present: queue(families.present),
+        transfer: queue(families.transfer),
+    };
+    Ok((device, queues))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_selector() {
+        assert_eq!("1".parse(), Ok(DeviceSelector::Index(1)));
+        let selector: DeviceSelector = "radeon".parse().unwrap();
+        assert_eq!(selector, DeviceSelector::Name("radeon".to_string()));
+        assert!(selector.matches(0, "AMD Radeon RX 6600"));
+        assert!(!selector.matches(0, "llvmpipe (LLVM 15.0.7, 256 bits)"));
+        assert!(DeviceSelector::Index(1).matches(1, "llvmpipe"));
+    }
+
+    #[test]
+    fn test_choose_queue_families() {
+        let all = QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+        let compute = QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+        let families = |graphics, present, transfer| QueueFamilies {
+            graphics,
+            present,
+            transfer,
+        };
+
+        assert_eq!(
+            choose_queue_families(&[all], |_| true),
+            Some(families(0, 0, 0))
+        );
+        assert_eq!(
+            choose_queue_families(&[all, compute, QueueFlags::TRANSFER], |_| true),
+            Some(families(0, 0, 2))
+        );
+        assert_eq!(
+            choose_queue_families(&[all, compute], |_| true),
+            Some(families(0, 0, 1))
+        );
+        // a graphics family that presents is preferred over separate ones
+        assert_eq!(
+            choose_queue_families(&[all, all, compute], |i| i > 0),
+            Some(families(1, 1, 2))
+        );
+        assert_eq!(
+            choose_queue_families(&[all, compute], |i| i == 1),
+            Some(families(0, 1, 1))
+        );
+        assert_eq!(choose_queue_families(&[all], |_| false), None);
+        assert_eq!(choose_queue_families(&[compute], |_| true), None);
+    }
+}
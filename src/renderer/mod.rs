@@ -0,0 +1,929 @@
+//! Vulkan renderer usable by any binary built on the engine.
+//!
+//! The caller owns the window and event loop, creates an instance with
+//! [`create_instance`] and a surface for its window, and hands the surface
+//! to [`Renderer::new`]. After that the renderer only needs to be told about
+//! resizes and asked to draw frames of a [`snapshot::RenderSnapshot`], which
+//! the caller builds from its game state. The last [`Label`]s handed to
+//! [`Renderer::set_labels`] and the last [`UiFrame`] handed to
+//! [`Renderer::set_ui`] are drawn over the world in every frame.
+//!
+//! Without a window, [`Renderer::headless`] draws into an image instead and
+//! [`Renderer::capture`] reads it back, which needs no display and works on
+//! CPU implementations such as lavapipe.
+//!
+//! A [`Renderer`] keeps the future of the last submitted frame, which is not
+//! `Send`, so it has to be created on the thread that draws with it.
+
+pub mod ants;
+pub mod camera;
+mod device;
+mod offscreen;
+mod pheromones;
+mod pipeline;
+#[cfg(feature = "shader-reload")]
+mod reload;
+pub mod snapshot;
+mod sprites;
+mod swapchain;
+mod text;
+mod texture;
+mod ui;
+
+pub use device::{create_instance, DeviceSelector};
+pub use offscreen::Screenshot;
+pub use pheromones::{ChannelStyle, ColorRamp, PheromoneLayer};
+pub use sprites::{SpriteAtlas, SpriteRegion, ANT_SPRITE, FOOD_SPRITE, NEST_SPRITE};
+pub use swapchain::PresentMode;
+pub use text::{Font, Label, LabelSpace};
+pub use texture::Texture;
+pub use ui::UiFrame;
+
+use crate::renderer::ants::{AntInstance, AntVertex};
+use crate::renderer::camera::Camera;
+use crate::renderer::device::Queues;
+use crate::renderer::offscreen::OffscreenTarget;
+use crate::renderer::pheromones::PheromoneRenderer;
+use crate::renderer::pipeline::CameraUniform;
+#[cfg(feature = "shader-reload")]
+use crate::renderer::pipeline::ShaderStages;
+#[cfg(feature = "shader-reload")]
+use crate::renderer::reload::{check_compatible, PipelineKind, ShaderReloader};
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::renderer::sprites::SpriteRenderer;
+use crate::renderer::text::TextRenderer;
+use crate::renderer::ui::UiRenderer;
+use crate::shared::config::Config;
+#[cfg(feature = "shader-reload")]
+use log::error;
+use log::info;
+use std::fmt;
+#[cfg(feature = "shader-reload")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{AllocateBufferError, Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferExecError, CommandBufferUsage, PrimaryAutoCommandBuffer,
+    RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{Device, DeviceExtensions, Queue};
+use vulkano::image::AllocateImageError;
+use vulkano::instance::InstanceExtensions;
+use vulkano::memory::allocator::{
+    AllocationCreateInfo, MemoryAllocatorError, MemoryTypeFilter, StandardMemoryAllocator,
+};
+use vulkano::pipeline::graphics::viewport::Viewport;
+use vulkano::pipeline::layout::IntoPipelineLayoutCreateInfoError;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::{Framebuffer, RenderPass};
+use vulkano::swapchain::{acquire_next_image, Surface, Swapchain, SwapchainPresentInfo};
+use vulkano::sync::{self, GpuFuture, HostAccessError};
+use vulkano::{LoadingError, Validated, ValidationError, VulkanError};
+
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+#[derive(Debug)]
+pub enum RendererError {
+    Library(LoadingError),
+    Vulkan(VulkanError),
+    Validation(Box<ValidationError>),
+    Allocation(AllocateBufferError),
+    ImageAllocation(AllocateImageError),
+    Memory(MemoryAllocatorError),
+    HostAccess(HostAccessError),
+    Execution(CommandBufferExecError),
+    PipelineLayout(IntoPipelineLayoutCreateInfoError),
+    /// No device can present to the surface with the required extensions.
+    NoSuitableDevice,
+    NoSurfaceFormat,
+    /// [`Renderer::new`] for a window without area, as when it is minimized.
+    ZeroExtent,
+    /// The surface was lost and was not made from a winit window, so it
+    /// cannot be made again.
+    NoWindow,
+    MissingEntryPoint(&'static str),
+    /// [`Renderer::capture`] on a renderer that presents to a window.
+    NotHeadless,
+    Io(std::io::Error),
+    Png(png::EncodingError),
+    PngDecoding(png::DecodingError),
+    Font(ab_glyph::InvalidFont),
+    /// Reloaded shaders that changed their descriptor sets or push constants.
+    IncompatibleShaders,
+    #[cfg(feature = "shader-reload")]
+    NoShaderCompiler,
+    #[cfg(feature = "shader-reload")]
+    ShaderCompilation(shaderc::Error),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::Library(e) => write!(f, "failed to load the Vulkan library: {}", e),
+            RendererError::Vulkan(e) => write!(f, "Vulkan error: {}", e),
+            RendererError::Validation(e) => write!(f, "invalid Vulkan usage: {}", e),
+            RendererError::Allocation(e) => write!(f, "failed to allocate a buffer: {}", e),
+            RendererError::ImageAllocation(e) => write!(f, "failed to allocate an image: {}", e),
+            RendererError::Memory(e) => write!(f, "failed to allocate memory: {}", e),
+            RendererError::HostAccess(e) => write!(f, "failed to access a buffer: {}", e),
+            RendererError::Execution(e) => write!(f, "failed to execute commands: {}", e),
+            RendererError::PipelineLayout(e) => {
+                write!(f, "failed to create the pipeline layout: {}", e)
+            }
+            RendererError::NoSuitableDevice => write!(f, "no suitable graphics device found"),
+            RendererError::NoSurfaceFormat => write!(f, "the surface supports no formats"),
+            RendererError::ZeroExtent => write!(f, "the window has no area"),
+            RendererError::NoWindow => write!(f, "the lost surface has no window to recreate it"),
+            RendererError::MissingEntryPoint(name) => {
+                write!(f, "shader has no entry point {:?}", name)
+            }
+            RendererError::NotHeadless => write!(f, "only headless renderers can be captured"),
+            RendererError::Io(e) => write!(f, "failed to write image: {}", e),
+            RendererError::Png(e) => write!(f, "failed to encode PNG: {}", e),
+            RendererError::PngDecoding(e) => write!(f, "failed to decode PNG: {}", e),
+            RendererError::Font(e) => write!(f, "failed to read font: {}", e),
+            RendererError::IncompatibleShaders => {
+                write!(
+                    f,
+                    "the shaders changed their descriptor sets or push constants"
+                )
+            }
+            #[cfg(feature = "shader-reload")]
+            RendererError::NoShaderCompiler => write!(f, "failed to create the shader compiler"),
+            #[cfg(feature = "shader-reload")]
+            RendererError::ShaderCompilation(e) => write!(f, "failed to compile shader: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<LoadingError> for RendererError {
+    fn from(e: LoadingError) -> RendererError {
+        RendererError::Library(e)
+    }
+}
+
+impl From<VulkanError> for RendererError {
+    fn from(e: VulkanError) -> RendererError {
+        RendererError::Vulkan(e)
+    }
+}
+
+impl From<Box<ValidationError>> for RendererError {
+    fn from(e: Box<ValidationError>) -> RendererError {
+        RendererError::Validation(e)
+    }
+}
+
+impl From<Validated<VulkanError>> for RendererError {
+    fn from(e: Validated<VulkanError>) -> RendererError {
+        match e {
+            Validated::Error(e) => RendererError::Vulkan(e),
+            Validated::ValidationError(e) => RendererError::Validation(e),
+        }
+    }
+}
+
+impl From<Validated<AllocateBufferError>> for RendererError {
+    fn from(e: Validated<AllocateBufferError>) -> RendererError {
+        match e {
+            Validated::Error(e) => RendererError::Allocation(e),
+            Validated::ValidationError(e) => RendererError::Validation(e),
+        }
+    }
+}
+
+impl From<Validated<AllocateImageError>> for RendererError {
+    fn from(e: Validated<AllocateImageError>) -> RendererError {
+        match e {
+            Validated::Error(e) => RendererError::ImageAllocation(e),
+            Validated::ValidationError(e) => RendererError::Validation(e),
+        }
+    }
+}
+
+impl From<MemoryAllocatorError> for RendererError {
+    fn from(e: MemoryAllocatorError) -> RendererError {
+        RendererError::Memory(e)
+    }
+}
+
+impl From<HostAccessError> for RendererError {
+    fn from(e: HostAccessError) -> RendererError {
+        RendererError::HostAccess(e)
+    }
+}
+
+impl From<CommandBufferExecError> for RendererError {
+    fn from(e: CommandBufferExecError) -> RendererError {
+        RendererError::Execution(e)
+    }
+}
+
+impl From<IntoPipelineLayoutCreateInfoError> for RendererError {
+    fn from(e: IntoPipelineLayoutCreateInfoError) -> RendererError {
+        RendererError::PipelineLayout(e)
+    }
+}
+
+impl From<std::io::Error> for RendererError {
+    fn from(e: std::io::Error) -> RendererError {
+        RendererError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for RendererError {
+    fn from(e: png::EncodingError) -> RendererError {
+        RendererError::Png(e)
+    }
+}
+
+impl From<png::DecodingError> for RendererError {
+    fn from(e: png::DecodingError) -> RendererError {
+        RendererError::PngDecoding(e)
+    }
+}
+
+#[cfg(feature = "shader-reload")]
+impl From<shaderc::Error> for RendererError {
+    fn from(e: shaderc::Error) -> RendererError {
+        RendererError::ShaderCompilation(e)
+    }
+}
+
+impl From<ab_glyph::InvalidFont> for RendererError {
+    fn from(e: ab_glyph::InvalidFont) -> RendererError {
+        RendererError::Font(e)
+    }
+}
+
+/// Where frames end up.
+enum Target {
+    Window {
+        swapchain: Arc<Swapchain>,
+        framebuffers: Vec<Arc<Framebuffer>>,
+    },
+    Offscreen(OffscreenTarget),
+}
+
+/// How [`Renderer::new`] sets up the device and swapchain.
+#[derive(Debug, Clone, Default)]
+pub struct RendererSettings {
+    pub present_mode: PresentMode,
+    /// The GPU to use instead of the one the renderer prefers.
+    pub device: Option<DeviceSelector>,
+}
+
+impl RendererSettings {
+    /// Reads `render.present_mode` and `render.device`.
+    pub fn from_config(config: &Config) -> RendererSettings {
+        RendererSettings {
+            present_mode: config.get_or("render.present_mode", PresentMode::default()),
+            device: config.get("render.device").ok().flatten(),
+        }
+    }
+}
+
+pub struct Renderer {
+    device: Arc<Device>,
+    queues: Queues,
+    target: Target,
+    render_pass: Arc<RenderPass>,
+    viewport: Viewport,
+    pipeline: Arc<GraphicsPipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    uniform_buffer: SubbufferAllocator,
+    /// Per-instance data is written anew every frame, so the buffer grows
+    /// and shrinks with the number of ants.
+    instance_buffer: SubbufferAllocator,
+    ant_mesh: Subbuffer<[AntVertex]>,
+    pheromones: PheromoneRenderer,
+    sprites: SpriteRenderer,
+    text: TextRenderer,
+    ui: UiRenderer,
+    extent: [u32; 2],
+    recreate_target: bool,
+    /// Set when the surface was lost, a new one is made for the window
+    /// with the swapchain.
+    surface_lost: bool,
+    present_mode: PresentMode,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    #[cfg(feature = "shader-reload")]
+    shader_reloader: Option<ShaderReloader>,
+}
+
+impl Renderer {
+    /// Sets up a device that can present to `surface` and a swapchain of
+    /// `extent`, the window's inner size in pixels.
+    pub fn new(
+        surface: Arc<Surface>,
+        extent: [u32; 2],
+        settings: &RendererSettings,
+    ) -> Result<Renderer, RendererError> {
+        let extensions = DeviceExtensions {
+            khr_swapchain: true,
+            ..DeviceExtensions::empty()
+        };
+        let (physical_device, queue_families) = device::select_physical_device(
+            surface.instance(),
+            Some(&surface),
+            &extensions,
+            settings.device.as_ref(),
+        )?;
+        let (device, queues) = device::create_device(physical_device, queue_families, extensions)?;
+
+        let (swapchain, images) =
+            swapchain::create_swapchain(&device, &queues, surface, extent, settings.present_mode)?
+                .ok_or(RendererError::ZeroExtent)?;
+        let render_pass = pipeline::create_render_pass(&device, swapchain.image_format())?;
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let mut viewport = Viewport::default();
+        let framebuffers =
+            pipeline::create_framebuffers(&images, &render_pass, &memory_allocator, &mut viewport)?;
+        let target = Target::Window {
+            swapchain,
+            framebuffers,
+        };
+        let mut renderer = Renderer::with_target(
+            device,
+            queues,
+            render_pass,
+            viewport,
+            memory_allocator,
+            target,
+            extent,
+        )?;
+        renderer.present_mode = settings.present_mode;
+        Ok(renderer)
+    }
+
+    /// Sets up a renderer without a window that draws into an `extent` sized
+    /// image, see [`Renderer::capture`].
+    pub fn headless(extent: [u32; 2]) -> Result<Renderer, RendererError> {
+        let instance = create_instance("Ant Engine", InstanceExtensions::empty())?;
+        let extensions = DeviceExtensions::empty();
+        let (physical_device, queue_families) =
+            device::select_physical_device(&instance, None, &extensions, None)?;
+        let (device, queues) = device::create_device(physical_device, queue_families, extensions)?;
+
+        let render_pass = pipeline::create_render_pass(&device, offscreen::FORMAT)?;
+        let mut viewport = Viewport::default();
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let target = Target::Offscreen(OffscreenTarget::new(
+            &memory_allocator,
+            &render_pass,
+            extent,
+            &mut viewport,
+        )?);
+        Renderer::with_target(
+            device,
+            queues,
+            render_pass,
+            viewport,
+            memory_allocator,
+            target,
+            extent,
+        )
+    }
+
+    fn with_target(
+        device: Arc<Device>,
+        queues: Queues,
+        render_pass: Arc<RenderPass>,
+        viewport: Viewport,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        target: Target,
+        extent: [u32; 2],
+    ) -> Result<Renderer, RendererError> {
+        let pipeline =
+            pipeline::create_pipeline(&device, &render_pass, pipeline::built_in_shaders(&device)?)?;
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let text = TextRenderer::new(
+            &device,
+            &render_pass,
+            &memory_allocator,
+            &descriptor_set_allocator,
+            &queues,
+        )?;
+        let pheromones = PheromoneRenderer::new(&device, &render_pass, &memory_allocator)?;
+        let sprites = SpriteRenderer::new(&device, &render_pass, &memory_allocator)?;
+        let ui = UiRenderer::new(&device, &render_pass, &memory_allocator)?;
+        let ant_mesh = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            ants::ANT_MESH,
+        )?;
+        let host_buffer = |buffer_usage| {
+            SubbufferAllocator::new(
+                memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            )
+        };
+        let uniform_buffer = host_buffer(BufferUsage::UNIFORM_BUFFER);
+        let instance_buffer = host_buffer(BufferUsage::VERTEX_BUFFER);
+
+        Ok(Renderer {
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+            descriptor_set_allocator,
+            previous_frame_end: Some(sync::now(device.clone()).boxed()),
+            device,
+            queues,
+            target,
+            render_pass,
+            viewport,
+            pipeline,
+            memory_allocator,
+            uniform_buffer,
+            instance_buffer,
+            ant_mesh,
+            pheromones,
+            sprites,
+            text,
+            ui,
+            extent,
+            recreate_target: false,
+            surface_lost: false,
+            present_mode: PresentMode::default(),
+            #[cfg(feature = "shader-reload")]
+            shader_reloader: None,
+        })
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// The queue frames are drawn on.
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queues.graphics
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self.target, Target::Offscreen(_))
+    }
+
+    /// The swapchain or offscreen image is recreated with the new size
+    /// before the next frame.
+    pub fn resize(&mut self, extent: [u32; 2]) {
+        self.extent = extent;
+        self.recreate_target = true;
+    }
+
+    /// Whether the window has no area, frames are skipped until it has.
+    pub fn is_minimized(&self) -> bool {
+        !self.is_headless() && self.extent.contains(&0)
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// The swapchain is recreated with `mode` before the next frame. Modes
+    /// the surface does not support fall back to vsync.
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if mode != self.present_mode {
+            self.present_mode = mode;
+            self.recreate_target |= !self.is_headless();
+        }
+    }
+
+    /// Replaces the UI drawn over the world from the next frame on. Texture
+    /// changes are uploaded right away.
+    pub fn set_ui(&mut self, frame: UiFrame) -> Result<(), RendererError> {
+        let upload = self.ui.update(
+            frame,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.descriptor_set_allocator,
+            &self.queues,
+        )?;
+        self.submit_upload(upload)
+    }
+
+    /// Draws ants, nests and food with the art in `atlas` from the next
+    /// frame on. Kinds the atlas has no sprite for keep their mesh.
+    pub fn set_sprites(&mut self, atlas: &SpriteAtlas) -> Result<(), RendererError> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queues.transfer.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        self.sprites.set_atlas(
+            atlas,
+            &self.memory_allocator,
+            &self.descriptor_set_allocator,
+            self.queues.upload_sharing(),
+            &mut builder,
+        )?;
+        self.submit_upload(Some(builder.build()?))
+    }
+
+    /// Replaces what the pheromone overlay shows from the next frame on.
+    pub fn set_pheromone_layer(&mut self, layer: &PheromoneLayer) {
+        self.pheromones.set_layer(layer);
+    }
+
+    /// Replaces the text font. Labels set before need to be set again.
+    pub fn set_font(&mut self, font: Font) {
+        self.text.set_font(font);
+    }
+
+    /// Replaces the labels drawn over the world from the next frame on. New
+    /// glyphs are uploaded right away.
+    pub fn set_labels(&mut self, labels: &[Label]) -> Result<(), RendererError> {
+        let upload = self.text.set_labels(
+            labels,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queues,
+        )?;
+        self.submit_upload(upload)
+    }
+
+    /// Compiles the shaders from the GLSL files in `directory` whenever they
+    /// change and rebuilds their pipelines before the next frame. Shaders
+    /// that fail to compile are logged and the old pipeline kept.
+    #[cfg(feature = "shader-reload")]
+    pub fn watch_shaders(&mut self, directory: impl Into<PathBuf>) -> Result<(), RendererError> {
+        self.shader_reloader = Some(ShaderReloader::new(directory.into())?);
+        Ok(())
+    }
+
+    #[cfg(feature = "shader-reload")]
+    fn reload_shaders(&mut self) {
+        // out of self while the pipelines are rebuilt
+        let Some(mut reloader) = self.shader_reloader.take() else {
+            return;
+        };
+        for kind in reloader.changed() {
+            let reloaded = reloader
+                .compile(&self.device, kind)
+                .and_then(|shaders| self.rebuild_pipeline(kind, shaders));
+            match reloaded {
+                Ok(()) => info!("Reloaded the {:?} shaders", kind),
+                Err(e) => error!("Failed to reload the {:?} shaders: {}", kind, e),
+            }
+        }
+        self.shader_reloader = Some(reloader);
+    }
+
+    #[cfg(feature = "shader-reload")]
+    fn rebuild_pipeline(
+        &mut self,
+        kind: PipelineKind,
+        shaders: ShaderStages,
+    ) -> Result<(), RendererError> {
+        match kind {
+            PipelineKind::Ants => {
+                let pipeline = pipeline::create_pipeline(&self.device, &self.render_pass, shaders)?;
+                check_compatible(self.pipeline.layout(), pipeline.layout())?;
+                self.pipeline = pipeline;
+                Ok(())
+            }
+            PipelineKind::Pheromones => {
+                self.pheromones.rebuild_pipeline(&self.render_pass, shaders)
+            }
+            PipelineKind::Sprites => self.sprites.rebuild_pipeline(&self.render_pass, shaders),
+            PipelineKind::Text => self.text.rebuild_pipeline(&self.render_pass, shaders),
+            PipelineKind::Ui => self.ui.rebuild_pipeline(&self.render_pass, shaders),
+        }
+    }
+
+    /// Queues `upload` on the transfer queue in front of the next frame.
+    fn submit_upload(
+        &mut self,
+        upload: Option<Arc<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), RendererError> {
+        if let Some(upload) = upload {
+            let previous = self
+                .previous_frame_end
+                .take()
+                .unwrap_or_else(|| sync::now(self.device.clone()).boxed());
+            // left in place if the upload fails
+            self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+            self.previous_frame_end = Some(
+                on_queue(previous, &self.queues.transfer)
+                    .then_execute(self.queues.transfer.clone(), upload)?
+                    .boxed(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Records and submits a frame showing `snapshot` through `camera`.
+    /// Frames are skipped while the window is minimized, and when the
+    /// swapchain went out of date or the surface was lost, both are
+    /// recreated for the next frame. Headless renderers draw into their
+    /// image and wait for the GPU.
+    pub fn draw_frame(
+        &mut self,
+        snapshot: &RenderSnapshot,
+        camera: &Camera,
+    ) -> Result<(), RendererError> {
+        // release resources of frames the GPU is done with
+        if let Some(previous) = self.previous_frame_end.as_mut() {
+            previous.cleanup_finished();
+        }
+        #[cfg(feature = "shader-reload")]
+        self.reload_shaders();
+        if self.is_minimized() || (self.recreate_target && !self.recreate_target()?) {
+            return Ok(());
+        }
+        let upload = self.pheromones.update(
+            snapshot,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.descriptor_set_allocator,
+            &self.queues,
+        )?;
+        self.submit_upload(upload)?;
+        let swapchain = match &self.target {
+            Target::Window { swapchain, .. } => swapchain.clone(),
+            Target::Offscreen(_) => return self.draw_offscreen(snapshot, camera, false),
+        };
+
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(swapchain.clone(), None) {
+                Ok(r) => r,
+                Err(Validated::Error(e @ (VulkanError::OutOfDate | VulkanError::SurfaceLost))) => {
+                    self.schedule_recreate(e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
+        if suboptimal {
+            self.recreate_target = true;
+        }
+
+        let builder = self.record(self.framebuffer(image_index as usize), snapshot, camera)?;
+        let command_buffer = builder.build()?;
+        let previous = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed());
+        let drawn = on_queue(previous, &self.queues.graphics)
+            .join(acquire_future)
+            .then_execute(self.queues.graphics.clone(), command_buffer)?;
+        let future = on_queue(drawn.boxed(), &self.queues.present)
+            .then_swapchain_present(
+                self.queues.present.clone(),
+                SwapchainPresentInfo::swapchain_image_index(swapchain, image_index),
+            )
+            .then_signal_fence_and_flush();
+        match future {
+            Ok(future) => {
+                self.previous_frame_end = Some(future.boxed());
+                Ok(())
+            }
+            Err(Validated::Error(e @ (VulkanError::OutOfDate | VulkanError::SurfaceLost))) => {
+                self.schedule_recreate(e);
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                Ok(())
+            }
+            Err(e) => {
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Draws `snapshot` on a headless renderer and reads it back.
+    pub fn capture(
+        &mut self,
+        snapshot: &RenderSnapshot,
+        camera: &Camera,
+    ) -> Result<Screenshot, RendererError> {
+        if !self.is_headless() {
+            return Err(RendererError::NotHeadless);
+        }
+        if self.recreate_target {
+            self.recreate_target()?;
+        }
+        self.draw_offscreen(snapshot, camera, true)?;
+        match &self.target {
+            Target::Offscreen(target) => target.read(),
+            Target::Window { .. } => unreachable!(),
+        }
+    }
+
+    fn draw_offscreen(
+        &mut self,
+        snapshot: &RenderSnapshot,
+        camera: &Camera,
+        read_back: bool,
+    ) -> Result<(), RendererError> {
+        let Target::Offscreen(target) = &self.target else {
+            unreachable!()
+        };
+        let copy = read_back.then(|| target.copy_to_buffer());
+        let mut builder = self.record(target.framebuffer().clone(), snapshot, camera)?;
+        if let Some(copy) = copy {
+            builder.copy_image_to_buffer(copy)?;
+        }
+        let command_buffer = builder.build()?;
+        // text and UI uploads may still be waiting in front of the frame
+        let previous = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed());
+        self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+        on_queue(previous, &self.queues.graphics)
+            .then_execute(self.queues.graphics.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+        Ok(())
+    }
+
+    fn framebuffer(&self, image_index: usize) -> Arc<Framebuffer> {
+        match &self.target {
+            Target::Window { framebuffers, .. } => framebuffers[image_index].clone(),
+            Target::Offscreen(target) => target.framebuffer().clone(),
+        }
+    }
+
+    /// Records the render pass into `framebuffer`.
+    fn record(
+        &self,
+        framebuffer: Arc<Framebuffer>,
+        snapshot: &RenderSnapshot,
+        camera: &Camera,
+    ) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, RendererError> {
+        let uniform_subbuffer = self.uniform_buffer.allocate_sized()?;
+        *uniform_subbuffer.write()? = CameraUniform {
+            view_projection: camera.view_projection().into(),
+        };
+        let layout = &self.pipeline.layout().set_layouts()[0];
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.clone(),
+            [WriteDescriptorSet::buffer(0, uniform_subbuffer)],
+            [],
+        )?;
+
+        let framebuffer_extent = framebuffer.extent();
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queues.graphics.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(CLEAR_COLOR.into()), Some(1.0.into())],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(0, [self.viewport.clone()].into_iter().collect())?;
+        self.pheromones.draw(&mut builder, set.clone())?;
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                set.clone(),
+            )?;
+        let mesh_instances = ants::instances(snapshot, self.sprites.sprites());
+        if !mesh_instances.is_empty() {
+            let instances = self
+                .instance_buffer
+                .allocate_slice::<AntInstance>(mesh_instances.len() as u64)?;
+            instances.write()?.copy_from_slice(&mesh_instances);
+            builder
+                .bind_vertex_buffers(0, (self.ant_mesh.clone(), instances.clone()))?
+                .draw(self.ant_mesh.len() as u32, instances.len() as u32, 0, 0)?;
+        }
+        self.sprites.draw(&mut builder, set, snapshot)?;
+        builder.next_subpass(
+            SubpassEndInfo::default(),
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )?;
+        self.text
+            .draw(&mut builder, framebuffer_extent, camera.view_projection())?;
+        self.ui.draw(&mut builder, framebuffer_extent)?;
+        builder.end_render_pass(SubpassEndInfo::default())?;
+        Ok(builder)
+    }
+
+    /// After `error`, `OutOfDate` or `SurfaceLost`, from acquiring or
+    /// presenting an image.
+    fn schedule_recreate(&mut self, error: VulkanError) {
+        self.surface_lost |= error == VulkanError::SurfaceLost;
+        self.recreate_target = true;
+    }
+
+    /// `false` when the window cannot be drawn to yet and the frame is
+    /// skipped, recreation is tried again with the next one.
+    fn recreate_target(&mut self) -> Result<bool, RendererError> {
+        match &mut self.target {
+            Target::Window {
+                swapchain,
+                framebuffers,
+            } => {
+                let recreated = if self.surface_lost {
+                    swapchain::recreate_surface(swapchain.surface()).and_then(|surface| {
+                        swapchain::create_swapchain(
+                            &self.device,
+                            &self.queues,
+                            surface,
+                            self.extent,
+                            self.present_mode,
+                        )
+                    })
+                } else {
+                    swapchain::recreate_swapchain(swapchain, self.extent, self.present_mode)
+                };
+                let (new_swapchain, images) = match recreated {
+                    Ok(Some(recreated)) => recreated,
+                    // minimized, or resized again meanwhile
+                    Ok(None) | Err(RendererError::Vulkan(VulkanError::OutOfDate)) => {
+                        return Ok(false)
+                    }
+                    Err(RendererError::Vulkan(VulkanError::SurfaceLost)) => {
+                        self.surface_lost = true;
+                        return Ok(false);
+                    }
+                    Err(e) => return Err(e),
+                };
+                self.surface_lost = false;
+                // a new surface may want another format
+                let format = new_swapchain.image_format();
+                if format != self.render_pass.attachments()[0].format {
+                    info!(
+                        "The surface switched to {:?}, rebuilding the pipelines",
+                        format
+                    );
+                    self.render_pass = pipeline::create_render_pass(&self.device, format)?;
+                    let shaders = pipeline::built_in_shaders(&self.device)?;
+                    self.pipeline =
+                        pipeline::create_pipeline(&self.device, &self.render_pass, shaders)?;
+                    self.pheromones.set_render_pass(&self.render_pass)?;
+                    self.sprites.set_render_pass(&self.render_pass)?;
+                    self.text.set_render_pass(&self.render_pass)?;
+                    self.ui.set_render_pass(&self.render_pass)?;
+                }
+                *swapchain = new_swapchain;
+                *framebuffers = pipeline::create_framebuffers(
+                    &images,
+                    &self.render_pass,
+                    &self.memory_allocator,
+                    &mut self.viewport,
+                )?;
+            }
+            Target::Offscreen(target) => {
+                *target = OffscreenTarget::new(
+                    &self.memory_allocator,
+                    &self.render_pass,
+                    self.extent,
+                    &mut self.viewport,
+                )?;
+            }
+        }
+        self.recreate_target = false;
+        Ok(true)
+    }
+}
+
+/// `future` ready to be followed by work on `queue`, through a semaphore if
+/// it ends on another queue.
+fn on_queue(future: Box<dyn GpuFuture>, queue: &Arc<Queue>) -> Box<dyn GpuFuture> {
+    match future.queue() {
+        Some(previous) if &previous != queue => future.then_signal_semaphore().boxed(),
+        _ => future,
+    }
+}
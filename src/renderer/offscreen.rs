@@ -0,0 +1,237 @@
+//! Rendering into an image in memory instead of a window.
+
+use crate::renderer::{pipeline, RendererError};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::CopyImageToBufferInfo;
+use vulkano::format::Format;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::viewport::Viewport;
+use vulkano::render_pass::{Framebuffer, RenderPass};
+
+/// Pixels are stored exactly as PNG expects them.
+pub(super) const FORMAT: Format = Format::R8G8B8A8_UNORM;
+const BYTES_PER_PIXEL: u32 = 4;
+
+pub(super) struct OffscreenTarget {
+    image: Arc<Image>,
+    framebuffer: Arc<Framebuffer>,
+    readback: Subbuffer<[u8]>,
+}
+
+impl OffscreenTarget {
+    pub(super) fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        render_pass: &Arc<RenderPass>,
+        extent: [u32; 2],
+        viewport: &mut Viewport,
+    ) -> Result<OffscreenTarget, RendererError> {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: FORMAT,
+                extent: [extent[0], extent[1], 1],
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )?;
+        let readback = Buffer::new_slice(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (extent[0] * extent[1] * BYTES_PER_PIXEL) as u64,
+        )?;
+        let framebuffer = pipeline::create_framebuffers(
+            std::slice::from_ref(&image),
+            render_pass,
+            memory_allocator,
+            viewport,
+        )?
+        .remove(0);
+        Ok(OffscreenTarget {
+            image,
+            framebuffer,
+            readback,
+        })
+    }
+
+    pub(super) fn framebuffer(&self) -> &Arc<Framebuffer> {
+        &self.framebuffer
+    }
+
+    pub(super) fn copy_to_buffer(&self) -> CopyImageToBufferInfo {
+        CopyImageToBufferInfo::image_buffer(self.image.clone(), self.readback.clone())
+    }
+
+    /// Reads the image back, after a copy recorded with `copy_to_buffer`
+    /// finished.
+    pub(super) fn read(&self) -> Result<Screenshot, RendererError> {
+        let [width, height, _] = self.image.extent();
+        Ok(Screenshot {
+            width,
+            height,
+            pixels: self.readback.read()?.to_vec(),
+        })
+    }
+}
+
+/// An RGBA image with 8 bits per channel, rows from top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let start = ((y * self.width + x) * BYTES_PER_PIXEL) as usize;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[start..start + BYTES_PER_PIXEL as usize]);
+        pixel
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> Result<(), RendererError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), RendererError> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::camera::Camera;
+    use crate::renderer::snapshot::RenderSnapshot;
+    use crate::renderer::{Renderer, Texture};
+    use crate::shared::fixed::FixedVec2;
+    use crate::shared::game::GameState;
+    use nalgebra_glm::vec2;
+
+    #[test]
+    fn test_png_round_trip() {
+        let screenshot = Screenshot {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 128],
+        };
+        assert_eq!(screenshot.pixel(1, 0), [0, 0, 255, 128]);
+        let mut bytes = Vec::new();
+        screenshot.write_png(&mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(pixels, screenshot.pixels);
+    }
+
+    /// Per channel difference tolerated between a capture and its golden
+    /// image, drivers may round blending differently.
+    const GOLDEN_TOLERANCE: u8 = 2;
+
+    /// Compares `screenshot` to `tests/golden/<name>.png`. With
+    /// `UPDATE_GOLDEN=1` the golden image is rewritten instead. A capture
+    /// that doesn't match is kept in `target/golden` to look at or bless.
+    fn assert_golden(screenshot: &Screenshot, name: &str) {
+        let file = Path::new(name).with_extension("png");
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = root.join("tests/golden").join(&file);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            screenshot.save_png(&path).unwrap();
+            return;
+        }
+        if let Err(difference) = compare_golden(screenshot, &path) {
+            let actual = root.join("target/golden").join(&file);
+            std::fs::create_dir_all(actual.parent().unwrap()).unwrap();
+            screenshot.save_png(&actual).unwrap();
+            panic!(
+                "{}: {}, the capture is in {}, rerun with UPDATE_GOLDEN=1 to bless it",
+                path.display(),
+                difference,
+                actual.display()
+            );
+        }
+    }
+
+    fn compare_golden(screenshot: &Screenshot, path: &Path) -> Result<(), String> {
+        let golden = Texture::load_png(path).map_err(|e| e.to_string())?;
+        if (screenshot.width, screenshot.height) != (golden.width, golden.height) {
+            return Err(format!(
+                "the capture is {}x{} instead of {}x{}",
+                screenshot.width, screenshot.height, golden.width, golden.height
+            ));
+        }
+        let mismatch = screenshot
+            .pixels
+            .chunks(4)
+            .zip(golden.pixels.chunks(4))
+            .position(|(actual, expected)| {
+                actual
+                    .iter()
+                    .zip(expected)
+                    .any(|(a, e)| a.abs_diff(*e) > GOLDEN_TOLERANCE)
+            });
+        match mismatch {
+            Some(index) => {
+                let (x, y) = (index as u32 % golden.width, index as u32 / golden.width);
+                Err(format!(
+                    "({}, {}) is {:?} instead of {:?}",
+                    x,
+                    y,
+                    screenshot.pixel(x, y),
+                    &golden.pixels[index * 4..index * 4 + 4]
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device, CI runs it on lavapipe"]
+    fn test_headless_ants() {
+        let mut renderer = Renderer::headless([64, 64]).unwrap();
+        let mut state = GameState::new(3);
+        state.add_colony(0, FixedVec2::from_int(4, 4));
+        let mut snapshot = RenderSnapshot::new(&state);
+        snapshot.world_size = vec2(8.0, 8.0);
+        snapshot.ants.truncate(1);
+        snapshot.ants[0].position = vec2(4.0, 4.0);
+
+        let camera = Camera::new(snapshot.world_size, [64, 64]);
+        let screenshot = renderer.capture(&snapshot, &camera).unwrap();
+        assert_golden(&screenshot, "headless_ants");
+
+        renderer.resize([32, 16]);
+        let camera = Camera::new(snapshot.world_size, [32, 16]);
+        let screenshot = renderer
+            .capture(&RenderSnapshot::default(), &camera)
+            .unwrap();
+        assert_golden(&screenshot, "headless_empty");
+    }
+}
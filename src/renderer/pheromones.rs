@@ -0,0 +1,528 @@
+//! Heat map of a colony's pheromone field, drawn on the ground under the
+//! ants.
+//!
+//! The field of the colony picked by the [`PheromoneLayer`] is uploaded
+//! whenever a new tick arrives, one color component per [`Channel`]. Each
+//! channel is colored by its own [`ColorRamp`], looked up in a small texture
+//! with a row per channel, and the channels are blended over each other.
+
+use crate::renderer::device::Queues;
+use crate::renderer::pipeline::{ShaderStages, WORLD_SUBPASS};
+#[cfg(feature = "shader-reload")]
+use crate::renderer::reload::check_compatible;
+use crate::renderer::snapshot::RenderSnapshot;
+use crate::renderer::texture::{create_sampler, Texture};
+use crate::renderer::RendererError;
+use crate::shared::pheromone::{Channel, PheromoneGrid};
+use crate::shared::protocols::PlayerId;
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{RenderPass, Subpass};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/pheromone.vert",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/pheromone.frag",
+    }
+}
+
+/// The grid's channels as the color components of one texel.
+const FIELD_FORMAT: Format = Format::R16G16B16A16_UNORM;
+/// Ramp colors are blended as they are, like the colony colors.
+const RAMP_FORMAT: Format = Format::R8G8B8A8_UNORM;
+/// Texels per ramp row.
+const RAMP_WIDTH: u32 = 256;
+
+/// Colors from no pheromone to saturated, transparent at the low end so
+/// empty cells leave the ground visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRamp {
+    Green,
+    Blue,
+    Red,
+    Heat,
+    Gray,
+}
+
+impl ColorRamp {
+    pub const ALL: [ColorRamp; 5] = [
+        ColorRamp::Green,
+        ColorRamp::Blue,
+        ColorRamp::Red,
+        ColorRamp::Heat,
+        ColorRamp::Gray,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorRamp::Green => "green",
+            ColorRamp::Blue => "blue",
+            ColorRamp::Red => "red",
+            ColorRamp::Heat => "heat",
+            ColorRamp::Gray => "gray",
+        }
+    }
+
+    /// Evenly spaced, RGBA.
+    fn stops(self) -> &'static [[f32; 4]] {
+        match self {
+            ColorRamp::Green => &[[0.1, 0.6, 0.1, 0.0], [0.3, 1.0, 0.3, 0.75]],
+            ColorRamp::Blue => &[[0.1, 0.3, 0.8, 0.0], [0.4, 0.7, 1.0, 0.75]],
+            ColorRamp::Red => &[[0.7, 0.1, 0.1, 0.0], [1.0, 0.3, 0.2, 0.75]],
+            ColorRamp::Heat => &[
+                [0.5, 0.0, 0.0, 0.0],
+                [0.9, 0.2, 0.0, 0.5],
+                [1.0, 0.7, 0.0, 0.7],
+                [1.0, 1.0, 0.7, 0.85],
+            ],
+            ColorRamp::Gray => &[[1.0, 1.0, 1.0, 0.0], [1.0, 1.0, 1.0, 0.75]],
+        }
+    }
+
+    /// The color at `t` between 0, no pheromone, and 1, saturated.
+    pub fn color(self, t: f32) -> [f32; 4] {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let (from, to) = (stops[index], stops[index + 1]);
+        let blend = position - index as f32;
+        std::array::from_fn(|i| from[i] + (to[i] - from[i]) * blend)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStyle {
+    pub visible: bool,
+    pub ramp: ColorRamp,
+}
+
+/// What the pheromone overlay shows, see
+/// [`Renderer::set_pheromone_layer`](crate::renderer::Renderer::set_pheromone_layer).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PheromoneLayer {
+    pub visible: bool,
+    /// Whose field is shown.
+    pub colony: PlayerId,
+    /// Indexed by [`Channel::index`].
+    pub channels: [ChannelStyle; Channel::COUNT],
+    /// Intensity at the top of the ramps, lower shows fainter trails.
+    pub saturation: u16,
+}
+
+impl Default for PheromoneLayer {
+    fn default() -> PheromoneLayer {
+        let style = |ramp| ChannelStyle {
+            visible: true,
+            ramp,
+        };
+        PheromoneLayer {
+            visible: false,
+            colony: 0,
+            channels: [
+                style(ColorRamp::Green),
+                style(ColorRamp::Blue),
+                style(ColorRamp::Red),
+            ],
+            saturation: 4096,
+        }
+    }
+}
+
+/// A row per channel, hidden channels are transparent.
+fn ramp_texture(layer: &PheromoneLayer) -> Texture {
+    let mut texture = Texture::filled(RAMP_WIDTH, Channel::COUNT as u32, [0; 4]);
+    let rows = texture.pixels.chunks_exact_mut((RAMP_WIDTH * 4) as usize);
+    for (style, row) in layer.channels.iter().zip(rows) {
+        if !style.visible {
+            continue;
+        }
+        for (x, texel) in row.chunks_exact_mut(4).enumerate() {
+            let color = style.ramp.color(x as f32 / (RAMP_WIDTH - 1) as f32);
+            for (byte, component) in texel.iter_mut().zip(color) {
+                *byte = (component * 255.0).round() as u8;
+            }
+        }
+    }
+    texture
+}
+
+/// The world size the grid covers.
+fn field_size(grid: &PheromoneGrid) -> [f32; 2] {
+    let cell_size = grid.cell_size().to_f32();
+    [
+        grid.width() as f32 * cell_size,
+        grid.height() as f32 * cell_size,
+    ]
+}
+
+/// A corner of the quad under the field, in units of its size.
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct FieldVertex {
+    #[format(R32G32_SFLOAT)]
+    corner: [f32; 2],
+}
+
+const FIELD_QUAD: [FieldVertex; 6] = [
+    FieldVertex { corner: [0.0, 0.0] },
+    FieldVertex { corner: [1.0, 0.0] },
+    FieldVertex { corner: [0.0, 1.0] },
+    FieldVertex { corner: [0.0, 1.0] },
+    FieldVertex { corner: [1.0, 0.0] },
+    FieldVertex { corner: [1.0, 1.0] },
+];
+
+/// The uploaded field of one tick.
+struct Field {
+    colony: PlayerId,
+    tick: u64,
+    size: [f32; 2],
+    image: Arc<Image>,
+}
+
+pub(super) struct PheromoneRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    quad: Subbuffer<[FieldVertex]>,
+    layer: PheromoneLayer,
+    /// Uploaded again after the layer changed.
+    ramps: Option<Arc<Image>>,
+    field: Option<Field>,
+    /// The field and ramps, `None` until both are uploaded.
+    set: Option<Arc<PersistentDescriptorSet>>,
+}
+
+impl PheromoneRenderer {
+    pub(super) fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+    ) -> Result<PheromoneRenderer, RendererError> {
+        let quad = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            FIELD_QUAD,
+        )?;
+        Ok(PheromoneRenderer {
+            pipeline: create_pipeline(device, render_pass, built_in_shaders(device)?)?,
+            sampler: create_sampler(device)?,
+            quad,
+            layer: PheromoneLayer::default(),
+            ramps: None,
+            field: None,
+            set: None,
+        })
+    }
+
+    pub(super) fn set_layer(&mut self, layer: &PheromoneLayer) {
+        if self.layer.channels != layer.channels {
+            self.ramps = None;
+        }
+        self.layer = layer.clone();
+    }
+
+    /// Uploads the ramps if the layer changed and the shown field if
+    /// `snapshot` is from a new tick. Nothing is uploaded while the layer is
+    /// hidden.
+    pub(super) fn update(
+        &mut self,
+        snapshot: &RenderSnapshot,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: &StandardCommandBufferAllocator,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        queues: &Queues,
+    ) -> Result<Option<Arc<PrimaryAutoCommandBuffer>>, RendererError> {
+        if !self.layer.visible {
+            return Ok(None);
+        }
+        let Some(grid) = snapshot.pheromones(self.layer.colony) else {
+            return Ok(None);
+        };
+        let stale = !self
+            .field
+            .as_ref()
+            .is_some_and(|field| field.colony == self.layer.colony && field.tick == snapshot.tick);
+        if self.ramps.is_some() && !stale {
+            return Ok(None);
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            command_buffer_allocator,
+            queues.transfer.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let mut images_changed = false;
+        if self.ramps.is_none() {
+            let ramps = ramp_texture(&self.layer).upload_as(
+                RAMP_FORMAT,
+                memory_allocator,
+                queues.upload_sharing(),
+                &mut builder,
+            )?;
+            self.ramps = Some(ramps);
+            images_changed = true;
+        }
+        if stale {
+            let extent = [grid.width(), grid.height(), 1];
+            let image = match self.field.take() {
+                Some(field) if field.image.extent() == extent => field.image,
+                _ => {
+                    images_changed = true;
+                    Image::new(
+                        memory_allocator.clone(),
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format: FIELD_FORMAT,
+                            extent,
+                            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                            sharing: queues.upload_sharing(),
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )?
+                }
+            };
+            let staging = Buffer::from_iter(
+                memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                grid.texels(),
+            )?;
+            builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                staging,
+                image.clone(),
+            ))?;
+            self.field = Some(Field {
+                colony: self.layer.colony,
+                tick: snapshot.tick,
+                size: field_size(grid),
+                image,
+            });
+        }
+        if let (true, Some(field), Some(ramps)) = (images_changed, &self.field, &self.ramps) {
+            self.set = Some(PersistentDescriptorSet::new(
+                descriptor_set_allocator,
+                self.pipeline.layout().set_layouts()[1].clone(),
+                [
+                    WriteDescriptorSet::image_view_sampler(
+                        0,
+                        ImageView::new_default(field.image.clone())?,
+                        self.sampler.clone(),
+                    ),
+                    WriteDescriptorSet::image_view_sampler(
+                        1,
+                        ImageView::new_default(ramps.clone())?,
+                        self.sampler.clone(),
+                    ),
+                ],
+                [],
+            )?);
+        }
+        Ok(Some(builder.build()?))
+    }
+
+    /// Rebuilds the pipeline for a render pass with another color format.
+    pub(super) fn set_render_pass(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+    ) -> Result<(), RendererError> {
+        let device = self.pipeline.device().clone();
+        self.pipeline = create_pipeline(&device, render_pass, built_in_shaders(&device)?)?;
+        Ok(())
+    }
+
+    /// Replaces the pipeline with one built from `shaders`, which must use
+    /// the same descriptor sets and push constants.
+    #[cfg(feature = "shader-reload")]
+    pub(super) fn rebuild_pipeline(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        shaders: ShaderStages,
+    ) -> Result<(), RendererError> {
+        let pipeline = create_pipeline(self.pipeline.device(), render_pass, shaders)?;
+        check_compatible(self.pipeline.layout(), pipeline.layout())?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Records the field into the world subpass, before the ants so they
+    /// are drawn over it. `camera` is the set with the camera uniform the
+    /// world pipeline uses.
+    pub(super) fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: Arc<PersistentDescriptorSet>,
+    ) -> Result<(), RendererError> {
+        let (Some(field), Some(set)) = (&self.field, &self.set) else {
+            return Ok(());
+        };
+        if !self.layer.visible || field.colony != self.layer.colony {
+            return Ok(());
+        }
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                (camera, set.clone()),
+            )?
+            .push_constants(
+                layout,
+                0,
+                vs::Field {
+                    size: field.size,
+                    gain: u16::MAX as f32 / self.layer.saturation.max(1) as f32,
+                },
+            )?
+            .bind_vertex_buffers(0, self.quad.clone())?
+            .draw(self.quad.len() as u32, 1, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// The shaders compiled into the binary.
+fn built_in_shaders(device: &Arc<Device>) -> Result<ShaderStages, RendererError> {
+    ShaderStages::new(vs::load(device.clone())?, fs::load(device.clone())?)
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    shaders: ShaderStages,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let ShaderStages {
+        vertex: vs,
+        fragment: fs,
+    } = shaders;
+    let vertex_input_state = FieldVertex::per_vertex().definition(&vs.info().input_interface)?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), WORLD_SUBPASS).unwrap();
+
+    Ok(GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            // neither tested nor written, the ants are drawn over the field
+            depth_stencil_state: Some(DepthStencilState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::fixed::{Fixed, FixedVec2};
+
+    #[test]
+    fn test_ramp_color() {
+        for ramp in ColorRamp::ALL {
+            let stops = ramp.stops();
+            assert_eq!(ramp.color(0.0), stops[0]);
+            assert_eq!(ramp.color(-1.0)[3], 0.0, "{} is not clear", ramp.name());
+            assert_eq!(ramp.color(2.0), stops[stops.len() - 1]);
+        }
+        let [r, g, b, a] = ColorRamp::Gray.color(0.5);
+        assert_eq!([r, g, b], [1.0; 3]);
+        assert!((a - 0.375).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ramp_texture() {
+        let mut layer = PheromoneLayer::default();
+        layer.channels[Channel::HomeTrail.index()].visible = false;
+        let texture = ramp_texture(&layer);
+        assert_eq!([texture.width, texture.height], [RAMP_WIDTH, 3]);
+        let texel = |x: u32, channel: Channel| {
+            let start = ((channel.index() as u32 * RAMP_WIDTH + x) * 4) as usize;
+            texture.pixels[start..start + 4].to_vec()
+        };
+        assert_eq!(texel(0, Channel::FoodTrail)[3], 0);
+        assert_eq!(
+            texel(RAMP_WIDTH - 1, Channel::FoodTrail),
+            vec![77, 255, 77, 191]
+        );
+        assert_eq!(texel(RAMP_WIDTH - 1, Channel::HomeTrail), vec![0; 4]);
+        assert_eq!(texel(RAMP_WIDTH - 1, Channel::Danger)[3], 191);
+    }
+
+    #[test]
+    fn test_field_size() {
+        let grid = PheromoneGrid::new(FixedVec2::from_int(9, 4), Fixed::from_int(2));
+        assert_eq!(field_size(&grid), [10.0, 4.0]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Shaders, the render pass and the graphics pipeline.
+
+use crate::renderer::ants::{AntInstance, AntVertex};
+use crate::renderer::RendererError;
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::shader::{EntryPoint, ShaderModule};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/ant.vert",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/ant.frag",
+    }
+}
+
+pub(super) type CameraUniform = vs::Camera;
+
+/// The entry points a pipeline is built from.
+pub(super) struct ShaderStages {
+    pub(super) vertex: EntryPoint,
+    pub(super) fragment: EntryPoint,
+}
+
+impl ShaderStages {
+    pub(super) fn new(
+        vertex: Arc<ShaderModule>,
+        fragment: Arc<ShaderModule>,
+    ) -> Result<ShaderStages, RendererError> {
+        Ok(ShaderStages {
+            vertex: vertex
+                .entry_point("main")
+                .ok_or(RendererError::MissingEntryPoint("main"))?,
+            fragment: fragment
+                .entry_point("main")
+                .ok_or(RendererError::MissingEntryPoint("main"))?,
+        })
+    }
+}
+
+pub(super) const DEPTH_FORMAT: Format = Format::D16_UNORM;
+/// The overlay subpass follows it, for UI the world must never hide.
+pub(super) const WORLD_SUBPASS: u32 = 0;
+pub(super) const OVERLAY_SUBPASS: u32 = 1;
+
+/// The world is drawn depth-tested in the first subpass, the overlay on
+/// top of it without depth in the second.
+pub(super) fn create_render_pass(
+    device: &Arc<Device>,
+    format: Format,
+) -> Result<Arc<RenderPass>, RendererError> {
+    Ok(vulkano::ordered_passes_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: DEPTH_FORMAT,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        passes: [
+            { color: [color], depth_stencil: {depth}, input: [] },
+            { color: [color], depth_stencil: {}, input: [] },
+        ],
+    )?)
+}
+
+/// Creates a framebuffer per image, sharing one depth buffer, and fits
+/// `viewport` to them. Called again whenever the images change size.
+pub(super) fn create_framebuffers(
+    images: &[Arc<Image>],
+    render_pass: &Arc<RenderPass>,
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    viewport: &mut Viewport,
+) -> Result<Vec<Arc<Framebuffer>>, RendererError> {
+    let extent = images[0].extent();
+    viewport.extent = [extent[0] as f32, extent[1] as f32];
+
+    let depth = ImageView::new_default(Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: DEPTH_FORMAT,
+            extent,
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?)?;
+    images
+        .iter()
+        .map(|image| {
+            let view = ImageView::new_default(image.clone())?;
+            Ok(Framebuffer::new(
+                render_pass.clone(),
+                FramebufferCreateInfo {
+                    attachments: vec![view, depth.clone()],
+                    ..Default::default()
+                },
+            )?)
+        })
+        .collect()
+}
+
+/// The shaders compiled into the binary.
+pub(super) fn built_in_shaders(device: &Arc<Device>) -> Result<ShaderStages, RendererError> {
+    ShaderStages::new(vs::load(device.clone())?, fs::load(device.clone())?)
+}
+
+pub(super) fn create_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    shaders: ShaderStages,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let ShaderStages {
+        vertex: vs,
+        fragment: fs,
+    } = shaders;
+    let vertex_input_state = [AntVertex::per_vertex(), AntInstance::per_instance()]
+        .definition(&vs.info().input_interface)?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), WORLD_SUBPASS).unwrap();
+
+    Ok(GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: true,
+                    compare_op: CompareOp::LessOrEqual,
+                }),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
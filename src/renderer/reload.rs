@@ -0,0 +1,175 @@
+//! Shader hot-reloading for development, behind the `shader-reload`
+//! feature.
+//!
+//! The pipelines are built from the shaders compiled into the binary from
+//! `shaders/`. [`ShaderReloader`] polls the same files in a directory on
+//! disk and compiles the ones that changed with shaderc, so the renderer can
+//! rebuild the affected pipelines without a restart. A reloaded shader may
+//! change what it computes but not its descriptor sets or push constants.
+
+use crate::renderer::pipeline::ShaderStages;
+use crate::renderer::RendererError;
+use log::warn;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use vulkano::device::Device;
+use vulkano::pipeline::PipelineLayout;
+use vulkano::shader::{ShaderModule, ShaderModuleCreateInfo};
+
+/// Files are checked this often, editors save in bursts anyway.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum PipelineKind {
+    Ants,
+    Pheromones,
+    Sprites,
+    Text,
+    Ui,
+}
+
+impl PipelineKind {
+    pub(super) const ALL: [PipelineKind; 5] = [
+        PipelineKind::Ants,
+        PipelineKind::Pheromones,
+        PipelineKind::Sprites,
+        PipelineKind::Text,
+        PipelineKind::Ui,
+    ];
+
+    /// The vertex and fragment shader, relative to the shader directory.
+    fn files(self) -> [&'static str; 2] {
+        match self {
+            PipelineKind::Ants => ["ant.vert", "ant.frag"],
+            PipelineKind::Pheromones => ["pheromone.vert", "pheromone.frag"],
+            PipelineKind::Sprites => ["sprite.vert", "sprite.frag"],
+            PipelineKind::Text => ["text.vert", "text.frag"],
+            PipelineKind::Ui => ["ui.vert", "ui.frag"],
+        }
+    }
+}
+
+pub(super) struct ShaderReloader {
+    directory: PathBuf,
+    compiler: shaderc::Compiler,
+    /// When each file was modified as of the last poll.
+    modified: HashMap<&'static str, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderReloader {
+    /// Watches the shader files in `directory`. The running pipelines are
+    /// taken to match the files as they are now.
+    pub(super) fn new(directory: PathBuf) -> Result<ShaderReloader, RendererError> {
+        let compiler = shaderc::Compiler::new().ok_or(RendererError::NoShaderCompiler)?;
+        let mut modified = HashMap::new();
+        for file in PipelineKind::ALL.into_iter().flat_map(PipelineKind::files) {
+            modified.insert(file, std::fs::metadata(directory.join(file))?.modified()?);
+        }
+        Ok(ShaderReloader {
+            directory,
+            compiler,
+            modified,
+            last_poll: Instant::now(),
+        })
+    }
+
+    /// The pipelines with a shader file saved since the last poll. Polls at
+    /// most every [`POLL_INTERVAL`], returning nothing in between.
+    pub(super) fn changed(&mut self) -> Vec<PipelineKind> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        let mut changed = Vec::new();
+        for kind in PipelineKind::ALL {
+            for file in kind.files() {
+                // a file that is missing for a moment is being saved
+                let Ok(modified) =
+                    std::fs::metadata(self.directory.join(file)).and_then(|m| m.modified())
+                else {
+                    continue;
+                };
+                if self.modified.insert(file, modified) != Some(modified)
+                    && !changed.contains(&kind)
+                {
+                    changed.push(kind);
+                }
+            }
+        }
+        changed
+    }
+
+    pub(super) fn compile(
+        &self,
+        device: &Arc<Device>,
+        kind: PipelineKind,
+    ) -> Result<ShaderStages, RendererError> {
+        let [vertex, fragment] = kind.files();
+        ShaderStages::new(
+            self.compile_file(device, vertex, shaderc::ShaderKind::Vertex)?,
+            self.compile_file(device, fragment, shaderc::ShaderKind::Fragment)?,
+        )
+    }
+
+    fn compile_file(
+        &self,
+        device: &Arc<Device>,
+        file: &str,
+        stage: shaderc::ShaderKind,
+    ) -> Result<Arc<ShaderModule>, RendererError> {
+        let path = self.directory.join(file);
+        let source = std::fs::read_to_string(&path)?;
+        let name = path.display().to_string();
+        let artifact = self
+            .compiler
+            .compile_into_spirv(&source, stage, &name, "main", None)?;
+        if artifact.get_num_warnings() > 0 {
+            warn!("{}", artifact.get_warning_messages());
+        }
+        // SAFETY: shaderc only produces valid SPIR-V
+        Ok(unsafe {
+            ShaderModule::new(
+                device.clone(),
+                ShaderModuleCreateInfo::new(artifact.as_binary()),
+            )
+        }?)
+    }
+}
+
+/// Checks that descriptor sets and push constants made for `old` work with
+/// `new`, so a rebuilt pipeline can replace it.
+pub(super) fn check_compatible(
+    old: &PipelineLayout,
+    new: &PipelineLayout,
+) -> Result<(), RendererError> {
+    let sets = old.set_layouts().len();
+    if sets == new.set_layouts().len() && old.is_compatible_with(new, sets as u32) {
+        Ok(())
+    } else {
+        Err(RendererError::IncompatibleShaders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_changed() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+        let mut reloader = ShaderReloader::new(directory).unwrap();
+        reloader.last_poll -= POLL_INTERVAL;
+        assert!(reloader.changed().is_empty());
+
+        reloader
+            .modified
+            .insert("text.frag", SystemTime::UNIX_EPOCH);
+        assert!(reloader.changed().is_empty(), "polled too early");
+        reloader.last_poll -= POLL_INTERVAL;
+        assert_eq!(reloader.changed(), vec![PipelineKind::Text]);
+    }
+}
@@ -0,0 +1,204 @@
+//! What the render thread gets to see of the [`GameState`].
+//!
+//! Snapshots are plain float data taken after every tick. The renderer
+//! draws somewhere between the last two of them, so movement stays smooth
+//! when the monitor refreshes faster than the simulation ticks.
+
+use crate::shared::ecs::Entity;
+use crate::shared::game::GameState;
+use crate::shared::pheromone::PheromoneGrid;
+use crate::shared::protocols::PlayerId;
+use nalgebra_glm::{lerp, Vec2};
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AntSnapshot {
+    pub entity: Entity,
+    pub colony: PlayerId,
+    pub position: Vec2,
+    /// Radians, counter-clockwise from the x axis.
+    pub heading: f32,
+    pub carrying: bool,
+    /// Set by the render thread for the local player's selection.
+    pub selected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NestSnapshot {
+    pub entity: Entity,
+    pub colony: PlayerId,
+    pub position: Vec2,
+    pub food: u32,
+    pub selected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodSnapshot {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub amount: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PheromoneSnapshot {
+    pub colony: PlayerId,
+    /// Shared by the snapshots interpolated from this tick.
+    pub grid: Arc<PheromoneGrid>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderSnapshot {
+    pub tick: u64,
+    pub world_size: Vec2,
+    /// Sorted by entity index.
+    pub ants: Vec<AntSnapshot>,
+    pub nests: Vec<NestSnapshot>,
+    pub food: Vec<FoodSnapshot>,
+    pub pheromones: Vec<PheromoneSnapshot>,
+}
+
+impl RenderSnapshot {
+    pub fn new(state: &GameState) -> RenderSnapshot {
+        let position = |entity| {
+            state
+                .positions()
+                .get(entity)
+                .map(|position| position.to_glm())
+                .unwrap_or_default()
+        };
+        RenderSnapshot {
+            tick: state.tick(),
+            world_size: state.world_size().to_glm(),
+            ants: state
+                .ants()
+                .iter()
+                .map(|(entity, ant)| AntSnapshot {
+                    entity,
+                    colony: ant.colony,
+                    position: position(entity),
+                    heading: ant.heading.to_radians_f32(),
+                    carrying: ant.carrying > 0,
+                    selected: false,
+                })
+                .collect(),
+            nests: state
+                .nests()
+                .iter()
+                .map(|(entity, nest)| NestSnapshot {
+                    entity,
+                    colony: nest.colony,
+                    position: position(entity),
+                    food: nest.food,
+                    selected: false,
+                })
+                .collect(),
+            food: state
+                .food()
+                .iter()
+                .map(|(entity, food)| FoodSnapshot {
+                    entity,
+                    position: position(entity),
+                    amount: food.amount,
+                })
+                .collect(),
+            pheromones: state
+                .colonies()
+                .iter()
+                .map(|colony| PheromoneSnapshot {
+                    colony: colony.player,
+                    grid: Arc::new(colony.pheromones.clone()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn pheromones(&self, colony: PlayerId) -> Option<&PheromoneGrid> {
+        self.pheromones
+            .iter()
+            .find(|pheromones| pheromones.colony == colony)
+            .map(|pheromones| pheromones.grid.as_ref())
+    }
+
+    /// Blends ants between two snapshots, `alpha` 0 is `previous` and 1 is
+    /// `current`. Everything else, and ants that did not exist before, are
+    /// taken from `current`.
+    pub fn interpolate(
+        previous: &RenderSnapshot,
+        current: &RenderSnapshot,
+        alpha: f32,
+    ) -> RenderSnapshot {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let ants = current
+            .ants
+            .iter()
+            .map(|ant| {
+                let before = previous
+                    .ants
+                    .binary_search_by_key(&ant.entity.index(), |a| a.entity.index())
+                    .ok()
+                    .map(|i| &previous.ants[i])
+                    .filter(|before| before.entity == ant.entity);
+                match before {
+                    Some(before) => AntSnapshot {
+                        position: lerp(&before.position, &ant.position, alpha),
+                        heading: lerp_angle(before.heading, ant.heading, alpha),
+                        ..ant.clone()
+                    },
+                    None => ant.clone(),
+                }
+            })
+            .collect();
+        RenderSnapshot {
+            ants,
+            ..current.clone()
+        }
+    }
+}
+
+/// Interpolates along the shorter way around the circle.
+fn lerp_angle(from: f32, to: f32, alpha: f32) -> f32 {
+    let delta = (to - from + PI).rem_euclid(TAU) - PI;
+    from + delta * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::fixed::FixedVec2;
+
+    #[test]
+    fn test_interpolate() {
+        let mut state = GameState::new(2);
+        state.add_colony(0, FixedVec2::from_int(50, 50));
+        let previous = RenderSnapshot::new(&state);
+        state.step(&[]);
+        let current = RenderSnapshot::new(&state);
+        assert_eq!(current.ants.len(), previous.ants.len());
+
+        let start = RenderSnapshot::interpolate(&previous, &current, 0.0);
+        assert_eq!(start.ants, previous.ants);
+        assert_eq!(start.tick, current.tick);
+        let end = RenderSnapshot::interpolate(&previous, &current, 1.0);
+        for (ant, expected) in end.ants.iter().zip(current.ants.iter()) {
+            assert_eq!(ant.position, expected.position);
+            assert!((ant.heading - expected.heading).abs() < 1e-5);
+        }
+        assert!(Arc::ptr_eq(
+            &end.pheromones[0].grid,
+            &current.pheromones[0].grid
+        ));
+        assert!(end.pheromones(0).is_some_and(|grid| !grid.is_empty()));
+        assert!(end.pheromones(1).is_none());
+        let half = RenderSnapshot::interpolate(&previous, &current, 0.5);
+        let (a, b) = (&previous.ants[0].position, &current.ants[0].position);
+        assert!((half.ants[0].position - (a + b) / 2.0).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_lerp_angle_wraps() {
+        let angle = lerp_angle(TAU - 0.1, 0.1, 0.5);
+        assert!((angle.rem_euclid(TAU)).min(TAU - angle.rem_euclid(TAU)) < 1e-5);
+        assert!((lerp_angle(0.0, 1.0, 0.25) - 0.25).abs() < 1e-6);
+    }
+}
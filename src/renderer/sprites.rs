@@ -0,0 +1,573 @@
+//! Textured sprites from an atlas, drawn instead of the ant mesh for the
+//! kinds of entities the atlas has art for.
+//!
+//! A [`SpriteAtlas`] packs named images into one texture. The renderer looks
+//! up [`ANT_SPRITE`], [`NEST_SPRITE`] and [`FOOD_SPRITE`] in the atlas handed
+//! to [`Renderer::set_sprites`](crate::renderer::Renderer::set_sprites); ants
+//! and nests without art keep the colored mesh, food is only drawn with art.
+
+use crate::renderer::ants::colony_color;
+use crate::renderer::device::QueueSharing;
+use crate::renderer::pipeline::{ShaderStages, WORLD_SUBPASS};
+#[cfg(feature = "shader-reload")]
+use crate::renderer::reload::check_compatible;
+use crate::renderer::snapshot::{AntSnapshot, FoodSnapshot, NestSnapshot, RenderSnapshot};
+use crate::renderer::texture::{create_sampler, Texture};
+use crate::renderer::RendererError;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::image::view::ImageView;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{RenderPass, Subpass};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/sprite.vert",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/sprite.frag",
+    }
+}
+
+/// Art for ants, pointing east like the mesh.
+pub const ANT_SPRITE: &str = "ant";
+pub const NEST_SPRITE: &str = "nest";
+pub const FOOD_SPRITE: &str = "food";
+/// Atlases are at least this wide, wider images widen them.
+const ATLAS_WIDTH: u32 = 1024;
+/// Empty pixels between sprites, so linear filtering does not pick up the
+/// neighbours.
+const SPRITE_PADDING: u32 = 1;
+/// The longer side of a sprite at scale 1, in world units.
+const ANT_SIZE: f32 = 1.0;
+const NEST_SIZE: f32 = 3.0;
+const FOOD_SIZE: f32 = 1.5;
+const SELECTED_TINT: [f32; 4] = [1.0, 1.0, 0.6, 1.0];
+const WHITE: [f32; 4] = [1.0; 4];
+
+/// Where a sprite is in its atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteRegion {
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    /// In pixels.
+    pub size: [u32; 2],
+}
+
+impl SpriteRegion {
+    /// World size of the sprite with its longer side `length` long.
+    fn world_size(&self, length: f32) -> [f32; 2] {
+        let longer = self.size[0].max(self.size[1]).max(1) as f32;
+        [
+            self.size[0] as f32 / longer * length,
+            self.size[1] as f32 / longer * length,
+        ]
+    }
+}
+
+/// Named images packed into one texture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteAtlas {
+    texture: Texture,
+    regions: HashMap<String, SpriteRegion>,
+}
+
+impl SpriteAtlas {
+    /// Packs `sprites` in rows, tallest first.
+    pub fn pack(mut sprites: Vec<(String, Texture)>) -> SpriteAtlas {
+        sprites.sort_by(|(a, a_texture), (b, b_texture)| {
+            b_texture.height.cmp(&a_texture.height).then(a.cmp(b))
+        });
+        let widest = sprites.iter().map(|(_, texture)| texture.width).max();
+        let width = ATLAS_WIDTH.max(widest.unwrap_or(0) + 2 * SPRITE_PADDING);
+
+        let mut positions = Vec::with_capacity(sprites.len());
+        let mut cursor = [SPRITE_PADDING; 2];
+        let mut row_height = 0;
+        for (_, texture) in &sprites {
+            if cursor[0] + texture.width + SPRITE_PADDING > width {
+                cursor = [SPRITE_PADDING, cursor[1] + row_height + SPRITE_PADDING];
+                row_height = 0;
+            }
+            positions.push(cursor);
+            cursor[0] += texture.width + SPRITE_PADDING;
+            row_height = row_height.max(texture.height);
+        }
+        let height = (cursor[1] + row_height + SPRITE_PADDING).max(1);
+
+        let mut atlas = Texture::filled(width, height, [0; 4]);
+        let mut regions = HashMap::with_capacity(sprites.len());
+        for ((name, texture), [x, y]) in sprites.into_iter().zip(positions) {
+            let row_length = (texture.width * 4) as usize;
+            for row in 0..texture.height {
+                let source = (row * texture.width * 4) as usize;
+                let target = (((y + row) * width + x) * 4) as usize;
+                atlas.pixels[target..target + row_length]
+                    .copy_from_slice(&texture.pixels[source..source + row_length]);
+            }
+            let uv = |pixel: u32, size: u32| pixel as f32 / size as f32;
+            regions.insert(
+                name,
+                SpriteRegion {
+                    uv_min: [uv(x, width), uv(y, height)],
+                    uv_max: [uv(x + texture.width, width), uv(y + texture.height, height)],
+                    size: [texture.width, texture.height],
+                },
+            );
+        }
+        SpriteAtlas {
+            texture: atlas,
+            regions,
+        }
+    }
+
+    /// Packs every PNG in `directory`, named after the file without its
+    /// extension.
+    pub fn load_directory(directory: impl AsRef<Path>) -> Result<SpriteAtlas, RendererError> {
+        let mut sprites = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("png") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            sprites.push((name.to_string(), Texture::load_png(&path)?));
+        }
+        Ok(SpriteAtlas::pack(sprites))
+    }
+
+    pub fn region(&self, name: &str) -> Option<SpriteRegion> {
+        self.regions.get(name).copied()
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+/// A corner of the unit quad around a sprite's center.
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct SpriteVertex {
+    #[format(R32G32_SFLOAT)]
+    corner: [f32; 2],
+}
+
+const SPRITE_QUAD: [SpriteVertex; 6] = [
+    SpriteVertex {
+        corner: [-0.5, -0.5],
+    },
+    SpriteVertex {
+        corner: [0.5, -0.5],
+    },
+    SpriteVertex {
+        corner: [-0.5, 0.5],
+    },
+    SpriteVertex {
+        corner: [-0.5, 0.5],
+    },
+    SpriteVertex {
+        corner: [0.5, -0.5],
+    },
+    SpriteVertex { corner: [0.5, 0.5] },
+];
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct SpriteInstance {
+    #[format(R32G32_SFLOAT)]
+    offset: [f32; 2],
+    #[format(R32_SFLOAT)]
+    heading: f32,
+    #[format(R32G32_SFLOAT)]
+    size: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv_min: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv_max: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    tint: [f32; 4],
+}
+
+impl SpriteInstance {
+    fn new(
+        region: &SpriteRegion,
+        offset: [f32; 2],
+        heading: f32,
+        length: f32,
+        tint: [f32; 4],
+    ) -> SpriteInstance {
+        SpriteInstance {
+            offset,
+            heading,
+            size: region.world_size(length),
+            uv_min: region.uv_min,
+            uv_max: region.uv_max,
+            tint,
+        }
+    }
+
+    fn ant(region: &SpriteRegion, ant: &AntSnapshot) -> SpriteInstance {
+        let tint = if ant.selected {
+            SELECTED_TINT
+        } else {
+            colony_color(ant.colony)
+        };
+        SpriteInstance::new(region, ant.position.into(), ant.heading, ANT_SIZE, tint)
+    }
+
+    /// Nests stay upright.
+    fn nest(region: &SpriteRegion, nest: &NestSnapshot) -> SpriteInstance {
+        let tint = if nest.selected {
+            SELECTED_TINT
+        } else {
+            colony_color(nest.colony)
+        };
+        SpriteInstance::new(region, nest.position.into(), 0.0, NEST_SIZE, tint)
+    }
+
+    fn food(region: &SpriteRegion, food: &FoodSnapshot) -> SpriteInstance {
+        SpriteInstance::new(region, food.position.into(), 0.0, FOOD_SIZE, WHITE)
+    }
+}
+
+/// The regions of the entity art in the current atlas.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) struct EntitySprites {
+    pub(super) ant: Option<SpriteRegion>,
+    pub(super) nest: Option<SpriteRegion>,
+    food: Option<SpriteRegion>,
+}
+
+impl EntitySprites {
+    fn from_atlas(atlas: &SpriteAtlas) -> EntitySprites {
+        EntitySprites {
+            ant: atlas.region(ANT_SPRITE),
+            nest: atlas.region(NEST_SPRITE),
+            food: atlas.region(FOOD_SPRITE),
+        }
+    }
+
+    /// Food first, then nests, then ants, so ants stay visible on top.
+    fn instances(&self, snapshot: &RenderSnapshot) -> Vec<SpriteInstance> {
+        let mut instances = Vec::new();
+        if let Some(region) = &self.food {
+            let food = snapshot.food.iter().filter(|food| food.amount > 0);
+            instances.extend(food.map(|food| SpriteInstance::food(region, food)));
+        }
+        if let Some(region) = &self.nest {
+            let nests = snapshot.nests.iter();
+            instances.extend(nests.map(|nest| SpriteInstance::nest(region, nest)));
+        }
+        if let Some(region) = &self.ant {
+            let ants = snapshot.ants.iter();
+            instances.extend(ants.map(|ant| SpriteInstance::ant(region, ant)));
+        }
+        instances
+    }
+}
+
+pub(super) struct SpriteRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+    /// The atlas texture, `None` until one is set.
+    set: Option<Arc<PersistentDescriptorSet>>,
+    sprites: EntitySprites,
+    quad: Subbuffer<[SpriteVertex]>,
+    instance_buffer: SubbufferAllocator,
+}
+
+impl SpriteRenderer {
+    pub(super) fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+    ) -> Result<SpriteRenderer, RendererError> {
+        let memory_type_filter =
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE;
+        let quad = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter,
+                ..Default::default()
+            },
+            SPRITE_QUAD,
+        )?;
+        Ok(SpriteRenderer {
+            pipeline: create_pipeline(device, render_pass, built_in_shaders(device)?)?,
+            set: None,
+            sprites: EntitySprites::default(),
+            quad,
+            instance_buffer: SubbufferAllocator::new(
+                memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter,
+                    ..Default::default()
+                },
+            ),
+        })
+    }
+
+    /// Which entities have art, none until an atlas is set.
+    pub(super) fn sprites(&self) -> &EntitySprites {
+        &self.sprites
+    }
+
+    /// Records the upload of `atlas` into `builder` and draws with it from
+    /// then on.
+    pub(super) fn set_atlas(
+        &mut self,
+        atlas: &SpriteAtlas,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        sharing: QueueSharing,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), RendererError> {
+        let image = atlas.texture.upload(memory_allocator, sharing, builder)?;
+        let set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            self.pipeline.layout().set_layouts()[1].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(image)?,
+                create_sampler(self.pipeline.device())?,
+            )],
+            [],
+        )?;
+        self.set = Some(set);
+        self.sprites = EntitySprites::from_atlas(atlas);
+        Ok(())
+    }
+
+    /// Rebuilds the pipeline for a render pass with another color format.
+    pub(super) fn set_render_pass(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+    ) -> Result<(), RendererError> {
+        let device = self.pipeline.device().clone();
+        self.pipeline = create_pipeline(&device, render_pass, built_in_shaders(&device)?)?;
+        Ok(())
+    }
+
+    /// Replaces the pipeline with one built from `shaders`, which must use
+    /// the same descriptor sets and push constants.
+    #[cfg(feature = "shader-reload")]
+    pub(super) fn rebuild_pipeline(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        shaders: ShaderStages,
+    ) -> Result<(), RendererError> {
+        let pipeline = create_pipeline(self.pipeline.device(), render_pass, shaders)?;
+        check_compatible(self.pipeline.layout(), pipeline.layout())?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Records the sprites of `snapshot` into the world subpass. `camera` is
+    /// the set with the camera uniform the world pipeline uses.
+    pub(super) fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: Arc<PersistentDescriptorSet>,
+        snapshot: &RenderSnapshot,
+    ) -> Result<(), RendererError> {
+        let Some(atlas) = &self.set else {
+            return Ok(());
+        };
+        let instances = self.sprites.instances(snapshot);
+        if instances.is_empty() {
+            return Ok(());
+        }
+        let buffer = self
+            .instance_buffer
+            .allocate_slice::<SpriteInstance>(instances.len() as u64)?;
+        buffer.write()?.copy_from_slice(&instances);
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                (camera, atlas.clone()),
+            )?
+            .bind_vertex_buffers(0, (self.quad.clone(), buffer))?
+            .draw(self.quad.len() as u32, instances.len() as u32, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// The shaders compiled into the binary.
+fn built_in_shaders(device: &Arc<Device>) -> Result<ShaderStages, RendererError> {
+    ShaderStages::new(vs::load(device.clone())?, fs::load(device.clone())?)
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    shaders: ShaderStages,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let ShaderStages {
+        vertex: vs,
+        fragment: fs,
+    } = shaders;
+    let vertex_input_state = [SpriteVertex::per_vertex(), SpriteInstance::per_instance()]
+        .definition(&vs.info().input_interface)?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), WORLD_SUBPASS).unwrap();
+
+    Ok(GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: true,
+                    compare_op: CompareOp::LessOrEqual,
+                }),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ecs::Entities;
+    use nalgebra_glm::vec2;
+
+    #[test]
+    fn test_pack() {
+        let atlas = SpriteAtlas::pack(vec![
+            ("small".into(), Texture::filled(2, 2, [1, 1, 1, 1])),
+            ("tall".into(), Texture::filled(4, 8, [2, 2, 2, 2])),
+            ("wide".into(), Texture::filled(1100, 3, [3, 3, 3, 3])),
+        ]);
+        let texture = atlas.texture();
+        assert_eq!(texture.width, 1100 + 2 * SPRITE_PADDING);
+        assert_eq!(atlas.region("missing"), None);
+
+        let tall = atlas.region("tall").unwrap();
+        assert_eq!(tall.size, [4, 8]);
+        // the tallest goes first, the wide one does not fit next to it
+        assert_eq!(tall.uv_min[0], SPRITE_PADDING as f32 / texture.width as f32);
+        let wide = atlas.region("wide").unwrap();
+        assert!(wide.uv_min[1] >= tall.uv_max[1]);
+
+        // every region holds its image's pixels
+        for (name, value) in [("small", 1), ("tall", 2), ("wide", 3)] {
+            let region = atlas.region(name).unwrap();
+            let x = (region.uv_min[0] * texture.width as f32).round() as u32;
+            let y = (region.uv_min[1] * texture.height as f32).round() as u32;
+            let index = ((y * texture.width + x) * 4) as usize;
+            assert_eq!(texture.pixels[index], value, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_instances() {
+        let atlas = SpriteAtlas::pack(vec![
+            (ANT_SPRITE.into(), Texture::filled(8, 4, [255; 4])),
+            (FOOD_SPRITE.into(), Texture::filled(4, 4, [255; 4])),
+        ]);
+        let sprites = EntitySprites::from_atlas(&atlas);
+        assert_eq!(sprites.nest, None);
+
+        let entity = Entities::new().spawn();
+        let snapshot = RenderSnapshot {
+            ants: vec![AntSnapshot {
+                entity,
+                colony: 1,
+                position: vec2(3.0, 4.0),
+                heading: 1.5,
+                carrying: false,
+                selected: false,
+            }],
+            nests: vec![NestSnapshot {
+                entity,
+                colony: 1,
+                position: vec2(8.0, 8.0),
+                food: 0,
+                selected: false,
+            }],
+            food: vec![
+                FoodSnapshot {
+                    entity,
+                    position: vec2(1.0, 1.0),
+                    amount: 5,
+                },
+                FoodSnapshot {
+                    entity,
+                    position: vec2(2.0, 1.0),
+                    amount: 0,
+                },
+            ],
+            ..RenderSnapshot::default()
+        };
+        let instances = sprites.instances(&snapshot);
+        // empty food sources and nests without art are left out
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].offset, [1.0, 1.0]);
+        assert_eq!(instances[0].size, [FOOD_SIZE, FOOD_SIZE]);
+        assert_eq!(instances[1].heading, 1.5);
+        assert_eq!(instances[1].size, [ANT_SIZE, ANT_SIZE / 2.0]);
+        assert_eq!(instances[1].tint, colony_color(1));
+    }
+}
@@ -0,0 +1,275 @@
+//! Swapchain creation, and what it takes to recreate one after the window
+//! changed.
+//!
+//! Formats are chosen so colors come out the same on every platform: sRGB
+//! ones are preferred, the UI shader encodes for the others.
+
+use crate::renderer::device::Queues;
+use crate::renderer::RendererError;
+use log::warn;
+use std::str::FromStr;
+use std::sync::Arc;
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::format::{Format, NumericFormat};
+use vulkano::image::{Image, ImageUsage};
+use vulkano::swapchain::{
+    ColorSpace, CompositeAlpha, Surface, SurfaceCapabilities, Swapchain, SwapchainCreateInfo,
+};
+use winit::window::Window;
+
+/// A swapchain and its images.
+pub(super) type SwapchainImages = (Arc<Swapchain>, Vec<Arc<Image>>);
+
+/// How finished frames reach the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for the vertical blank, never tears. Always supported.
+    #[default]
+    Vsync,
+    /// Shows frames right away, may tear.
+    Immediate,
+    /// Replaces waiting frames with newer ones, never tears nor blocks.
+    Mailbox,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 3] = [
+        PresentMode::Vsync,
+        PresentMode::Immediate,
+        PresentMode::Mailbox,
+    ];
+
+    /// Name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            PresentMode::Vsync => "vsync",
+            PresentMode::Immediate => "immediate",
+            PresentMode::Mailbox => "mailbox",
+        }
+    }
+
+    fn to_vulkan(self) -> vulkano::swapchain::PresentMode {
+        match self {
+            PresentMode::Vsync => vulkano::swapchain::PresentMode::Fifo,
+            PresentMode::Immediate => vulkano::swapchain::PresentMode::Immediate,
+            PresentMode::Mailbox => vulkano::swapchain::PresentMode::Mailbox,
+        }
+    }
+}
+
+impl FromStr for PresentMode {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<PresentMode, ()> {
+        PresentMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+            .ok_or(())
+    }
+}
+
+/// The extent a swapchain for a window of `requested` pixels needs. Some
+/// platforms dictate it, others take anything between the limits. `None`
+/// while the window has no area, as when it is minimized.
+fn fit_extent(requested: [u32; 2], capabilities: &SurfaceCapabilities) -> Option<[u32; 2]> {
+    let [min, max] = [capabilities.min_image_extent, capabilities.max_image_extent];
+    fit(requested, capabilities.current_extent, min, max)
+}
+
+fn fit(
+    requested: [u32; 2],
+    current: Option<[u32; 2]>,
+    min: [u32; 2],
+    max: [u32; 2],
+) -> Option<[u32; 2]> {
+    let extent = current.unwrap_or_else(|| {
+        [
+            requested[0].clamp(min[0], max[0]),
+            requested[1].clamp(min[1], max[1]),
+        ]
+    });
+    (extent[0] > 0 && extent[1] > 0).then_some(extent)
+}
+
+/// Prefers an sRGB format in the sRGB color space, then any format in
+/// that color space, then whatever the surface lists first.
+fn choose_format(formats: &[(Format, ColorSpace)]) -> Option<(Format, ColorSpace)> {
+    let srgb = |format: &Format| format.numeric_format_color() == Some(NumericFormat::SRGB);
+    let nonlinear = |color_space| color_space == ColorSpace::SrgbNonLinear;
+    formats
+        .iter()
+        .find(|(format, color_space)| srgb(format) && nonlinear(*color_space))
+        .or_else(|| {
+            formats
+                .iter()
+                .find(|(_, color_space)| nonlinear(*color_space))
+        })
+        .or_else(|| formats.first())
+        .copied()
+}
+
+/// Images to ask for. Mailbox needs one beyond the minimum to hold the
+/// waiting frame while the next one is drawn.
+fn image_count(
+    present_mode: vulkano::swapchain::PresentMode,
+    capabilities: &SurfaceCapabilities,
+) -> u32 {
+    count_images(
+        present_mode,
+        capabilities.min_image_count,
+        capabilities.max_image_count,
+    )
+}
+
+fn count_images(present_mode: vulkano::swapchain::PresentMode, min: u32, max: Option<u32>) -> u32 {
+    if present_mode != vulkano::swapchain::PresentMode::Mailbox {
+        return min;
+    }
+    max.map_or(min + 1, |max| (min + 1).min(max))
+}
+
+/// Falls back to vsync when the surface does not support `mode`.
+fn supported_present_mode(
+    device: &Arc<Device>,
+    surface: &Surface,
+    mode: PresentMode,
+) -> Result<vulkano::swapchain::PresentMode, RendererError> {
+    let supported = device
+        .physical_device()
+        .surface_present_modes(surface, Default::default())?
+        .any(|supported| supported == mode.to_vulkan());
+    if supported {
+        Ok(mode.to_vulkan())
+    } else {
+        warn!(
+            "The surface does not support the {} present mode, using vsync",
+            mode.name()
+        );
+        Ok(PresentMode::Vsync.to_vulkan())
+    }
+}
+
+/// `None` while the window has no area, see [`fit_extent`]. The images
+/// are shared by the graphics and present queue families of `queues`.
+pub(super) fn create_swapchain(
+    device: &Arc<Device>,
+    queues: &Queues,
+    surface: Arc<Surface>,
+    extent: [u32; 2],
+    present_mode: PresentMode,
+) -> Result<Option<SwapchainImages>, RendererError> {
+    let physical_device = device.physical_device();
+    let capabilities = physical_device.surface_capabilities(&surface, Default::default())?;
+    let Some(image_extent) = fit_extent(extent, &capabilities) else {
+        return Ok(None);
+    };
+    // the window is never see-through, every surface supports some mode
+    let supported_alpha = capabilities.supported_composite_alpha;
+    let composite_alpha = if supported_alpha.contains_enum(CompositeAlpha::Opaque) {
+        CompositeAlpha::Opaque
+    } else {
+        supported_alpha.into_iter().next().unwrap()
+    };
+    let (image_format, image_color_space) =
+        choose_format(&physical_device.surface_formats(&surface, Default::default())?)
+            .ok_or(RendererError::NoSurfaceFormat)?;
+    let present_mode = supported_present_mode(device, &surface, present_mode)?;
+
+    Ok(Some(Swapchain::new(
+        device.clone(),
+        surface,
+        SwapchainCreateInfo {
+            min_image_count: image_count(present_mode, &capabilities),
+            image_format,
+            image_color_space,
+            image_extent,
+            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            image_sharing: queues.swapchain_sharing(),
+            composite_alpha,
+            present_mode,
+            ..Default::default()
+        },
+    )?))
+}
+
+/// Recreates `swapchain` with a new extent and present mode, `None` while
+/// the window has no area.
+pub(super) fn recreate_swapchain(
+    swapchain: &Arc<Swapchain>,
+    extent: [u32; 2],
+    present_mode: PresentMode,
+) -> Result<Option<SwapchainImages>, RendererError> {
+    let surface = swapchain.surface();
+    let capabilities = swapchain
+        .device()
+        .physical_device()
+        .surface_capabilities(surface, Default::default())?;
+    let Some(image_extent) = fit_extent(extent, &capabilities) else {
+        return Ok(None);
+    };
+    let present_mode = supported_present_mode(swapchain.device(), surface, present_mode)?;
+    Ok(Some(swapchain.recreate(SwapchainCreateInfo {
+        min_image_count: image_count(present_mode, &capabilities),
+        image_extent,
+        present_mode,
+        ..swapchain.create_info()
+    })?))
+}
+
+/// A new surface for the window of `lost`, whose surface is gone.
+pub(super) fn recreate_surface(lost: &Surface) -> Result<Arc<Surface>, RendererError> {
+    let window = lost
+        .object()
+        .and_then(|object| object.clone().downcast::<Window>().ok())
+        .ok_or(RendererError::NoWindow)?;
+    Ok(Surface::from_window(lost.instance().clone(), window)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_present_mode_names() {
+        for mode in PresentMode::ALL {
+            assert_eq!(mode.name().parse(), Ok(mode));
+        }
+        assert_eq!("fast".parse::<PresentMode>(), Err(()));
+    }
+
+    #[test]
+    fn test_choose_format() {
+        let nonlinear = ColorSpace::SrgbNonLinear;
+        let formats = [
+            (Format::B8G8R8A8_UNORM, nonlinear),
+            (Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear),
+            (Format::B8G8R8A8_SRGB, nonlinear),
+        ];
+        assert_eq!(choose_format(&formats), Some(formats[2]));
+        assert_eq!(choose_format(&formats[..2]), Some(formats[0]));
+        assert_eq!(choose_format(&formats[1..2]), Some(formats[1]));
+        assert_eq!(choose_format(&[]), None);
+    }
+
+    #[test]
+    fn test_image_count() {
+        use vulkano::swapchain::PresentMode::{Fifo, Mailbox};
+        assert_eq!(count_images(Fifo, 2, Some(3)), 2);
+        assert_eq!(count_images(Mailbox, 2, Some(3)), 3);
+        assert_eq!(count_images(Mailbox, 3, Some(3)), 3);
+        assert_eq!(count_images(Mailbox, 2, None), 3);
+    }
+
+    #[test]
+    fn test_fit_extent() {
+        let (min, max) = ([1, 1], [4096, 2048]);
+        assert_eq!(fit([800, 600], None, min, max), Some([800, 600]));
+        assert_eq!(fit([8000, 600], None, min, max), Some([4096, 600]));
+        assert_eq!(
+            fit([800, 600], Some([640, 480]), min, max),
+            Some([640, 480])
+        );
+        assert_eq!(fit([800, 600], Some([0, 0]), min, max), None);
+        assert_eq!(fit([0, 600], None, [0, 0], [0, 0]), None);
+    }
+}
@@ -0,0 +1,641 @@
+//! Text drawn from a glyph atlas, as labels on the screen or on the ground.
+//!
+//! Glyphs are rasterized into a coverage atlas the first time a label uses
+//! them, at [`ATLAS_PIXEL_SIZE`], and scaled to the label's size when drawn.
+//! Labels are laid out when they are handed over with
+//! [`Renderer::set_labels`](crate::renderer::Renderer::set_labels) and drawn
+//! in the overlay subpass, under the UI.
+
+use crate::renderer::device::Queues;
+use crate::renderer::pipeline::{ShaderStages, OVERLAY_SUBPASS};
+#[cfg(feature = "shader-reload")]
+use crate::renderer::reload::check_compatible;
+use crate::renderer::RendererError;
+use ab_glyph::{Font as _, FontArc, GlyphId, ScaleFont};
+use log::warn;
+use nalgebra_glm::{ortho_rh_zo, Mat4};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{RenderPass, Subpass};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/text.vert",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/text.frag",
+    }
+}
+
+/// Hack, see `assets/fonts/Hack-Regular.txt` for its license.
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../../assets/fonts/Hack-Regular.ttf");
+/// Glyphs are rasterized at this height in pixels and scaled when drawn.
+pub const ATLAS_PIXEL_SIZE: f32 = 32.0;
+const ATLAS_SIZE: u32 = 512;
+const ATLAS_FORMAT: Format = Format::R8_UNORM;
+/// Empty pixels around every glyph, so linear filtering does not pick up
+/// the neighbours.
+const GLYPH_PADDING: u32 = 1;
+
+/// A TrueType or OpenType font.
+#[derive(Clone)]
+pub struct Font(FontArc);
+
+impl Font {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Font, RendererError> {
+        Ok(Font(FontArc::try_from_vec(bytes)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Font, RendererError> {
+        Font::from_bytes(std::fs::read(path)?)
+    }
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font(FontArc::try_from_slice(DEFAULT_FONT).expect("the default font is valid"))
+    }
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Font")
+            .field("glyphs", &self.0.glyph_count())
+            .finish()
+    }
+}
+
+/// What a label's position and size are measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelSpace {
+    /// Pixels, the position is the top left corner of the text.
+    Screen,
+    /// World units on the ground, the text is centered on the position and
+    /// turns and scales with the camera.
+    World,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// Lines are separated by `\n`.
+    pub text: String,
+    pub position: [f32; 2],
+    /// Line height.
+    pub size: f32,
+    pub color: [f32; 4],
+    pub space: LabelSpace,
+}
+
+/// A glyph placed by [`layout`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PlacedGlyph {
+    id: GlyphId,
+    /// Origin on the baseline, in pixels at [`ATLAS_PIXEL_SIZE`] from the
+    /// top left of the text.
+    origin: [f32; 2],
+}
+
+/// Places the glyphs of `text` at [`ATLAS_PIXEL_SIZE`], with kerning, and
+/// returns them with the size of the text.
+fn layout(font: &FontArc, text: &str) -> (Vec<PlacedGlyph>, [f32; 2]) {
+    let font = font.as_scaled(ATLAS_PIXEL_SIZE);
+    let line_height = font.height() + font.line_gap();
+    let mut glyphs = Vec::new();
+    let mut width: f32 = 0.0;
+    let mut lines = 0;
+    for (line, text) in text.split('\n').enumerate() {
+        let baseline = font.ascent() + line as f32 * line_height;
+        let mut caret = 0.0;
+        let mut previous = None;
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            glyphs.push(PlacedGlyph {
+                id,
+                origin: [caret, baseline],
+            });
+            caret += font.h_advance(id);
+            previous = Some(id);
+        }
+        width = width.max(caret);
+        lines = line + 1;
+    }
+    let height = lines as f32 * line_height - font.line_gap();
+    (glyphs, [width, height])
+}
+
+/// Where a rasterized glyph is in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AtlasGlyph {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    /// Of the bitmap's top left corner from the glyph origin, in pixels at
+    /// [`ATLAS_PIXEL_SIZE`].
+    offset: [f32; 2],
+    size: [f32; 2],
+}
+
+/// Coverage bitmaps of every glyph used so far, packed in rows.
+struct GlyphAtlas {
+    pixels: Vec<u8>,
+    /// `None` for glyphs without an outline, such as spaces, and glyphs that
+    /// did not fit.
+    glyphs: HashMap<GlyphId, Option<AtlasGlyph>>,
+    cursor: [u32; 2],
+    row_height: u32,
+    /// Changed since the last upload.
+    dirty: bool,
+}
+
+impl GlyphAtlas {
+    fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            glyphs: HashMap::new(),
+            cursor: [GLYPH_PADDING; 2],
+            row_height: 0,
+            dirty: true,
+        }
+    }
+
+    fn glyph(&mut self, font: &FontArc, id: GlyphId) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&id) {
+            return *glyph;
+        }
+        let glyph = self.rasterize(font, id);
+        self.glyphs.insert(id, glyph);
+        glyph
+    }
+
+    fn rasterize(&mut self, font: &FontArc, id: GlyphId) -> Option<AtlasGlyph> {
+        let outlined = font.outline_glyph(id.with_scale(ATLAS_PIXEL_SIZE))?;
+        let bounds = outlined.px_bounds();
+        let width = bounds.width() as u32;
+        let height = bounds.height() as u32;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        if self.cursor[0] + width + GLYPH_PADDING > ATLAS_SIZE {
+            self.cursor = [
+                GLYPH_PADDING,
+                self.cursor[1] + self.row_height + GLYPH_PADDING,
+            ];
+            self.row_height = 0;
+        }
+        if self.cursor[1] + height + GLYPH_PADDING > ATLAS_SIZE {
+            warn!("The glyph atlas is full, glyph {:?} is not drawn", id);
+            return None;
+        }
+        let [x, y] = self.cursor;
+        outlined.draw(|dx, dy, coverage| {
+            let index = (y + dy) * ATLAS_SIZE + x + dx;
+            self.pixels[index as usize] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+        });
+        self.cursor[0] += width + GLYPH_PADDING;
+        self.row_height = self.row_height.max(height);
+        self.dirty = true;
+
+        let uv = |pixel: u32| pixel as f32 / ATLAS_SIZE as f32;
+        Some(AtlasGlyph {
+            uv_min: [uv(x), uv(y)],
+            uv_max: [uv(x + width), uv(y + height)],
+            offset: [bounds.min.x, bounds.min.y],
+            size: [width as f32, height as f32],
+        })
+    }
+}
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct TextVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+/// Two triangles per glyph of `label`, in the label's space.
+fn label_vertices(font: &FontArc, atlas: &mut GlyphAtlas, label: &Label) -> Vec<TextVertex> {
+    let (glyphs, size) = layout(font, &label.text);
+    let scale = label.size / ATLAS_PIXEL_SIZE;
+    let origin = match label.space {
+        LabelSpace::Screen => label.position,
+        LabelSpace::World => [
+            label.position[0] - size[0] * scale / 2.0,
+            label.position[1] - size[1] * scale / 2.0,
+        ],
+    };
+    let mut vertices = Vec::with_capacity(glyphs.len() * 6);
+    for placed in glyphs {
+        let Some(glyph) = atlas.glyph(font, placed.id) else {
+            continue;
+        };
+        let min = [
+            origin[0] + (placed.origin[0] + glyph.offset[0]) * scale,
+            origin[1] + (placed.origin[1] + glyph.offset[1]) * scale,
+        ];
+        let max = [
+            min[0] + glyph.size[0] * scale,
+            min[1] + glyph.size[1] * scale,
+        ];
+        let vertex = |x: usize, y: usize| TextVertex {
+            position: [[min[0], max[0]][x], [min[1], max[1]][y]],
+            uv: [
+                [glyph.uv_min[0], glyph.uv_max[0]][x],
+                [glyph.uv_min[1], glyph.uv_max[1]][y],
+            ],
+            color: label.color,
+        };
+        vertices.extend([
+            vertex(0, 0),
+            vertex(1, 0),
+            vertex(0, 1),
+            vertex(0, 1),
+            vertex(1, 0),
+            vertex(1, 1),
+        ]);
+    }
+    vertices
+}
+
+/// Maps pixels from the top left corner of an `extent` sized frame to clip
+/// space.
+fn screen_transform(extent: [u32; 2]) -> Mat4 {
+    ortho_rh_zo(
+        0.0,
+        extent[0].max(1) as f32,
+        0.0,
+        extent[1].max(1) as f32,
+        -1.0,
+        1.0,
+    )
+}
+
+pub(super) struct TextRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+    font: Font,
+    atlas: GlyphAtlas,
+    image: Arc<Image>,
+    set: Arc<PersistentDescriptorSet>,
+    screen_vertices: Vec<TextVertex>,
+    world_vertices: Vec<TextVertex>,
+    vertex_buffer: SubbufferAllocator,
+}
+
+impl TextRenderer {
+    pub(super) fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        queues: &Queues,
+    ) -> Result<TextRenderer, RendererError> {
+        let pipeline = create_pipeline(device, render_pass, built_in_shaders(device)?)?;
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: ATLAS_FORMAT,
+                extent: [ATLAS_SIZE, ATLAS_SIZE, 1],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                sharing: queues.upload_sharing(),
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+        let set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(image.clone())?,
+                sampler,
+            )],
+            [],
+        )?;
+        Ok(TextRenderer {
+            pipeline,
+            font: Font::default(),
+            atlas: GlyphAtlas::new(),
+            image,
+            set,
+            screen_vertices: Vec::new(),
+            world_vertices: Vec::new(),
+            vertex_buffer: SubbufferAllocator::new(
+                memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+        })
+    }
+
+    /// Glyphs of the old font are dropped, labels need to be set again.
+    pub(super) fn set_font(&mut self, font: Font) {
+        self.font = font;
+        self.atlas = GlyphAtlas::new();
+        self.screen_vertices.clear();
+        self.world_vertices.clear();
+    }
+
+    /// Lays out `labels` for the following draws and returns the commands
+    /// uploading the atlas, if they added glyphs to it.
+    pub(super) fn set_labels(
+        &mut self,
+        labels: &[Label],
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: &StandardCommandBufferAllocator,
+        queues: &Queues,
+    ) -> Result<Option<Arc<PrimaryAutoCommandBuffer>>, RendererError> {
+        self.screen_vertices.clear();
+        self.world_vertices.clear();
+        for label in labels {
+            let vertices = label_vertices(&self.font.0, &mut self.atlas, label);
+            match label.space {
+                LabelSpace::Screen => self.screen_vertices.extend(vertices),
+                LabelSpace::World => self.world_vertices.extend(vertices),
+            }
+        }
+        if !self.atlas.dirty {
+            return Ok(None);
+        }
+
+        let staging = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            self.atlas.pixels.iter().copied(),
+        )?;
+        let mut builder = AutoCommandBufferBuilder::primary(
+            command_buffer_allocator,
+            queues.transfer.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging,
+            self.image.clone(),
+        ))?;
+        self.atlas.dirty = false;
+        Ok(Some(builder.build()?))
+    }
+
+    /// Rebuilds the pipeline for a render pass with another color format.
+    pub(super) fn set_render_pass(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+    ) -> Result<(), RendererError> {
+        let device = self.pipeline.device().clone();
+        self.pipeline = create_pipeline(&device, render_pass, built_in_shaders(&device)?)?;
+        Ok(())
+    }
+
+    /// Replaces the pipeline with one built from `shaders`, which must use
+    /// the same descriptor sets and push constants.
+    #[cfg(feature = "shader-reload")]
+    pub(super) fn rebuild_pipeline(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        shaders: ShaderStages,
+    ) -> Result<(), RendererError> {
+        let pipeline = create_pipeline(self.pipeline.device(), render_pass, shaders)?;
+        check_compatible(self.pipeline.layout(), pipeline.layout())?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Records the labels into the overlay subpass of a frame `extent`
+    /// pixels large, seen through `view_projection`.
+    pub(super) fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        extent: [u32; 2],
+        view_projection: Mat4,
+    ) -> Result<(), RendererError> {
+        if self.screen_vertices.is_empty() && self.world_vertices.is_empty() {
+            return Ok(());
+        }
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.set.clone(),
+            )?;
+        for (vertices, transform) in [
+            (&self.world_vertices, view_projection),
+            (&self.screen_vertices, screen_transform(extent)),
+        ] {
+            if vertices.is_empty() {
+                continue;
+            }
+            let buffer = self
+                .vertex_buffer
+                .allocate_slice::<TextVertex>(vertices.len() as u64)?;
+            buffer.write()?.copy_from_slice(vertices);
+            builder
+                .push_constants(
+                    layout.clone(),
+                    0,
+                    vs::Transform {
+                        transform: transform.into(),
+                    },
+                )?
+                .bind_vertex_buffers(0, buffer)?
+                .draw(vertices.len() as u32, 1, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The shaders compiled into the binary.
+fn built_in_shaders(device: &Arc<Device>) -> Result<ShaderStages, RendererError> {
+    ShaderStages::new(vs::load(device.clone())?, fs::load(device.clone())?)
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    shaders: ShaderStages,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let ShaderStages {
+        vertex: vs,
+        fragment: fs,
+    } = shaders;
+    let vertex_input_state = TextVertex::per_vertex().definition(&vs.info().input_interface)?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), OVERLAY_SUBPASS).unwrap();
+
+    Ok(GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::vec4;
+
+    fn font() -> FontArc {
+        Font::default().0
+    }
+
+    #[test]
+    fn test_layout() {
+        let font = font();
+        let (glyphs, size) = layout(&font, "Ab\nc");
+        assert_eq!(glyphs.len(), 3);
+        let scaled = font.as_scaled(ATLAS_PIXEL_SIZE);
+        let a = scaled.glyph_id('A');
+        let b = scaled.glyph_id('b');
+        assert_eq!(glyphs[1].origin[0], scaled.h_advance(a) + scaled.kern(a, b));
+        assert_eq!(glyphs[0].origin[1], glyphs[1].origin[1]);
+        // the second line starts over, one line lower
+        assert_eq!(glyphs[2].origin[0], 0.0);
+        assert!(glyphs[2].origin[1] > glyphs[0].origin[1]);
+        assert_eq!(size[0], glyphs[1].origin[0] + scaled.h_advance(b));
+        assert!(size[1] > ATLAS_PIXEL_SIZE);
+
+        // multi-byte characters are single glyphs
+        let (glyphs, _) = layout(&font, "äö");
+        assert_eq!(glyphs.len(), 2);
+    }
+
+    #[test]
+    fn test_atlas() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new();
+        let id = font.glyph_id('A');
+        let glyph = atlas.glyph(&font, id).unwrap();
+        assert!(atlas.pixels.iter().any(|&coverage| coverage > 0));
+        assert_eq!(atlas.glyph(&font, id), Some(glyph));
+        assert_eq!(atlas.glyphs.len(), 1);
+        assert_eq!(atlas.glyph(&font, font.glyph_id(' ')), None);
+
+        let other = atlas.glyph(&font, font.glyph_id('B')).unwrap();
+        assert!(other.uv_min[0] >= glyph.uv_max[0]);
+    }
+
+    #[test]
+    fn test_label_vertices() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new();
+        let mut label = Label {
+            text: "a b".into(),
+            position: [10.0, 10.0],
+            size: 16.0,
+            color: [1.0; 4],
+            space: LabelSpace::Screen,
+        };
+        let vertices = label_vertices(&font, &mut atlas, &label);
+        // the space has no quad
+        assert_eq!(vertices.len(), 12);
+        assert!(vertices.iter().all(|vertex| vertex.position[0] >= 10.0));
+
+        label.space = LabelSpace::World;
+        let vertices = label_vertices(&font, &mut atlas, &label);
+        let min_x = vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MAX, f32::min);
+        let max_x = vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MIN, f32::max);
+        assert!(min_x < 10.0 && max_x > 10.0);
+    }
+
+    #[test]
+    fn test_screen_transform() {
+        let transform = screen_transform([800, 600]);
+        let top_left = transform * vec4(0.0, 0.0, 0.0, 1.0);
+        let bottom_right = transform * vec4(800.0, 600.0, 0.0, 1.0);
+        assert_eq!((top_left.x, top_left.y), (-1.0, -1.0));
+        assert_eq!((bottom_right.x, bottom_right.y), (1.0, 1.0));
+    }
+}
@@ -0,0 +1,175 @@
+//! Images loaded from PNG files and uploaded for sampling.
+
+use crate::renderer::device::QueueSharing;
+use crate::renderer::RendererError;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+
+/// PNG colors are sRGB encoded, the sampler decodes them.
+const FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// An RGBA image with 8 bits per channel, rows from the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Texture {
+    /// A `width` by `height` image filled with `color`.
+    pub fn filled(width: u32, height: u32, color: [u8; 4]) -> Texture {
+        Texture {
+            width,
+            height,
+            pixels: color.repeat((width * height) as usize),
+        }
+    }
+
+    /// Decodes any PNG color type and bit depth to RGBA8.
+    pub fn decode_png(bytes: &[u8]) -> Result<Texture, RendererError> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer
+                .iter()
+                .flat_map(|&gray| [gray, gray, gray, u8::MAX])
+                .collect(),
+            // expanded to RGB by the transformations
+            png::ColorType::Indexed => unreachable!(),
+        };
+        Ok(Texture {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Texture, RendererError> {
+        Texture::decode_png(&std::fs::read(path)?)
+    }
+
+    /// Creates the image and records the copy filling it into `builder`.
+    pub(super) fn upload(
+        &self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        sharing: QueueSharing,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<Arc<Image>, RendererError> {
+        self.upload_as(FORMAT, memory_allocator, sharing, builder)
+    }
+
+    /// Like [`Texture::upload`] for pixels that are not sRGB encoded,
+    /// `format` needs 4 bytes per pixel.
+    pub(super) fn upload_as(
+        &self,
+        format: Format,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        sharing: QueueSharing,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<Arc<Image>, RendererError> {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [self.width, self.height, 1],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                sharing,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        let staging = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            self.pixels.iter().copied(),
+        )?;
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))?;
+        Ok(image)
+    }
+}
+
+/// Linear filtering, clamped at the edges.
+pub(super) fn create_sampler(device: &Arc<Device>) -> Result<Arc<Sampler>, RendererError> {
+    Ok(Sampler::new(
+        device.clone(),
+        SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let rgba = encode(2, 1, png::ColorType::Rgba, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            Texture::decode_png(&rgba).unwrap(),
+            Texture {
+                width: 2,
+                height: 1,
+                pixels: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            }
+        );
+        let rgb = encode(1, 2, png::ColorType::Rgb, &[1, 2, 3, 4, 5, 6]);
+        let texture = Texture::decode_png(&rgb).unwrap();
+        assert_eq!(texture.pixels, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        let gray = encode(1, 1, png::ColorType::GrayscaleAlpha, &[9, 128]);
+        assert_eq!(
+            Texture::decode_png(&gray).unwrap().pixels,
+            vec![9, 9, 9, 128]
+        );
+        assert!(Texture::decode_png(b"not a png").is_err());
+    }
+}
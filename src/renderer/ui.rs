@@ -0,0 +1,485 @@
+//! Draws egui output in the overlay subpass.
+//!
+//! The client runs egui and hands the tessellated result over as a
+//! [`UiFrame`]. Textures arrive as deltas: new and changed ones are uploaded
+//! right away, ahead of the next frame, freed ones are dropped once the
+//! frame that still used them was recorded.
+
+use crate::renderer::device::{QueueSharing, Queues};
+use crate::renderer::pipeline::{ShaderStages, OVERLAY_SUBPASS};
+#[cfg(feature = "shader-reload")]
+use crate::renderer::reload::check_compatible;
+use crate::renderer::RendererError;
+use egui::epaint::textures::TexturesDelta;
+use egui::epaint::{ImageDelta, Primitive, TextureId};
+use egui::{ClippedPrimitive, ImageData, Rect, TextureFilter, TextureOptions};
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyBufferToImageInfo,
+    PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::format::{Format, NumericFormat};
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Scissor, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{RenderPass, Subpass};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/ui.vert",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/ui.frag",
+    }
+}
+
+/// Textures are kept as egui sends them, sRGB encoded but sampled raw.
+const TEXTURE_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// What the client's UI wants drawn this frame.
+#[derive(Debug, Clone, Default)]
+pub struct UiFrame {
+    pub primitives: Vec<ClippedPrimitive>,
+    pub textures: TexturesDelta,
+    /// Physical pixels per egui point.
+    pub pixels_per_point: f32,
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct UiVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+    #[format(R8G8B8A8_UNORM)]
+    color: [u8; 4],
+}
+
+struct UiTexture {
+    image: Arc<Image>,
+    set: Arc<PersistentDescriptorSet>,
+}
+
+pub(super) struct UiRenderer {
+    pipeline: Arc<GraphicsPipeline>,
+    linear_output: bool,
+    textures: HashMap<TextureId, UiTexture>,
+    /// Freed by egui, dropped after the next frame is recorded.
+    freed: Vec<TextureId>,
+    primitives: Vec<ClippedPrimitive>,
+    pixels_per_point: f32,
+    vertex_buffer: SubbufferAllocator,
+    index_buffer: SubbufferAllocator,
+}
+
+impl UiRenderer {
+    /// Color attachments with an sRGB format get linear colors written to
+    /// them.
+    pub(super) fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+    ) -> Result<UiRenderer, RendererError> {
+        let format = render_pass.attachments()[0].format;
+        let host_buffer = |buffer_usage| {
+            SubbufferAllocator::new(
+                memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            )
+        };
+        Ok(UiRenderer {
+            pipeline: create_pipeline(device, render_pass, built_in_shaders(device)?)?,
+            linear_output: format.numeric_format_color() == Some(NumericFormat::SRGB),
+            textures: HashMap::new(),
+            freed: Vec::new(),
+            primitives: Vec::new(),
+            pixels_per_point: 1.0,
+            vertex_buffer: host_buffer(BufferUsage::VERTEX_BUFFER),
+            index_buffer: host_buffer(BufferUsage::INDEX_BUFFER),
+        })
+    }
+
+    /// Takes over `frame` for the following draws and returns the commands
+    /// uploading its textures, if it changed any.
+    pub(super) fn update(
+        &mut self,
+        frame: UiFrame,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: &StandardCommandBufferAllocator,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        queues: &Queues,
+    ) -> Result<Option<Arc<PrimaryAutoCommandBuffer>>, RendererError> {
+        for id in self.freed.drain(..) {
+            self.textures.remove(&id);
+        }
+        self.primitives = frame.primitives;
+        self.pixels_per_point = frame.pixels_per_point;
+        self.freed = frame.textures.free;
+        if frame.textures.set.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            command_buffer_allocator,
+            queues.transfer.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let sharing = queues.upload_sharing();
+        for (id, delta) in frame.textures.set {
+            let upload = self.set_texture(
+                id,
+                delta,
+                memory_allocator,
+                descriptor_set_allocator,
+                &sharing,
+            )?;
+            if let Some(upload) = upload {
+                builder.copy_buffer_to_image(upload)?;
+            }
+        }
+        Ok(Some(builder.build()?))
+    }
+
+    /// Creates the texture for a whole image, or finds the one a patch goes
+    /// into, and returns the copy filling it in.
+    fn set_texture(
+        &mut self,
+        id: TextureId,
+        delta: ImageDelta,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        sharing: &QueueSharing,
+    ) -> Result<Option<CopyBufferToImageInfo>, RendererError> {
+        let [width, height] = delta.image.size().map(|size| size as u32);
+        if width == 0 || height == 0 {
+            return Ok(None);
+        }
+        let image = match delta.pos {
+            Some(_) => match self.textures.get(&id) {
+                Some(texture) => texture.image.clone(),
+                None => return Ok(None),
+            },
+            None => {
+                let image = Image::new(
+                    memory_allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format: TEXTURE_FORMAT,
+                        extent: [width, height, 1],
+                        usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                        sharing: sharing.clone(),
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )?;
+                let sampler = Sampler::new(
+                    memory_allocator.device().clone(),
+                    sampler_create_info(delta.options),
+                )?;
+                let set = PersistentDescriptorSet::new(
+                    descriptor_set_allocator,
+                    self.pipeline.layout().set_layouts()[0].clone(),
+                    [WriteDescriptorSet::image_view_sampler(
+                        0,
+                        ImageView::new_default(image.clone())?,
+                        sampler,
+                    )],
+                    [],
+                )?;
+                self.textures.insert(
+                    id,
+                    UiTexture {
+                        image: image.clone(),
+                        set,
+                    },
+                );
+                image
+            }
+        };
+
+        let staging = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            pixels(&delta.image),
+        )?;
+        let [x, y] = delta.pos.unwrap_or([0, 0]).map(|position| position as u32);
+        Ok(Some(CopyBufferToImageInfo {
+            regions: [BufferImageCopy {
+                image_subresource: image.subresource_layers(),
+                image_offset: [x, y, 0],
+                image_extent: [width, height, 1],
+                ..Default::default()
+            }]
+            .into_iter()
+            .collect(),
+            ..CopyBufferToImageInfo::buffer_image(staging, image)
+        }))
+    }
+
+    /// Rebuilds the pipeline for a render pass with another color format,
+    /// see [`UiRenderer::new`].
+    pub(super) fn set_render_pass(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+    ) -> Result<(), RendererError> {
+        let device = self.pipeline.device().clone();
+        self.pipeline = create_pipeline(&device, render_pass, built_in_shaders(&device)?)?;
+        let format = render_pass.attachments()[0].format;
+        self.linear_output = format.numeric_format_color() == Some(NumericFormat::SRGB);
+        Ok(())
+    }
+
+    /// Replaces the pipeline with one built from `shaders`, which must use
+    /// the same descriptor sets and push constants.
+    #[cfg(feature = "shader-reload")]
+    pub(super) fn rebuild_pipeline(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        shaders: ShaderStages,
+    ) -> Result<(), RendererError> {
+        let pipeline = create_pipeline(self.pipeline.device(), render_pass, shaders)?;
+        check_compatible(self.pipeline.layout(), pipeline.layout())?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Records the UI into the overlay subpass of a frame `extent` pixels
+    /// large.
+    pub(super) fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        extent: [u32; 2],
+    ) -> Result<(), RendererError> {
+        if self.primitives.is_empty() {
+            return Ok(());
+        }
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .push_constants(
+                layout.clone(),
+                0,
+                vs::Screen {
+                    size: extent.map(|size| size as f32 / self.pixels_per_point),
+                    linear_output: self.linear_output as u32,
+                },
+            )?;
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &self.primitives
+        {
+            // paint callbacks are not supported
+            let Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                continue;
+            };
+            let Some(scissor) = scissor(clip_rect, self.pixels_per_point, extent) else {
+                continue;
+            };
+            if mesh.indices.is_empty() {
+                continue;
+            }
+            let vertices = self
+                .vertex_buffer
+                .allocate_slice::<UiVertex>(mesh.vertices.len() as u64)?;
+            for (slot, vertex) in vertices.write()?.iter_mut().zip(&mesh.vertices) {
+                *slot = UiVertex {
+                    position: [vertex.pos.x, vertex.pos.y],
+                    uv: [vertex.uv.x, vertex.uv.y],
+                    color: vertex.color.to_array(),
+                };
+            }
+            let indices = self
+                .index_buffer
+                .allocate_slice::<u32>(mesh.indices.len() as u64)?;
+            indices.write()?.copy_from_slice(&mesh.indices);
+            builder
+                .set_scissor(0, [scissor].into_iter().collect())?
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    layout.clone(),
+                    0,
+                    texture.set.clone(),
+                )?
+                .bind_vertex_buffers(0, vertices)?
+                .bind_index_buffer(indices)?
+                .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+fn pixels(image: &ImageData) -> Vec<u8> {
+    match image {
+        ImageData::Color(image) => image
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .collect(),
+        ImageData::Font(image) => image
+            .srgba_pixels(None)
+            .flat_map(|pixel| pixel.to_array())
+            .collect(),
+    }
+}
+
+fn sampler_create_info(options: TextureOptions) -> SamplerCreateInfo {
+    let filter = |filter| match filter {
+        TextureFilter::Nearest => Filter::Nearest,
+        TextureFilter::Linear => Filter::Linear,
+    };
+    SamplerCreateInfo {
+        mag_filter: filter(options.magnification),
+        min_filter: filter(options.minification),
+        address_mode: [SamplerAddressMode::ClampToEdge; 3],
+        ..Default::default()
+    }
+}
+
+/// `clip_rect` in pixels, cut to the frame. `None` if nothing is left.
+fn scissor(clip_rect: &Rect, pixels_per_point: f32, extent: [u32; 2]) -> Option<Scissor> {
+    let clamp =
+        |points: f32, size: u32| (points * pixels_per_point).round().clamp(0.0, size as f32);
+    let min = [
+        clamp(clip_rect.min.x, extent[0]),
+        clamp(clip_rect.min.y, extent[1]),
+    ];
+    let max = [
+        clamp(clip_rect.max.x, extent[0]),
+        clamp(clip_rect.max.y, extent[1]),
+    ];
+    if max[0] <= min[0] || max[1] <= min[1] {
+        return None;
+    }
+    Some(Scissor {
+        offset: min.map(|min| min as u32),
+        extent: [(max[0] - min[0]) as u32, (max[1] - min[1]) as u32],
+    })
+}
+
+/// The shaders compiled into the binary.
+fn built_in_shaders(device: &Arc<Device>) -> Result<ShaderStages, RendererError> {
+    ShaderStages::new(vs::load(device.clone())?, fs::load(device.clone())?)
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    render_pass: &Arc<RenderPass>,
+    shaders: ShaderStages,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let ShaderStages {
+        vertex: vs,
+        fragment: fs,
+    } = shaders;
+    let vertex_input_state = UiVertex::per_vertex().definition(&vs.info().input_interface)?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), OVERLAY_SUBPASS).unwrap();
+    // egui colors are premultiplied
+    let blend = AttachmentBlend {
+        src_color_blend_factor: BlendFactor::One,
+        dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+        color_blend_op: BlendOp::Add,
+        src_alpha_blend_factor: BlendFactor::OneMinusDstAlpha,
+        dst_alpha_blend_factor: BlendFactor::One,
+        alpha_blend_op: BlendOp::Add,
+    };
+
+    Ok(GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(blend),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::pos2;
+
+    #[test]
+    fn test_scissor() {
+        let rect = Rect::from_min_max(pos2(10.0, 20.0), pos2(50.0, 40.0));
+        let clipped = scissor(&rect, 2.0, [800, 600]).unwrap();
+        assert_eq!(clipped.offset, [20, 40]);
+        assert_eq!(clipped.extent, [80, 40]);
+
+        // egui clips to an infinite rect when nothing limits it
+        let everything = scissor(&Rect::EVERYTHING, 1.0, [800, 600]).unwrap();
+        assert_eq!(everything.offset, [0, 0]);
+        assert_eq!(everything.extent, [800, 600]);
+
+        let outside = Rect::from_min_max(pos2(900.0, 0.0), pos2(950.0, 10.0));
+        assert!(scissor(&outside, 1.0, [800, 600]).is_none());
+    }
+}
@@ -1,48 +1,439 @@
-use std::sync::mpsc::Receiver;
-use std::net::SocketAddr;
+use crate::shared::game::Command;
+use crate::shared::protocols::{GameClientMessages, GameServerMessages, PlayerId, TICK_HISTORY};
+use crate::shared::timestep::{FixedTimestep, TimestepSettings};
+use log::{debug, error, warn};
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
-pub fn game_server(udp_addr: SocketAddr, stop: Receiver<()>) {
-    let udp_socket = std::net::UdpSocket::bind(udp_addr).unwrap();
+/// How often the tick statistics are logged, in seconds.
+const STATS_INTERVAL: u64 = 10;
+/// Clients that did not send anything for this long stop getting ticks.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the server sleeps between polls before the match starts.
+const IDLE_INTERVAL: Duration = Duration::from_millis(10);
+/// Most ticks answered for one [`GameClientMessages::Resend`].
+const RESEND_LIMIT: u64 = 64;
+/// Budget for the commands of one [`GameServerMessages::Tick`], leaves room
+/// for its header within the 65507 bytes of a UDP datagram.
+const MAX_TICK_BYTES: u64 = 60 * 1024;
+/// Commands a player may have waiting for later ticks, more are dropped.
+const MAX_QUEUED_COMMANDS: usize = 64;
+/// The server tick a match starts after, see
+/// [`LobbyServerMessages::MatchStarting`](crate::shared::protocols::LobbyServerMessages::MatchStarting).
+pub const START_TICK: u64 = 0;
+
+/// Lobby to game server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameServerControl {
+    /// Starts ticking from [`START_TICK`] for the players with these
+    /// tokens.
+    Start(Vec<(PlayerId, u64)>),
+    Stop,
+}
+
+struct Client {
+    addr: SocketAddr,
+    player: PlayerId,
+    last_seen: Instant,
+}
+
+/// The broadcast ticks, the newest [`TICK_HISTORY`] of them serialized.
+struct History {
+    ticks: VecDeque<Vec<u8>>,
+    /// The tick at the front.
+    first: u64,
+}
+
+impl History {
+    fn push(&mut self, tick: u64, bytes: Vec<u8>) {
+        if self.ticks.is_empty() {
+            self.first = tick;
+        }
+        self.ticks.push_back(bytes);
+        if self.ticks.len() as u64 > TICK_HISTORY {
+            self.ticks.pop_front();
+            self.first += 1;
+        }
+    }
+
+    fn get(&self, tick: u64) -> Option<&Vec<u8>> {
+        self.ticks.get(tick.checked_sub(self.first)? as usize)
+    }
+}
+
+/// Commands waiting for a tick. Every player gets an equal share of
+/// [`MAX_TICK_BYTES`] per tick, whatever doesn't fit waits for the next one.
+struct CommandQueues {
+    /// Serialized bytes of commands one player may put into a tick.
+    budget: u64,
+    queues: Vec<(PlayerId, VecDeque<(Command, u64)>)>,
+}
+
+impl CommandQueues {
+    fn new(players: impl IntoIterator<Item = PlayerId>) -> CommandQueues {
+        let queues: Vec<_> = players
+            .into_iter()
+            .map(|player| (player, VecDeque::new()))
+            .collect();
+        CommandQueues {
+            budget: MAX_TICK_BYTES / queues.len().max(1) as u64,
+            queues,
+        }
+    }
+
+    /// Refuses commands larger than a player's share and commands of
+    /// players with a full queue.
+    fn push(&mut self, command: Command) -> Result<(), String> {
+        let size = bincode::serialized_size(&command).unwrap();
+        if size > self.budget {
+            return Err(format!(
+                "command of {} bytes exceeds the {} bytes per tick",
+                size, self.budget
+            ));
+        }
+        let Some((_, queue)) = self
+            .queues
+            .iter_mut()
+            .find(|(player, _)| *player == command.player)
+        else {
+            return Err(format!("player {} is not in the match", command.player));
+        };
+        if queue.len() >= MAX_QUEUED_COMMANDS {
+            return Err(format!("{} commands are already queued", queue.len()));
+        }
+        queue.push_back((command, size));
+        Ok(())
+    }
+
+    /// Commands of the next tick in player order, each player within its
+    /// budget.
+    fn next_tick(&mut self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for (_, queue) in self.queues.iter_mut() {
+            let mut used = 0;
+            while let Some((command, size)) = queue.pop_front() {
+                if used + size > self.budget {
+                    queue.push_front((command, size));
+                    break;
+                }
+                used += size;
+                commands.push(command);
+            }
+        }
+        commands
+    }
+}
+
+/// Serves one match. Nothing is ticked before [`GameServerControl::Start`],
+/// after it only datagrams from players that joined with their token count.
+pub fn game_server(
+    udp_addr: SocketAddr,
+    settings: TimestepSettings,
+    control: Receiver<GameServerControl>,
+) {
+    let udp_socket = UdpSocket::bind(udp_addr).unwrap();
     udp_socket.set_nonblocking(true).unwrap();
-    let mut buf = [0; 1024];
-    while stop.try_recv().is_err() {
-        match udp_socket.recv_from(&mut buf) {
-            Ok((size, src)) => {
-                let message = &buf[..size];
-                println!("Received: {:?}", String::from_utf8_lossy(message));
-                udp_socket.send_to(message, src).expect("Failed to send a response");
+    let mut buf = [0; 65536];
+    let mut timestep = FixedTimestep::new(settings);
+    let mut tick = START_TICK;
+    let mut tokens: Vec<(PlayerId, u64)> = Vec::new();
+    let mut running = false;
+    let mut clients: Vec<Client> = Vec::new();
+    let mut commands = CommandQueues::new([]);
+    let mut history = History {
+        ticks: VecDeque::new(),
+        first: 0,
+    };
+    loop {
+        match control.try_recv() {
+            Ok(GameServerControl::Start(players)) => {
+                debug!("Game server on {}: match started", udp_addr);
+                commands = CommandQueues::new(players.iter().map(|(player, _)| *player));
+                tokens = players;
+                tick = START_TICK;
+                timestep = FixedTimestep::new(settings);
+                running = true;
             }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No data received, just continue the loop
-                continue;
+            Ok(GameServerControl::Stop) | Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        // handle everything that arrived since the last tick
+        loop {
+            match udp_socket.recv_from(&mut buf) {
+                Ok((size, src)) => {
+                    let message = match bincode::deserialize(&buf[..size]) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            warn!("Invalid message from {}: {}", src, e);
+                            continue;
+                        }
+                    };
+                    if let GameClientMessages::Join { player, token } = message {
+                        if tokens.contains(&(player, token)) {
+                            debug!("Game server on {}: {} joined as {}", udp_addr, src, player);
+                            clients.retain(|client| client.player != player);
+                            clients.push(Client {
+                                addr: src,
+                                player,
+                                last_seen: Instant::now(),
+                            });
+                        } else {
+                            warn!("{} failed to join as player {}", src, player);
+                        }
+                        continue;
+                    }
+                    let Some(client) = clients.iter_mut().find(|client| client.addr == src) else {
+                        debug!("Ignoring a message from unknown {}", src);
+                        continue;
+                    };
+                    client.last_seen = Instant::now();
+                    match message {
+                        GameClientMessages::Join { .. } | GameClientMessages::KeepAlive => {}
+                        GameClientMessages::Command(mut command) => {
+                            command.player = client.player;
+                            if let Err(e) = commands.push(command) {
+                                warn!("Dropped a command from {}: {}", src, e);
+                            }
+                        }
+                        GameClientMessages::Ping(number) => {
+                            let bytes =
+                                bincode::serialize(&GameServerMessages::Pong(number)).unwrap();
+                            if let Err(e) = udp_socket.send_to(&bytes, src) {
+                                warn!("Failed to answer ping from {}: {}", src, e);
+                            }
+                        }
+                        GameClientMessages::Resend { first, last } => {
+                            let last = last.min(first.saturating_add(RESEND_LIMIT - 1));
+                            for bytes in (first..=last).filter_map(|tick| history.get(tick)) {
+                                if let Err(e) = udp_socket.send_to(bytes, src) {
+                                    warn!("Failed to resend ticks to {}: {}", src, e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("recv_from error: {}", e);
+                    return;
+                }
             }
-            Err(e) => {
-                eprintln!("recv_from error: {}", e);
-                break;
+        }
+
+        if !running {
+            std::thread::sleep(IDLE_INTERVAL);
+            continue;
+        }
+        for _ in 0..timestep.advance(Instant::now()) {
+            let started = Instant::now();
+            tick += 1;
+            clients.retain(|client| client.last_seen.elapsed() < CLIENT_TIMEOUT);
+            let message = GameServerMessages::Tick {
+                tick,
+                commands: commands.next_tick(),
+            };
+            let bytes = bincode::serialize(&message).unwrap();
+            for client in clients.iter() {
+                if let Err(e) = udp_socket.send_to(&bytes, client.addr) {
+                    warn!("Failed to send tick {} to {}: {}", tick, client.addr, e);
+                }
+            }
+            history.push(tick, bytes);
+            timestep.record_tick(started.elapsed());
+            if tick.is_multiple_of(settings.tick_rate as u64 * STATS_INTERVAL) {
+                debug!("Game server on {}: {:?}", udp_addr, timestep.stats());
             }
         }
+        std::thread::sleep(timestep.time_until_next_tick(Instant::now()));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::mpsc::channel;
+    use crate::shared::ecs::Entities;
+    use crate::shared::fixed::FixedVec2;
+    use crate::shared::game::CommandKind;
     use crate::utils::ADDRESSES;
+    use std::sync::mpsc::channel;
 
     #[test]
     fn test_game_server() {
         let (tx, rx) = channel();
         let udp_addr: SocketAddr = ADDRESSES[0].parse().unwrap();
         let handle = std::thread::spawn(move || {
-            game_server(udp_addr, rx);
+            game_server(udp_addr, TimestepSettings::default(), rx);
+        });
+
+        let udp_socket = UdpSocket::bind(ADDRESSES[1]).unwrap();
+        udp_socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let send = |message: &GameClientMessages| {
+            let bytes = bincode::serialize(message).unwrap();
+            udp_socket.send_to(&bytes, udp_addr).unwrap();
+        };
+        let receive = || {
+            let mut buf = [0; 65536];
+            let (size, _) = udp_socket.recv_from(&mut buf).ok()?;
+            bincode::deserialize::<GameServerMessages>(&buf[..size]).ok()
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        // nothing is ticked before the match starts
+        send(&GameClientMessages::Join {
+            player: 1,
+            token: 7,
         });
+        assert_eq!(receive(), None);
+        tx.send(GameServerControl::Start(vec![(0, 3), (1, 7)]))
+            .unwrap();
 
-        let udp_socket = std::net::UdpSocket::bind(ADDRESSES[1]).unwrap();
+        // a wrong token and garbage are ignored
+        send(&GameClientMessages::Join {
+            player: 0,
+            token: 7,
+        });
         udp_socket.send_to(b"Hello, world!", udp_addr).unwrap();
+        send(&GameClientMessages::Ping(1));
+        assert_eq!(receive(), None, "answered an unregistered ping");
+        send(&GameClientMessages::Join {
+            player: 1,
+            token: 7,
+        });
+        let Some(GameServerMessages::Tick { tick: first, .. }) = receive() else {
+            panic!("expected a tick");
+        };
+        assert!(
+            first <= START_TICK + 10,
+            "tick {} long after the start",
+            first
+        );
+
+        // commands are from the joined player, and more than fit 1024 bytes
+        let mut entities = Entities::new();
+        let command = Command {
+            player: 0,
+            kind: CommandKind::Move {
+                ants: (0..200).map(|_| entities.spawn()).collect(),
+                target: FixedVec2::from_int(3, 4),
+            },
+        };
+        send(&GameClientMessages::Ping(7));
+        send(&GameClientMessages::Command(command.clone()));
+        let mut tick = first;
+        let mut pong = None;
+        let confirmed = loop {
+            let (next, commands) = match receive().unwrap() {
+                GameServerMessages::Tick { tick, commands } => (tick, commands),
+                GameServerMessages::Pong(number) => {
+                    pong = Some(number);
+                    continue;
+                }
+            };
+            assert_eq!(next, tick + 1);
+            tick = next;
+            if !commands.is_empty() {
+                let expected = Command {
+                    player: 1,
+                    ..command.clone()
+                };
+                assert_eq!(commands, vec![expected]);
+                break tick;
+            }
+        };
+        assert_eq!(pong, Some(7));
+
+        // lost ticks are sent again
+        send(&GameClientMessages::Resend {
+            first: confirmed,
+            last: confirmed,
+        });
+        let resent = loop {
+            match receive().unwrap() {
+                GameServerMessages::Tick { tick, commands } if tick == confirmed => break commands,
+                _ => {}
+            }
+        };
+        assert_eq!(resent.len(), 1);
 
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        tx.send(()).unwrap();
+        // a batch too large for one datagram is spread over several ticks
+        let batch = MAX_TICK_BYTES / bincode::serialized_size(&command).unwrap() * 3 / 2;
+        for _ in 0..batch {
+            send(&GameClientMessages::Command(command.clone()));
+        }
+        let mut received = 0;
+        let mut ticks = 0;
+        while received < batch {
+            if let GameServerMessages::Tick { commands, .. } = receive().unwrap() {
+                received += commands.len() as u64;
+                ticks += usize::from(!commands.is_empty());
+            }
+        }
+        assert_eq!(received, batch);
+        assert!(ticks >= 2);
+
+        tx.send(GameServerControl::Stop).unwrap();
         handle.join().unwrap();
     }
+
+    #[test]
+    fn test_command_queues() {
+        let mut entities = Entities::new();
+        let mut command = |player, ants| Command {
+            player,
+            kind: CommandKind::Move {
+                ants: (0..ants).map(|_| entities.spawn()).collect(),
+                target: FixedVec2::from_int(3, 4),
+            },
+        };
+        let mut queues = CommandQueues::new([0, 1]);
+        assert!(queues.push(command(0, 5000)).is_err());
+        assert!(queues.push(command(2, 1)).is_err());
+        let large = command(0, 1000);
+        for _ in 0..MAX_QUEUED_COMMANDS {
+            queues.push(large.clone()).unwrap();
+        }
+        assert!(queues.push(large.clone()).is_err());
+        queues.push(command(1, 1)).unwrap();
+
+        // player 1 gets its command through while player 0 drains its queue
+        let size = bincode::serialized_size(&large).unwrap();
+        let per_tick = (queues.budget / size) as usize;
+        let first = queues.next_tick();
+        assert_eq!(first.len(), per_tick + 1);
+        assert_eq!(first.last().unwrap().player, 1);
+        let message = GameServerMessages::Tick {
+            tick: 1,
+            commands: first,
+        };
+        assert!(bincode::serialized_size(&message).unwrap() < 65507);
+        let mut drained = per_tick;
+        while drained < MAX_QUEUED_COMMANDS {
+            let commands = queues.next_tick();
+            assert!(!commands.is_empty());
+            drained += commands.len();
+        }
+        assert_eq!(drained, MAX_QUEUED_COMMANDS);
+        assert!(queues.next_tick().is_empty());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History {
+            ticks: VecDeque::new(),
+            first: 0,
+        };
+        for tick in 1..=TICK_HISTORY + 2 {
+            history.push(tick, tick.to_le_bytes().to_vec());
+        }
+        assert_eq!(history.get(1), None);
+        assert_eq!(history.get(2), None);
+        assert_eq!(history.get(3), Some(&3u64.to_le_bytes().to_vec()));
+        assert_eq!(history.get(TICK_HISTORY + 2).map(Vec::len), Some(8));
+        assert_eq!(history.get(TICK_HISTORY + 3), None);
+    }
 }
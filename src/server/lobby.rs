@@ -1,51 +1,463 @@
 use log::{debug, error, info};
 
-use crate::server::game_server::game_server;
-use std::net::SocketAddr;
-use std::sync::mpsc::Receiver;
+use crate::server::game_server::{game_server, GameServerControl, START_TICK};
+use crate::shared::map::Map;
+use crate::shared::protocols::{LobbyClientMessages, LobbyServerMessages, PlayerId, PlayerInfo};
+use crate::shared::timestep::TimestepSettings;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+struct Player {
+    info: PlayerInfo,
+    stream: TcpStream,
+    /// Confirmed to have a map with the lobby's hash.
+    has_map: bool,
+}
+
+struct Lobby {
+    game_port: u16,
+    game_server: Sender<GameServerControl>,
+    /// Name the map was loaded by, clients look it up under the same name.
+    map_name: String,
+    map: Map,
+    map_hash: u64,
+    players: Vec<Player>,
+    seed: Option<u64>,
+}
+
+impl Lobby {
+    fn new(
+        game_port: u16,
+        game_server: Sender<GameServerControl>,
+        map_name: String,
+        map: Map,
+    ) -> Lobby {
+        Lobby {
+            game_port,
+            game_server,
+            map_name,
+            map_hash: map.hash(),
+            map,
+            players: Vec::new(),
+            seed: None,
+        }
+    }
+
+    fn roster(&self) -> Vec<PlayerInfo> {
+        self.players
+            .iter()
+            .map(|player| player.info.clone())
+            .collect()
+    }
+
+    /// The lowest id nobody in the lobby has, ids of players who left are
+    /// handed out again.
+    fn free_id(&self) -> Option<PlayerId> {
+        (0..=PlayerId::MAX).find(|&id| self.players.iter().all(|player| player.info.id != id))
+    }
+
+    fn host(&self) -> Option<PlayerId> {
+        self.players.first().map(|player| player.info.id)
+    }
+
+    fn player_mut(&mut self, id: PlayerId) -> Option<&mut Player> {
+        self.players.iter_mut().find(|player| player.info.id == id)
+    }
+
+    fn broadcast(&mut self, message: &LobbyServerMessages) {
+        let bytes = bincode::serialize(message).unwrap();
+        for player in self.players.iter_mut() {
+            if let Err(e) = player.stream.write_all(&bytes) {
+                error!("Failed to send to player {}: {}", player.info.id, e);
+            }
+        }
+    }
+}
+
+/// Picks the seed for the match. Only the lobby calls this, every peer gets
+/// the result with the roster.
+fn match_seed() -> u64 {
+    random()
+}
+
+/// Picks the secret a player joins the game server with.
+fn player_token() -> u64 {
+    random()
+}
+
+/// Not deterministic, only for what the lobby decides for everyone.
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(time) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    hasher.finish()
+}
 
 pub fn lobby_code(
     tcp_addr: SocketAddr,
     udp_addr: SocketAddr,
+    map_name: String,
+    map: Map,
+    timestep: TimestepSettings,
     stop: Receiver<()>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // open game server thread
     let (tx, rx) = std::sync::mpsc::channel();
-    let game_server = std::thread::spawn(move || {
-        game_server(udp_addr, rx);
+    let game_server_thread = std::thread::spawn(move || {
+        game_server(udp_addr, timestep, rx);
     });
 
     // open tcp
     let tcp_listener = std::net::TcpListener::bind(tcp_addr)?;
+    tcp_listener.set_nonblocking(true)?;
+    info!("Lobby on {} plays {}", tcp_addr, map.name());
+    let lobby = Arc::new(Mutex::new(Lobby::new(
+        udp_addr.port(),
+        tx.clone(),
+        map_name,
+        map,
+    )));
     while stop.try_recv().is_err() {
-        let streams = tcp_listener.incoming().take_while(|v| v.is_ok());
+        match tcp_listener.accept() {
+            Ok((stream, addr)) => {
+                debug!("Lobby connection from {}", addr);
+                stream.set_nonblocking(false)?;
+                let lobby = lobby.clone();
+                std::thread::spawn(move || {
+                    handle_stream(stream, lobby);
+                });
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
-    tx.send(()).unwrap();
+    tx.send(GameServerControl::Stop).unwrap();
     info!("Waiting for game server to finish");
-    game_server
+    game_server_thread
         .join()
         .expect("Failed to join game server thread");
     Ok(())
 }
 
+fn handle_stream(mut stream: TcpStream, lobby: Arc<Mutex<Lobby>>) {
+    let mut player = None;
+    while let Ok(message) = bincode::deserialize_from(stream.try_clone().unwrap()) {
+        match message {
+            LobbyClientMessages::Join { name } => {
+                if player.is_some() {
+                    continue;
+                }
+                let mut lock = lobby.lock().unwrap();
+                let id = if lock.seed.is_some() {
+                    Err("the match is already running")
+                } else if lock.players.len() >= lock.map.max_players() {
+                    Err("the lobby is full")
+                } else {
+                    lock.free_id().ok_or("no player ids are left")
+                };
+                let id = match id {
+                    Ok(id) => id,
+                    Err(reason) => {
+                        error!("{} can not join: {}", name, reason);
+                        let message = LobbyServerMessages::Refused(reason.to_string());
+                        let _ = stream.write_all(&bincode::serialize(&message).unwrap());
+                        break;
+                    }
+                };
+                let joined = bincode::serialize(&LobbyServerMessages::Joined(id)).unwrap();
+                let map = bincode::serialize(&LobbyServerMessages::Map {
+                    name: lock.map_name.clone(),
+                    hash: lock.map_hash,
+                })
+                .unwrap();
+                if stream.write_all(&joined).is_err() || stream.write_all(&map).is_err() {
+                    break;
+                }
+                info!("{} joined as player {}", name, id);
+                lock.players.push(Player {
+                    info: PlayerInfo {
+                        id,
+                        name,
+                        ready: false,
+                    },
+                    stream: stream.try_clone().unwrap(),
+                    has_map: false,
+                });
+                let roster = LobbyServerMessages::Roster(lock.roster());
+                lock.broadcast(&roster);
+                player = Some(id);
+            }
+            LobbyClientMessages::HasMap { hash } => {
+                let Some(id) = player else {
+                    continue;
+                };
+                let mut lock = lobby.lock().unwrap();
+                let matches = hash == lock.map_hash;
+                if !matches {
+                    error!("Player {} has a different version of the map", id);
+                }
+                if let Some(player) = lock.player_mut(id) {
+                    player.has_map = matches;
+                }
+            }
+            LobbyClientMessages::Ready(ready) => {
+                let Some(id) = player else {
+                    continue;
+                };
+                let mut lock = lobby.lock().unwrap();
+                if lock.seed.is_some() {
+                    continue;
+                }
+                if let Some(player) = lock.player_mut(id) {
+                    player.info.ready = ready;
+                }
+                let roster = LobbyServerMessages::Roster(lock.roster());
+                lock.broadcast(&roster);
+            }
+            LobbyClientMessages::StartMatch => {
+                let mut lock = lobby.lock().unwrap();
+                if player.is_none() || lock.host() != player || lock.seed.is_some() {
+                    continue;
+                }
+                if let Some(missing) = lock.players.iter().find(|player| !player.has_map) {
+                    info!("Not starting, player {} lacks the map", missing.info.id);
+                    continue;
+                }
+                if let Some(waiting) = lock.players.iter().find(|player| !player.info.ready) {
+                    info!("Not starting, player {} is not ready", waiting.info.id);
+                    continue;
+                }
+                let seed = match_seed();
+                let tokens: Vec<(PlayerId, u64)> = lock
+                    .players
+                    .iter()
+                    .map(|player| (player.info.id, player_token()))
+                    .collect();
+                if lock
+                    .game_server
+                    .send(GameServerControl::Start(tokens.clone()))
+                    .is_err()
+                {
+                    error!("The game server is gone, not starting");
+                    continue;
+                }
+                lock.seed = Some(seed);
+                info!("Starting match with seed {}", seed);
+                let roster = lock.roster();
+                let (map_hash, game_port) = (lock.map_hash, lock.game_port);
+                for (player, (_, token)) in lock.players.iter_mut().zip(tokens) {
+                    // each player only learns its own token
+                    let message = LobbyServerMessages::MatchStarting {
+                        seed,
+                        map_hash,
+                        roster: roster.clone(),
+                        game_port,
+                        start_tick: START_TICK,
+                        token,
+                    };
+                    let bytes = bincode::serialize(&message).unwrap();
+                    if let Err(e) = player.stream.write_all(&bytes) {
+                        error!("Failed to send to player {}: {}", player.info.id, e);
+                    }
+                }
+            }
+        }
+    }
+    if let Some(id) = player {
+        let mut lock = lobby.lock().unwrap();
+        lock.players.retain(|player| player.info.id != id);
+        if lock.seed.is_none() {
+            let roster = LobbyServerMessages::Roster(lock.roster());
+            lock.broadcast(&roster);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shared::map::MAP_DIRECTORY;
     use crate::utils::ADDRESSES;
+    use std::net::TcpListener;
     use std::sync::mpsc::channel;
 
+    fn send(stream: &mut TcpStream, message: LobbyClientMessages) {
+        stream
+            .write_all(&bincode::serialize(&message).unwrap())
+            .unwrap();
+    }
+
+    fn receive(stream: &TcpStream) -> LobbyServerMessages {
+        bincode::deserialize_from(stream.try_clone().unwrap()).unwrap()
+    }
+
+    /// Checks the lobby's map message against the local copy like a client
+    /// would, returns the local hash.
+    fn receive_map(stream: &TcpStream) -> u64 {
+        let LobbyServerMessages::Map { name, hash } = receive(stream) else {
+            panic!("expected the lobby's map");
+        };
+        let map = Map::load_named(MAP_DIRECTORY, &name).unwrap();
+        assert_eq!(map.hash(), hash);
+        map.hash()
+    }
+
+    fn assert_silent(stream: &TcpStream) {
+        let timeout = std::time::Duration::from_millis(300);
+        stream.set_read_timeout(Some(timeout)).unwrap();
+        let message: Result<LobbyServerMessages, _> =
+            bincode::deserialize_from(stream.try_clone().unwrap());
+        assert!(message.is_err(), "unexpected {:?}", message);
+        stream.set_read_timeout(None).unwrap();
+    }
+
+    #[test]
+    fn test_free_id() {
+        let (tx, _rx) = channel();
+        let map = Map::load_named(MAP_DIRECTORY, "meadow").unwrap();
+        let mut lobby = Lobby::new(0, tx, "meadow".into(), map);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let player = |id| Player {
+            info: PlayerInfo {
+                id,
+                name: format!("player {}", id),
+                ready: false,
+            },
+            stream: stream.try_clone().unwrap(),
+            has_map: false,
+        };
+        assert_eq!(lobby.free_id(), Some(0));
+        lobby.players.extend([player(0), player(2)]);
+        assert_eq!(lobby.free_id(), Some(1));
+        lobby.players = (0..=PlayerId::MAX).map(player).collect();
+        assert_eq!(lobby.free_id(), None);
+    }
+
     #[test]
     fn test_lobby_code() {
         env_logger::init();
         let (tx, rx) = channel();
         let tcp_addr: SocketAddr = ADDRESSES[5].parse().unwrap();
         let udp_addr: SocketAddr = ADDRESSES[3].parse().unwrap();
+        let map = Map::load_named(MAP_DIRECTORY, "meadow").unwrap();
         let handle = std::thread::spawn(move || {
-            lobby_code(tcp_addr, udp_addr, rx).unwrap();
+            lobby_code(
+                tcp_addr,
+                udp_addr,
+                "meadow".into(),
+                map,
+                TimestepSettings::default(),
+                rx,
+            )
+            .unwrap();
         });
         std::thread::sleep(std::time::Duration::from_secs(1));
-        let tcp_stream = std::net::TcpStream::connect(ADDRESSES[5]).unwrap();
-        std::thread::sleep(std::time::Duration::from_secs(1));
+
+        let mut host = TcpStream::connect(ADDRESSES[5]).unwrap();
+        send(
+            &mut host,
+            LobbyClientMessages::Join {
+                name: "host".into(),
+            },
+        );
+        assert_eq!(receive(&host), LobbyServerMessages::Joined(0));
+        let map_hash = receive_map(&host);
+        assert_eq!(
+            receive(&host),
+            LobbyServerMessages::Roster(vec![PlayerInfo {
+                id: 0,
+                name: "host".into(),
+                ready: false,
+            }])
+        );
+
+        let mut guest = TcpStream::connect(ADDRESSES[5]).unwrap();
+        send(
+            &mut guest,
+            LobbyClientMessages::Join {
+                name: "guest".into(),
+            },
+        );
+        assert_eq!(receive(&guest), LobbyServerMessages::Joined(1));
+        assert_eq!(receive_map(&guest), map_hash);
+        let mut roster = vec![
+            PlayerInfo {
+                id: 0,
+                name: "host".into(),
+                ready: false,
+            },
+            PlayerInfo {
+                id: 1,
+                name: "guest".into(),
+                ready: false,
+            },
+        ];
+        assert_eq!(receive(&guest), LobbyServerMessages::Roster(roster.clone()));
+        assert_eq!(receive(&host), LobbyServerMessages::Roster(roster.clone()));
+
+        // the map has two slots
+        let mut third = TcpStream::connect(ADDRESSES[5]).unwrap();
+        send(
+            &mut third,
+            LobbyClientMessages::Join {
+                name: "third".into(),
+            },
+        );
+        assert!(matches!(receive(&third), LobbyServerMessages::Refused(_)));
+
+        // nobody confirmed the map yet, and a wrong hash does not count
+        send(&mut host, LobbyClientMessages::StartMatch);
+        send(&mut host, LobbyClientMessages::HasMap { hash: map_hash });
+        send(&mut guest, LobbyClientMessages::HasMap { hash: !map_hash });
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        send(&mut host, LobbyClientMessages::StartMatch);
+        assert_silent(&host);
+
+        // everyone has to be ready, every change is broadcast
+        send(&mut guest, LobbyClientMessages::HasMap { hash: map_hash });
+        send(&mut host, LobbyClientMessages::Ready(true));
+        roster[0].ready = true;
+        assert_eq!(receive(&host), LobbyServerMessages::Roster(roster.clone()));
+        assert_eq!(receive(&guest), LobbyServerMessages::Roster(roster.clone()));
+        send(&mut host, LobbyClientMessages::StartMatch);
+        assert_silent(&host);
+        send(&mut guest, LobbyClientMessages::Ready(true));
+        roster[1].ready = true;
+        assert_eq!(receive(&host), LobbyServerMessages::Roster(roster.clone()));
+        assert_eq!(receive(&guest), LobbyServerMessages::Roster(roster.clone()));
+
+        // only the host may start the match
+        send(&mut guest, LobbyClientMessages::StartMatch);
+        assert_silent(&guest);
+        send(&mut host, LobbyClientMessages::StartMatch);
+        let start = |stream| match receive(stream) {
+            LobbyServerMessages::MatchStarting {
+                seed,
+                map_hash: h,
+                roster: r,
+                game_port,
+                start_tick,
+                token,
+            } => {
+                assert_eq!((r, h), (roster.clone(), map_hash));
+                assert_eq!((game_port, start_tick), (udp_addr.port(), START_TICK));
+                (seed, token)
+            }
+            message => panic!("expected the match to start, got {:?}", message),
+        };
+        let (host_seed, host_token) = start(&host);
+        let (guest_seed, guest_token) = start(&guest);
+        assert_eq!(host_seed, guest_seed);
+        assert_ne!(host_token, guest_token);
+
         tx.send(()).unwrap();
         handle.join().unwrap();
     }
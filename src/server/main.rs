@@ -1,17 +1,20 @@
 use std::net::SocketAddr;
 
 use ant_engine::server::Distributer;
+use ant_engine::shared::config::{Config, CONFIG_FILE};
 use log::info;
 
 fn main() {
     env_logger::init();
     info!("Starting the game server");
 
-    let ports: [u16; 10] = [
-        3000, 3001, 3002, 3003, 3004, 3005, 3006, 3007, 3008, 3009,
-    ];
-    let addresses: Vec<SocketAddr> = ports.iter().map(|port| format!("0.0.0.0:{}", port).parse().unwrap()).collect();
+    let ports: [u16; 10] = [3000, 3001, 3002, 3003, 3004, 3005, 3006, 3007, 3008, 3009];
+    let addresses: Vec<SocketAddr> = ports
+        .iter()
+        .map(|port| format!("0.0.0.0:{}", port).parse().unwrap())
+        .collect();
 
-    let mut distributer = Distributer::new(addresses);
+    let config = Config::load_or_default(CONFIG_FILE);
+    let distributer = Distributer::new(addresses).with_config(&config);
     distributer.run();
 }
@@ -2,11 +2,15 @@
 pub mod game_server;
 mod lobby;
 
+use crate::shared::config::Config;
+use crate::shared::map::{Map, MAP_DIRECTORY};
 use crate::shared::protocols::{DistributorClientMessages, DistributorServerMessages};
+use crate::shared::timestep::TimestepSettings;
 use lobby::lobby_code;
 use log::error;
 use std::io::Write;
 use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
@@ -14,6 +18,8 @@ pub struct Distributer {
     free: Vec<SocketAddr>,
     main: SocketAddr,
     lobbies: Vec<(std::thread::JoinHandle<()>, Sender<()>, SocketAddr)>,
+    map_directory: PathBuf,
+    timestep: TimestepSettings,
 }
 
 impl Distributer {
@@ -24,19 +30,38 @@ impl Distributer {
             free,
             main,
             lobbies: Vec::new(),
+            map_directory: PathBuf::from(MAP_DIRECTORY),
+            timestep: TimestepSettings::default(),
         }
     }
 
-    fn try_open_lobby(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+    /// Applies the server settings, see [`TimestepSettings::from_config`].
+    pub fn with_config(mut self, config: &Config) -> Distributer {
+        self.timestep = TimestepSettings::from_config(config);
+        self
+    }
+
+    /// Directory lobbies load their maps from, [`MAP_DIRECTORY`] by default.
+    pub fn with_map_directory(mut self, directory: impl Into<PathBuf>) -> Distributer {
+        self.map_directory = directory.into();
+        self
+    }
+
+    fn try_open_lobby(&mut self, map: &str) -> Result<usize, Box<dyn std::error::Error>> {
         if self.free.len() < 2 {
             return Err("Not enough free sockets".into());
         }
+        let map_name = map.to_string();
+        let map = Map::load_named(&self.map_directory, map)?;
+        let timestep = self.timestep;
         let tcp_socket = self.free.pop().unwrap();
         let udp_socket = self.free.pop().unwrap();
 
         let (tx, rx) = std::sync::mpsc::channel();
         let handle = std::thread::spawn(move || {
-            lobby_code(tcp_socket, udp_socket, rx);
+            if let Err(e) = lobby_code(tcp_socket, udp_socket, map_name, map, timestep, rx) {
+                error!("Lobby on {} failed: {}", tcp_socket, e);
+            }
         });
 
         self.lobbies.push((handle, tx, tcp_socket));
@@ -71,16 +96,15 @@ fn handle_stream(mut stream: TcpStream, distributer: Arc<Mutex<Distributer>>) {
                     .write_all(&bincode::serialize(&message).unwrap())
                     .unwrap();
             }
-            DistributorClientMessages::OpenLobby => {
+            DistributorClientMessages::OpenLobby { map } => {
                 let mut lock = distributer.lock().unwrap();
-                let lobby = match lock.try_open_lobby() {
-                    Ok(lobby) => lobby,
+                let message = match lock.try_open_lobby(&map) {
+                    Ok(lobby) => DistributorServerMessages::LobbyOpened(lock.lobbies[lobby].2),
                     Err(e) => {
                         error!("Error opening lobby: {}", e);
-                        continue;
+                        DistributorServerMessages::LobbyRefused(e.to_string())
                     }
                 };
-                let message = DistributorServerMessages::LobbyOpened(lock.lobbies[lobby].2);
                 drop(lock);
                 stream
                     .write_all(&bincode::serialize(&message).unwrap())
@@ -114,8 +138,24 @@ mod server_tests {
             bincode::deserialize_from(client_stream.try_clone().unwrap()).unwrap();
         assert_eq!(message, DistributorServerMessages::Lobbies(vec![]));
 
+        // unknown maps are refused
+        let message = DistributorClientMessages::OpenLobby {
+            map: "no_such_map".into(),
+        };
+        client_stream
+            .write_all(&bincode::serialize(&message).unwrap())
+            .unwrap();
+        let message: DistributorServerMessages =
+            bincode::deserialize_from(client_stream.try_clone().unwrap()).unwrap();
+        assert!(matches!(
+            message,
+            DistributorServerMessages::LobbyRefused(_)
+        ));
+
         // open a lobby
-        let message = DistributorClientMessages::OpenLobby;
+        let message = DistributorClientMessages::OpenLobby {
+            map: "meadow".into(),
+        };
         client_stream
             .write_all(&bincode::serialize(&message).unwrap())
             .unwrap();
@@ -0,0 +1,60 @@
+//! Component types stored in the [`GameState`](crate::shared::game::GameState).
+
+use crate::shared::ecs::Entity;
+use crate::shared::fixed::Angle;
+use crate::shared::pheromone::PheromoneGrid;
+use crate::shared::protocols::PlayerId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntState {
+    /// Looking for food, follows food trails and lays home trails.
+    Foraging,
+    /// Carrying food back to the nest, lays food trails.
+    Returning,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ant {
+    pub colony: PlayerId,
+    pub nest: Entity,
+    pub heading: Angle,
+    pub state: AntState,
+    pub carrying: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nest {
+    pub colony: PlayerId,
+    pub food: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoodSource {
+    pub amount: u32,
+}
+
+/// What a colony's foraging ants should focus on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Follow known food trails closely.
+    Gather,
+    /// Wander more to discover new food sources.
+    Explore,
+    /// Dig tunnels through soil instead of turning around at it.
+    Dig,
+}
+
+/// Waypoints of a move order, ants without one forage on their own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub waypoints: Vec<(u32, u32)>,
+    pub next: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Colony {
+    pub player: PlayerId,
+    pub priority: Priority,
+    pub pheromones: PheromoneGrid,
+}
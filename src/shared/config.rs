@@ -0,0 +1,214 @@
+//! Settings file shared by the server and the client.
+//!
+//! The format is a small subset of INI: `[section]` headers, `key = value`
+//! lines and `#` comments. Keys are addressed as `section.key`, keys before
+//! the first header have no section prefix. Missing keys fall back to
+//! defaults chosen by the code reading them, so the file only needs to
+//! contain what differs.
+
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Looked up in the working directory by the server and client binaries.
+pub const CONFIG_FILE: &str = "ant_engine.cfg";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    InvalidLine { line: usize, text: String },
+    InvalidValue { key: String, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config: {}", e),
+            ConfigError::InvalidLine { line, text } => {
+                write!(f, "invalid config line {}: {:?}", line, text)
+            }
+            ConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for {}", value, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    values: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    pub fn from_text(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::new();
+        let mut section = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || ConfigError::InvalidLine {
+                line: index + 1,
+                text: line.to_string(),
+            };
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(invalid)?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(invalid());
+            }
+            let key = if section.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", section, key)
+            };
+            config.values.insert(key, value.trim().to_string());
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        Config::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    /// Like [`Config::load`], but a missing file is an empty config and a
+    /// broken one is logged and ignored, so the binaries always start.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Config {
+        match Config::load(&path) {
+            Ok(config) => config,
+            Err(ConfigError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Config::new(),
+            Err(e) => {
+                warn!("Ignoring {}: {}", path.as_ref().display(), e);
+                Config::new()
+            }
+        }
+    }
+
+    /// Writes all values grouped by section, comments are not preserved.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (key, value) in self.values.iter().filter(|(key, _)| !key.contains('.')) {
+            text += &format!("{} = {}\n", key, value);
+        }
+        let mut current = None;
+        for (key, value) in self.values.iter() {
+            let Some((section, name)) = key.rsplit_once('.') else {
+                continue;
+            };
+            if current != Some(section) {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text += &format!("[{}]\n", section);
+                current = Some(section);
+            }
+            text += &format!("{} = {}\n", name, value);
+        }
+        text
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|value| value.as_str())
+    }
+
+    /// Parsed value of `key`, `Ok(None)` if it is not set.
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        self.get_str(key)
+            .map(|value| {
+                value.parse().map_err(|_| ConfigError::InvalidValue {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// Parsed value of `key`, or `default` if it is missing or invalid.
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Ok(value) => value.unwrap_or(default),
+            Err(e) => {
+                warn!("{}, using the default", e);
+                default
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    /// Keys and values of a section, with the section prefix removed.
+    pub fn section<'a>(&'a self, section: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.values.iter().filter_map(move |(key, value)| {
+            let name = key.strip_prefix(section)?.strip_prefix('.')?;
+            Some((name, value.as_str()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# comment
+verbose = true
+
+[simulation]
+tick_rate = 30 # per second
+max_catch_up = 4
+
+[bindings]
+select = MouseLeft
+";
+
+    #[test]
+    fn test_parse() {
+        let config = Config::from_text(CONFIG).unwrap();
+        assert_eq!(config.get("simulation.tick_rate").unwrap(), Some(30u32));
+        assert_eq!(config.get::<bool>("verbose").unwrap(), Some(true));
+        assert_eq!(config.get::<u32>("simulation.missing").unwrap(), None);
+        assert!(config.get::<u32>("bindings.select").is_err());
+        assert_eq!(config.get_or("bindings.select", 7u32), 7);
+        assert_eq!(
+            config.section("bindings").collect::<Vec<_>>(),
+            vec![("select", "MouseLeft")]
+        );
+        assert!(matches!(
+            Config::from_text("[open"),
+            Err(ConfigError::InvalidLine { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut config = Config::from_text(CONFIG).unwrap();
+        config.set("simulation.tick_rate", 60);
+        let text = config.to_text();
+        assert_eq!(Config::from_text(&text).unwrap(), config);
+        assert!(text.starts_with("verbose = true\n\n[bindings]\n"));
+    }
+}
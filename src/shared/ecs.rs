@@ -0,0 +1,380 @@
+//! Entity/component storage for the simulation.
+//!
+//! Entities are generational indices, components live in one
+//! [`ComponentTable`] per type. Everything is plain vectors indexed by the
+//! entity index, so iteration order only depends on the order of spawns and
+//! despawns, cloning is cheap enough for rollback and the whole store can be
+//! serialized into snapshots.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+/// Allocates entity IDs. Freed indices are reused with a bumped generation,
+/// so stale IDs never alias a newer entity.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            }
+            None => {
+                let index = self.generations.len() as u32;
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Returns false if the entity was already dead.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.alive.len()
+            && self.alive[index]
+            && self.generations[index] == entity.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Living entities in index order.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .zip(self.generations.iter())
+            .enumerate()
+            .filter(|(_, (alive, _))| **alive)
+            .map(|(index, (_, generation))| Entity {
+                index: index as u32,
+                generation: *generation,
+            })
+    }
+}
+
+#[derive(Deserialize)]
+struct EncodedEntities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl<'de> Deserialize<'de> for Entities {
+    /// Rejects free lists that `spawn` couldn't use: every dead index has to
+    /// be free exactly once.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let EncodedEntities {
+            generations,
+            alive,
+            free,
+        } = EncodedEntities::deserialize(deserializer)?;
+        if generations.len() != alive.len() {
+            return Err(D::Error::custom(
+                "entity generations and alive flags differ in length",
+            ));
+        }
+        let mut freed = vec![false; alive.len()];
+        for &index in &free {
+            let index = index as usize;
+            if index >= alive.len() || alive[index] || freed[index] {
+                return Err(D::Error::custom(format!(
+                    "invalid free entity index {}",
+                    index
+                )));
+            }
+            freed[index] = true;
+        }
+        if free.len() != alive.iter().filter(|alive| !**alive).count() {
+            return Err(D::Error::custom("dead entities missing from the free list"));
+        }
+        Ok(Entities {
+            generations,
+            alive,
+            free,
+        })
+    }
+}
+
+/// Components of one type, stored densely by entity index.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ComponentTable<T> {
+    slots: Vec<Option<(u32, T)>>,
+    len: usize,
+}
+
+#[derive(Deserialize)]
+struct EncodedTable<T> {
+    slots: Vec<Option<(u32, T)>>,
+    len: usize,
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ComponentTable<T> {
+    /// Rejects a `len` that doesn't count the occupied slots, `remove` would
+    /// underflow it.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let EncodedTable { slots, len } = EncodedTable::deserialize(deserializer)?;
+        if slots.iter().filter(|slot| slot.is_some()).count() != len {
+            return Err(D::Error::custom(
+                "component count differs from the occupied slots",
+            ));
+        }
+        Ok(ComponentTable { slots, len })
+    }
+}
+
+impl<T> Default for ComponentTable<T> {
+    fn default() -> Self {
+        ComponentTable {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> ComponentTable<T> {
+    pub fn new() -> ComponentTable<T> {
+        ComponentTable::default()
+    }
+
+    /// Returns the previous component of this entity, if any.
+    pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let index = entity.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        let previous = self.slots[index].replace((entity.generation, value));
+        match previous {
+            Some((generation, value)) if generation == entity.generation => Some(value),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slots.get_mut(entity.index as usize)?;
+        match slot {
+            Some((generation, _)) if *generation == entity.generation => {
+                self.len -= 1;
+                slot.take().map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index as usize) {
+            Some(Some((generation, value))) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize) {
+            Some(Some((generation, value))) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.len = 0;
+    }
+
+    /// Components in entity index order.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|(generation, value)| {
+                (
+                    Entity {
+                        index: index as u32,
+                        generation: *generation,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    /// Components in entity index order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> + '_ {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.as_mut().map(|(generation, value)| {
+                    (
+                        Entity {
+                            index: index as u32,
+                            generation: *generation,
+                        },
+                        value,
+                    )
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generations() {
+        let mut entities = Entities::new();
+        let a = entities.spawn();
+        let b = entities.spawn();
+        assert!(entities.despawn(a));
+        assert!(!entities.despawn(a));
+        let c = entities.spawn();
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert!(!entities.is_alive(a));
+        assert!(entities.is_alive(c));
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![c, b]);
+        assert_eq!(entities.len(), 2);
+    }
+
+    #[test]
+    fn test_entities_deserialization() {
+        let mut entities = Entities::new();
+        let a = entities.spawn();
+        entities.spawn();
+        entities.despawn(a);
+        let bytes = bincode::serialize(&entities).unwrap();
+        assert_eq!(bincode::deserialize::<Entities>(&bytes).unwrap(), entities);
+
+        let decode = |generations: Vec<u32>, alive: Vec<bool>, free: Vec<u32>| {
+            let encoded = Entities {
+                generations,
+                alive,
+                free,
+            };
+            bincode::deserialize::<Entities>(&bincode::serialize(&encoded).unwrap())
+        };
+        assert!(decode(vec![0], vec![true, false], vec![1]).is_err());
+        assert!(decode(vec![0, 0], vec![true, false], vec![2]).is_err());
+        assert!(decode(vec![0, 0], vec![true, false], vec![0]).is_err());
+        assert!(decode(vec![0, 0], vec![false, false], vec![1, 1]).is_err());
+        assert!(decode(vec![0, 0], vec![true, false], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_component_table() {
+        let mut entities = Entities::new();
+        let mut table = ComponentTable::new();
+        let a = entities.spawn();
+        let b = entities.spawn();
+        table.insert(b, "b");
+        table.insert(a, "a");
+        assert_eq!(
+            table.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        entities.despawn(a);
+        let c = entities.spawn();
+        assert_eq!(table.get(c), None);
+        assert_eq!(table.insert(c, "c"), None);
+        assert_eq!(table.get(a), None);
+        assert_eq!(table.remove(a), None);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.remove(c), Some("c"));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_component_table_deserialization() {
+        let mut entities = Entities::new();
+        let mut table = ComponentTable::new();
+        table.insert(entities.spawn(), 1);
+        table.insert(entities.spawn(), 2);
+        for len in [0, 1, 3] {
+            let corrupted = ComponentTable {
+                slots: table.slots.clone(),
+                len,
+            };
+            let bytes = bincode::serialize(&corrupted).unwrap();
+            assert!(bincode::deserialize::<ComponentTable<i32>>(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut entities = Entities::new();
+        let mut table = ComponentTable::new();
+        for i in 0..10 {
+            let entity = entities.spawn();
+            table.insert(entity, i);
+        }
+        entities.despawn(table.iter().nth(3).unwrap().0);
+        let bytes = bincode::serialize(&(&entities, &table)).unwrap();
+        let (restored_entities, restored_table): (Entities, ComponentTable<i32>) =
+            bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored_entities, entities);
+        assert_eq!(restored_table, table);
+    }
+}
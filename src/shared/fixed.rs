@@ -0,0 +1,600 @@
+//! Fixed-point math for the deterministic simulation.
+//!
+//! Everything that feeds into the game state uses these types instead of
+//! floats, so every peer computes bit-identical results. Conversions to
+//! `nalgebra_glm` f32 types are only meant for rendering.
+//!
+//! Values can come from other peers, so no operation panics: arithmetic
+//! wraps on overflow in debug and release builds alike, `abs` saturates and
+//! division by zero gives `MAX` or `MIN` by the sign of the dividend, zero
+//! for zero.
+
+use nalgebra_glm::{vec2, vec3, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// Signed fixed-point scalar with 16 fractional bits.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const FRAC_BITS: u32 = 16;
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << Self::FRAC_BITS);
+    pub const HALF: Fixed = Fixed(1 << (Self::FRAC_BITS - 1));
+    pub const EPSILON: Fixed = Fixed(1);
+    pub const MAX: Fixed = Fixed(i64::MAX);
+    pub const MIN: Fixed = Fixed(i64::MIN);
+    pub const PI: Fixed = Fixed(205_887);
+    pub const TAU: Fixed = Fixed(411_774);
+    pub const FRAC_PI_2: Fixed = Fixed(102_944);
+
+    pub const fn from_bits(bits: i64) -> Fixed {
+        Fixed(bits)
+    }
+
+    pub const fn to_bits(self) -> i64 {
+        self.0
+    }
+
+    pub const fn from_int(value: i32) -> Fixed {
+        Fixed((value as i64) << Self::FRAC_BITS)
+    }
+
+    /// `numerator / denominator` without going through floats.
+    pub const fn from_ratio(numerator: i32, denominator: i32) -> Fixed {
+        Fixed(((numerator as i64) << Self::FRAC_BITS) / denominator as i64)
+    }
+
+    /// Only for loading tool data and tests, never inside the simulation.
+    pub fn from_f32(value: f32) -> Fixed {
+        Fixed((value as f64 * (1u64 << Self::FRAC_BITS) as f64).round() as i64)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1u64 << Self::FRAC_BITS) as f32
+    }
+
+    /// Rounds towards negative infinity.
+    pub const fn to_int(self) -> i32 {
+        (self.0 >> Self::FRAC_BITS) as i32
+    }
+
+    pub const fn floor(self) -> Fixed {
+        Fixed(self.0 & !((1 << Self::FRAC_BITS) - 1))
+    }
+
+    pub const fn ceil(self) -> Fixed {
+        Fixed(self.0.wrapping_add((1 << Self::FRAC_BITS) - 1)).floor()
+    }
+
+    pub const fn round(self) -> Fixed {
+        Fixed(self.0.wrapping_add(Self::HALF.0)).floor()
+    }
+
+    pub const fn frac(self) -> Fixed {
+        Fixed(self.0 & ((1 << Self::FRAC_BITS) - 1))
+    }
+
+    /// `MIN.abs()` saturates to `MAX`.
+    pub const fn abs(self) -> Fixed {
+        Fixed(self.0.saturating_abs())
+    }
+
+    pub const fn signum(self) -> Fixed {
+        Fixed(self.0.signum() << Self::FRAC_BITS)
+    }
+
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn saturating_add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+
+    /// Linear interpolation, `t` is not clamped.
+    pub fn lerp(self, other: Fixed, t: Fixed) -> Fixed {
+        self + (other - self) * t
+    }
+
+    /// Square root, negative values return zero.
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        Fixed(isqrt((self.0 as u128) << Self::FRAC_BITS) as i64)
+    }
+
+    /// Sine of an angle in radians.
+    pub fn sin(self) -> Fixed {
+        Angle::from_radians(self).sin()
+    }
+
+    /// Cosine of an angle in radians.
+    pub fn cos(self) -> Fixed {
+        Angle::from_radians(self).cos()
+    }
+}
+
+/// `(numerator << shift) / denominator`, division by zero saturates, see the
+/// module documentation.
+fn divide(numerator: i64, denominator: i64, shift: u32) -> Fixed {
+    match (denominator, numerator.signum()) {
+        (0, 1) => Fixed::MAX,
+        (0, -1) => Fixed::MIN,
+        (0, _) => Fixed::ZERO,
+        _ => Fixed((((numerator as i128) << shift) / denominator as i128) as i64),
+    }
+}
+
+fn isqrt(value: u128) -> u128 {
+    let mut remainder = value;
+    let mut result = 0u128;
+    let mut bit = 1u128 << ((127 - value.leading_zeros()) & !1);
+    while bit != 0 {
+        if remainder >= result + bit {
+            remainder -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+impl fmt::Debug for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fixed({})", self.to_f32())
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
+impl From<i32> for Fixed {
+    fn from(value: i32) -> Self {
+        Fixed::from_int(value)
+    }
+}
+
+impl From<Fixed> for f32 {
+    fn from(value: Fixed) -> Self {
+        value.to_f32()
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(((self.0 as i128 * other.0 as i128) >> Self::FRAC_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, other: Fixed) -> Fixed {
+        divide(self.0, other.0, Self::FRAC_BITS)
+    }
+}
+
+impl Mul<i32> for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: i32) -> Fixed {
+        Fixed(self.0.wrapping_mul(other as i64))
+    }
+}
+
+impl Div<i32> for Fixed {
+    type Output = Fixed;
+    fn div(self, other: i32) -> Fixed {
+        divide(self.0, other as i64, 0)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, other: Fixed) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, other: Fixed) {
+        *self = *self / other;
+    }
+}
+
+/// Binary angle, a full turn is `u16::MAX + 1` steps and wraps around.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Angle(u16);
+
+impl Angle {
+    pub const ZERO: Angle = Angle(0);
+    pub const QUARTER: Angle = Angle(1 << 14);
+    pub const HALF: Angle = Angle(1 << 15);
+    pub const THREE_QUARTERS: Angle = Angle(3 << 14);
+
+    pub const fn from_bits(bits: u16) -> Angle {
+        Angle(bits)
+    }
+
+    pub const fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_degrees(degrees: i32) -> Angle {
+        Angle(((degrees.rem_euclid(360) as u32 * (1 << 16) + 180) / 360) as u16)
+    }
+
+    pub fn from_radians(radians: Fixed) -> Angle {
+        let turns = radians / Fixed::TAU;
+        Angle((turns.to_bits() & 0xFFFF) as u16)
+    }
+
+    pub fn to_radians(self) -> Fixed {
+        Fixed::from_bits(((self.0 as i128 * Fixed::TAU.to_bits() as i128) >> 16) as i64)
+    }
+
+    pub fn to_radians_f32(self) -> f32 {
+        self.0 as f32 / (1u32 << 16) as f32 * std::f32::consts::TAU
+    }
+
+    /// Signed distance to `other` in the range `[-HALF, HALF)`.
+    pub fn delta(self, other: Angle) -> i32 {
+        other.0.wrapping_sub(self.0) as i16 as i32
+    }
+
+    /// Fifth order polynomial approximation, absolute error below 5e-4.
+    pub fn sin(self) -> Fixed {
+        let a = self.0 as i32;
+        let quarter = Angle::QUARTER.0 as i32;
+        let x = if a <= quarter {
+            a
+        } else if a < 3 * quarter {
+            2 * quarter - a
+        } else {
+            a - 4 * quarter
+        };
+        // z in [-1, 1] maps to [-pi/2, pi/2]
+        let z = Fixed::from_bits((x as i64) << (Fixed::FRAC_BITS - 14));
+        let z2 = z * z;
+        let a5 = Fixed::TAU - Fixed::from_int(5);
+        let b5 = Fixed::PI - Fixed::from_int(3);
+        z * (Fixed::PI - z2 * (a5 - z2 * b5)) / 2
+    }
+
+    pub fn cos(self) -> Fixed {
+        (self + Angle::QUARTER).sin()
+    }
+
+    /// Angle of the vector `(x, y)` measured counter-clockwise from the x axis,
+    /// accurate to about 0.1 degrees.
+    pub fn atan2(y: Fixed, x: Fixed) -> Angle {
+        if x == Fixed::ZERO && y == Fixed::ZERO {
+            return Angle::ZERO;
+        }
+        let (ax, ay) = (x.abs(), y.abs());
+        let (ratio, swapped) = if ax >= ay {
+            (ay / ax, false)
+        } else {
+            (ax / ay, true)
+        };
+        // atan(r) ~ pi/4 r + r (1 - r) (0.2447 + 0.0663 r) on [0, 1]
+        let c1 = Fixed::from_bits(16_037);
+        let c2 = Fixed::from_bits(4_345);
+        let octant = Fixed::PI / 4 * ratio + ratio * (Fixed::ONE - ratio) * (c1 + c2 * ratio);
+        let mut angle = Angle::from_radians(octant);
+        if swapped {
+            angle = Angle::QUARTER - angle;
+        }
+        if x.is_negative() {
+            angle = Angle::HALF - angle;
+        }
+        if y.is_negative() {
+            angle = -angle;
+        }
+        angle
+    }
+}
+
+impl Add for Angle {
+    type Output = Angle;
+    fn add(self, other: Angle) -> Angle {
+        Angle(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Sub for Angle {
+    type Output = Angle;
+    fn sub(self, other: Angle) -> Angle {
+        Angle(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl Neg for Angle {
+    type Output = Angle;
+    fn neg(self) -> Angle {
+        Angle(self.0.wrapping_neg())
+    }
+}
+
+impl AddAssign for Angle {
+    fn add_assign(&mut self, other: Angle) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Angle {
+    fn sub_assign(&mut self, other: Angle) {
+        *self = *self - other;
+    }
+}
+
+/// Two dimensional fixed-point vector.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FixedVec2 {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+impl FixedVec2 {
+    pub const ZERO: FixedVec2 = FixedVec2::new(Fixed::ZERO, Fixed::ZERO);
+
+    pub const fn new(x: Fixed, y: Fixed) -> FixedVec2 {
+        FixedVec2 { x, y }
+    }
+
+    pub const fn from_int(x: i32, y: i32) -> FixedVec2 {
+        FixedVec2::new(Fixed::from_int(x), Fixed::from_int(y))
+    }
+
+    /// Unit vector pointing in the direction of `angle`.
+    pub fn from_angle(angle: Angle) -> FixedVec2 {
+        FixedVec2::new(angle.cos(), angle.sin())
+    }
+
+    pub fn dot(self, other: FixedVec2) -> Fixed {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Z component of the 3D cross product.
+    pub fn perp_dot(self, other: FixedVec2) -> Fixed {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn length_squared(self) -> Fixed {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> Fixed {
+        self.length_squared().sqrt()
+    }
+
+    pub fn distance_squared(self, other: FixedVec2) -> Fixed {
+        (other - self).length_squared()
+    }
+
+    pub fn distance(self, other: FixedVec2) -> Fixed {
+        (other - self).length()
+    }
+
+    /// Returns `ZERO` for the zero vector.
+    pub fn normalize(self) -> FixedVec2 {
+        let length = self.length();
+        if length == Fixed::ZERO {
+            return FixedVec2::ZERO;
+        }
+        FixedVec2::new(self.x / length, self.y / length)
+    }
+
+    pub fn angle(self) -> Angle {
+        Angle::atan2(self.y, self.x)
+    }
+
+    pub fn rotate(self, angle: Angle) -> FixedVec2 {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        FixedVec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    pub fn lerp(self, other: FixedVec2, t: Fixed) -> FixedVec2 {
+        FixedVec2::new(self.x.lerp(other.x, t), self.y.lerp(other.y, t))
+    }
+
+    /// Only for tools and tests, never inside the simulation.
+    pub fn from_glm(value: &Vec2) -> FixedVec2 {
+        FixedVec2::new(Fixed::from_f32(value.x), Fixed::from_f32(value.y))
+    }
+
+    pub fn to_glm(self) -> Vec2 {
+        vec2(self.x.to_f32(), self.y.to_f32())
+    }
+
+    /// Places the vector on the ground plane at height `z` for rendering.
+    pub fn to_glm_vec3(self, z: f32) -> Vec3 {
+        vec3(self.x.to_f32(), self.y.to_f32(), z)
+    }
+}
+
+impl From<FixedVec2> for Vec2 {
+    fn from(value: FixedVec2) -> Self {
+        value.to_glm()
+    }
+}
+
+impl Add for FixedVec2 {
+    type Output = FixedVec2;
+    fn add(self, other: FixedVec2) -> FixedVec2 {
+        FixedVec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for FixedVec2 {
+    type Output = FixedVec2;
+    fn sub(self, other: FixedVec2) -> FixedVec2 {
+        FixedVec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<Fixed> for FixedVec2 {
+    type Output = FixedVec2;
+    fn mul(self, scale: Fixed) -> FixedVec2 {
+        FixedVec2::new(self.x * scale, self.y * scale)
+    }
+}
+
+impl Div<Fixed> for FixedVec2 {
+    type Output = FixedVec2;
+    fn div(self, scale: Fixed) -> FixedVec2 {
+        FixedVec2::new(self.x / scale, self.y / scale)
+    }
+}
+
+impl Neg for FixedVec2 {
+    type Output = FixedVec2;
+    fn neg(self) -> FixedVec2 {
+        FixedVec2::new(-self.x, -self.y)
+    }
+}
+
+impl AddAssign for FixedVec2 {
+    fn add_assign(&mut self, other: FixedVec2) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for FixedVec2 {
+    fn sub_assign(&mut self, other: FixedVec2) {
+        *self = *self - other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Fixed, b: f32, tolerance: f32) -> bool {
+        (a.to_f32() - b).abs() <= tolerance
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Fixed::from_int(3);
+        let b = Fixed::from_ratio(1, 2);
+        assert_eq!(a + b, Fixed::from_ratio(7, 2));
+        assert_eq!(a - b, Fixed::from_ratio(5, 2));
+        assert_eq!(a * b, Fixed::from_ratio(3, 2));
+        assert_eq!(a / b, Fixed::from_int(6));
+        assert_eq!(-a, Fixed::from_int(-3));
+        assert_eq!(Fixed::from_ratio(-3, 2).floor(), Fixed::from_int(-2));
+        assert_eq!(Fixed::from_ratio(-3, 2).to_int(), -2);
+        assert_eq!(Fixed::from_ratio(3, 2).ceil(), Fixed::from_int(2));
+        assert_eq!(Fixed::from_ratio(5, 4).round(), Fixed::ONE);
+        assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+        assert!(close(
+            Fixed::from_int(2).sqrt(),
+            std::f32::consts::SQRT_2,
+            1e-4
+        ));
+    }
+
+    #[test]
+    fn test_overflow_and_division_by_zero() {
+        assert_eq!(Fixed::MIN.abs(), Fixed::MAX);
+        assert_eq!(Fixed::MAX + Fixed::EPSILON, Fixed::MIN);
+        assert_eq!(Fixed::MIN - Fixed::EPSILON, Fixed::MAX);
+        assert_eq!(-Fixed::MIN, Fixed::MIN);
+        assert_eq!(Fixed::MAX * 2, Fixed::from_bits(-2));
+        assert_eq!(Fixed::MIN / -1, Fixed::MIN);
+        assert_eq!(Fixed::ONE / Fixed::ZERO, Fixed::MAX);
+        assert_eq!(-Fixed::ONE / Fixed::ZERO, Fixed::MIN);
+        assert_eq!(Fixed::ZERO / Fixed::ZERO, Fixed::ZERO);
+        assert_eq!(Fixed::ONE / 0, Fixed::MAX);
+        assert_eq!(Fixed::MAX.ceil(), Fixed::MIN.floor());
+        let mut value = Fixed::MAX;
+        value += Fixed::ONE;
+        assert!(value.is_negative());
+    }
+
+    #[test]
+    fn test_trig() {
+        for degrees in (-360..=360).step_by(5) {
+            let angle = Angle::from_degrees(degrees);
+            let radians = (degrees as f32).to_radians();
+            assert!(close(angle.sin(), radians.sin(), 5e-4), "sin {}", degrees);
+            assert!(close(angle.cos(), radians.cos(), 5e-4), "cos {}", degrees);
+        }
+        assert_eq!(Angle::from_radians(Fixed::PI), Angle::HALF);
+        assert!(close(
+            Angle::QUARTER.to_radians(),
+            std::f32::consts::FRAC_PI_2,
+            1e-4
+        ));
+    }
+
+    #[test]
+    fn test_atan2() {
+        for degrees in (0..360).step_by(7) {
+            let angle = Angle::from_degrees(degrees);
+            let direction = FixedVec2::from_angle(angle) * Fixed::from_int(10);
+            let delta = angle.delta(direction.angle());
+            assert!(delta.abs() <= 20, "atan2 {} off by {}", degrees, delta);
+        }
+        assert_eq!(FixedVec2::ZERO.angle(), Angle::ZERO);
+    }
+
+    #[test]
+    fn test_vectors() {
+        let v = FixedVec2::from_int(3, 4);
+        assert_eq!(v.length(), Fixed::from_int(5));
+        assert!(close(v.normalize().length(), 1.0, 1e-4));
+        let rotated = FixedVec2::from_int(1, 0).rotate(Angle::QUARTER);
+        assert!(close(rotated.x, 0.0, 1e-4) && close(rotated.y, 1.0, 1e-4));
+        assert_eq!(v.to_glm(), vec2(3.0, 4.0));
+        assert_eq!(
+            FixedVec2::from_glm(&vec2(1.5, -2.25)),
+            FixedVec2::new(Fixed::from_ratio(3, 2), Fixed::from_ratio(-9, 4))
+        );
+    }
+}
@@ -0,0 +1,892 @@
+use crate::shared::components::{Ant, AntState, Colony, FoodSource, Nest, Path, Priority};
+use crate::shared::ecs::{ComponentTable, Entities, Entity};
+use crate::shared::fixed::{Angle, Fixed, FixedVec2};
+use crate::shared::map::Map;
+use crate::shared::pathfinding::{find_path, FlowField};
+use crate::shared::pheromone::{Channel, PheromoneGrid};
+use crate::shared::protocols::PlayerId;
+use crate::shared::rng::Rng;
+use crate::shared::spatial::SpatialGrid;
+use crate::shared::terrain::{Terrain, Tile};
+use serde::{Deserialize, Serialize};
+
+pub const WORLD_SIZE: i32 = 256;
+pub const STARTING_ANTS: u32 = 10;
+pub const STARTING_FOOD: u32 = 100;
+pub const ANT_COST: u32 = 10;
+pub const FOOD_SOURCES: u32 = 24;
+pub const FOOD_PER_SOURCE: u32 = 200;
+
+const ANT_SPEED: Fixed = Fixed::from_ratio(1, 4);
+const ANT_MAX_TURN: i32 = 2731; // about 15 degrees
+const WANDER: i32 = 1024;
+const SENSE_RADIUS: Fixed = Fixed::from_int(6);
+const PICKUP_RADIUS: Fixed = Fixed::ONE;
+const NEST_RADIUS: Fixed = Fixed::from_int(2);
+const WAYPOINT_RADIUS: Fixed = Fixed::HALF;
+const ANTENNA_SPREAD: Angle = Angle::from_bits(5461); // about 30 degrees
+const ANTENNA_DISTANCE: Fixed = Fixed::from_int(3);
+const PHEROMONE_CELL_SIZE: Fixed = Fixed::from_int(2);
+const PHEROMONE_DEPOSIT: u16 = 1024;
+const DANGER_DEPOSIT: u16 = 8192;
+const NEIGHBOUR_RADIUS: Fixed = Fixed::from_int(2);
+const CROWD_LIMIT: u32 = 8;
+const SPATIAL_CELL_SIZE: Fixed = Fixed::from_int(8);
+
+/// Player input, applied by every peer at the same tick.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub player: PlayerId,
+    pub kind: CommandKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Buys ants at one of the player's nests for [`ANT_COST`] food each.
+    SpawnAnts {
+        nest: Entity,
+        count: u32,
+    },
+    SetPriority(Priority),
+    /// Sends the player's ants along the shortest path to `target`.
+    Move {
+        ants: Vec<Entity>,
+        target: FixedVec2,
+    },
+    /// Sends the player's ants to a food source, where those that are not
+    /// carrying anything pick up food.
+    Gather {
+        ants: Vec<Entity>,
+        food: Entity,
+    },
+    /// Sends the player's ants to a rival ant or nest. There is no fighting
+    /// yet, ants that meet rivals mark the spot with danger pheromone.
+    Attack {
+        ants: Vec<Entity>,
+        target: Entity,
+    },
+}
+
+/// Complete simulation state, identical on every peer for the same tick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameState {
+    tick: u64,
+    rng: Rng,
+    terrain: Terrain,
+    colonies: Vec<Colony>,
+    entities: Entities,
+    positions: ComponentTable<FixedVec2>,
+    ants: ComponentTable<Ant>,
+    nests: ComponentTable<Nest>,
+    food: ComponentTable<FoodSource>,
+    paths: ComponentTable<Path>,
+    #[serde(skip)]
+    navigation: Navigation,
+}
+
+/// Compares everything but the navigation, which is a cache of the terrain
+/// and the nests. A state decoded from a snapshot hasn't built it yet and is
+/// still the same state.
+impl PartialEq for GameState {
+    fn eq(&self, other: &GameState) -> bool {
+        let GameState {
+            tick,
+            rng,
+            terrain,
+            colonies,
+            entities,
+            positions,
+            ants,
+            nests,
+            food,
+            paths,
+            navigation: _,
+        } = self;
+        *tick == other.tick
+            && *rng == other.rng
+            && *terrain == other.terrain
+            && *colonies == other.colonies
+            && *entities == other.entities
+            && *positions == other.positions
+            && *ants == other.ants
+            && *nests == other.nests
+            && *food == other.food
+            && *paths == other.paths
+    }
+}
+
+/// Flow fields leading to each nest. They only depend on the terrain and the
+/// nests, so they are rebuilt on demand instead of being serialized.
+#[derive(Debug, Clone, Default)]
+struct Navigation {
+    terrain_version: Option<u64>,
+    nests: Vec<(Entity, FlowField)>,
+}
+
+impl Navigation {
+    fn direction(&self, nest: Entity, position: FixedVec2) -> Option<FixedVec2> {
+        let (_, field) = self.nests.iter().find(|(entity, _)| *entity == nest)?;
+        field.direction(position)
+    }
+}
+
+/// Copy of a [`GameState`] for rollback, replays and sending over the network.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot(GameState);
+
+impl Snapshot {
+    pub fn tick(&self) -> u64 {
+        self.0.tick
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+impl GameState {
+    /// Empty open world, `seed` is chosen by the lobby at match start.
+    pub fn new(seed: u64) -> GameState {
+        GameState::with_terrain(seed, Terrain::open(WORLD_SIZE as u32, WORLD_SIZE as u32))
+    }
+
+    /// World without colonies, with a food source on every deposit of the
+    /// terrain.
+    pub fn with_terrain(seed: u64, terrain: Terrain) -> GameState {
+        let mut state = GameState {
+            tick: 0,
+            rng: Rng::new(seed),
+            terrain,
+            colonies: Vec::new(),
+            entities: Entities::new(),
+            positions: ComponentTable::new(),
+            ants: ComponentTable::new(),
+            nests: ComponentTable::new(),
+            food: ComponentTable::new(),
+            paths: ComponentTable::new(),
+            navigation: Navigation::default(),
+        };
+        for (x, y) in state.terrain.food_deposits().to_vec() {
+            state.add_food(Terrain::tile_center(x, y), FOOD_PER_SOURCE);
+        }
+        state
+    }
+
+    /// Places one nest per player on a circle and scatters food sources.
+    pub fn new_match(seed: u64, players: &[PlayerId]) -> GameState {
+        let mut state = GameState::new(seed);
+        let world_size = state.world_size();
+        let center = world_size / Fixed::from_int(2);
+        let radius = world_size.x * Fixed::from_ratio(3, 8);
+        for (i, player) in players.iter().enumerate() {
+            let angle = Angle::from_bits((i * (1 << 16) / players.len()) as u16);
+            state.add_colony(*player, center + FixedVec2::from_angle(angle) * radius);
+        }
+        for _ in 0..FOOD_SOURCES {
+            let x = state.rng.fixed_range(Fixed::ZERO, world_size.x);
+            let y = state.rng.fixed_range(Fixed::ZERO, world_size.y);
+            state.add_food(FixedVec2::new(x, y), FOOD_PER_SOURCE);
+        }
+        state
+    }
+
+    /// Match on `map`, the n-th player of `players` gets the nests of slot n.
+    pub fn from_map(seed: u64, map: &Map, players: &[PlayerId]) -> GameState {
+        let mut state = GameState::with_terrain(seed, map.terrain().clone());
+        for (player, nests) in players.iter().zip(map.nests()) {
+            for (x, y) in nests {
+                state.add_colony(*player, Terrain::tile_center(*x, *y));
+            }
+        }
+        state
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// All randomness in the simulation has to come from here.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn world_size(&self) -> FixedVec2 {
+        self.terrain.size()
+    }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    pub fn colonies(&self) -> &[Colony] {
+        &self.colonies
+    }
+
+    pub fn colony(&self, player: PlayerId) -> Option<&Colony> {
+        self.colonies.iter().find(|colony| colony.player == player)
+    }
+
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    pub fn positions(&self) -> &ComponentTable<FixedVec2> {
+        &self.positions
+    }
+
+    pub fn ants(&self) -> &ComponentTable<Ant> {
+        &self.ants
+    }
+
+    pub fn nests(&self) -> &ComponentTable<Nest> {
+        &self.nests
+    }
+
+    pub fn food(&self) -> &ComponentTable<FoodSource> {
+        &self.food
+    }
+
+    pub fn paths(&self) -> &ComponentTable<Path> {
+        &self.paths
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.spawn()
+    }
+
+    /// Removes the entity together with all of its components.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.positions.remove(entity);
+        self.ants.remove(entity);
+        self.nests.remove(entity);
+        self.food.remove(entity);
+        self.paths.remove(entity);
+        self.entities.despawn(entity)
+    }
+
+    /// Adds a colony with its nest and starting ants, returns the nest.
+    pub fn add_colony(&mut self, player: PlayerId, position: FixedVec2) -> Entity {
+        if self.colony(player).is_none() {
+            self.colonies.push(Colony {
+                player,
+                priority: Priority::Gather,
+                pheromones: PheromoneGrid::new(self.world_size(), PHEROMONE_CELL_SIZE),
+            });
+        }
+        let nest = self.spawn();
+        self.positions.insert(nest, position);
+        self.nests.insert(
+            nest,
+            Nest {
+                colony: player,
+                food: STARTING_FOOD,
+            },
+        );
+        for _ in 0..STARTING_ANTS {
+            self.spawn_ant(nest);
+        }
+        nest
+    }
+
+    pub fn add_food(&mut self, position: FixedVec2, amount: u32) -> Entity {
+        let entity = self.spawn();
+        self.positions.insert(entity, position);
+        self.food.insert(entity, FoodSource { amount });
+        entity
+    }
+
+    /// Spawns an ant at the nest for free.
+    pub fn spawn_ant(&mut self, nest: Entity) -> Option<Entity> {
+        let colony = self.nests.get(nest)?.colony;
+        let position = *self.positions.get(nest)?;
+        let heading = self.rng.angle();
+        let ant = self.spawn();
+        self.positions.insert(ant, position);
+        self.ants.insert(
+            ant,
+            Ant {
+                colony,
+                nest,
+                heading,
+                state: AntState::Foraging,
+                carrying: 0,
+            },
+        );
+        Some(ant)
+    }
+
+    pub fn lay_pheromone(
+        &mut self,
+        colony: PlayerId,
+        channel: Channel,
+        position: FixedVec2,
+        amount: u16,
+    ) {
+        if let Some(colony) = self.colonies.iter_mut().find(|c| c.player == colony) {
+            colony.pheromones.deposit(channel, position, amount);
+        }
+    }
+
+    /// Invalid commands are ignored, identically on every peer.
+    pub fn apply(&mut self, command: &Command) {
+        match command.kind {
+            CommandKind::SpawnAnts { nest, count } => {
+                let Some(state) = self.nests.get_mut(nest) else {
+                    return;
+                };
+                if state.colony != command.player {
+                    return;
+                }
+                let count = count.min(state.food / ANT_COST);
+                state.food -= count * ANT_COST;
+                for _ in 0..count {
+                    self.spawn_ant(nest);
+                }
+            }
+            CommandKind::SetPriority(priority) => {
+                if let Some(colony) = self
+                    .colonies
+                    .iter_mut()
+                    .find(|colony| colony.player == command.player)
+                {
+                    colony.priority = priority;
+                }
+            }
+            CommandKind::Move { ref ants, target } => {
+                self.send_ants(command.player, ants, target);
+            }
+            CommandKind::Gather { ref ants, food } => {
+                if !self.food.contains(food) {
+                    return;
+                }
+                if let Some(target) = self.positions.get(food).copied() {
+                    self.send_ants(command.player, ants, target);
+                }
+            }
+            CommandKind::Attack { ref ants, target } => {
+                let colony = match (self.ants.get(target), self.nests.get(target)) {
+                    (Some(ant), _) => ant.colony,
+                    (None, Some(nest)) => nest.colony,
+                    (None, None) => return,
+                };
+                if colony == command.player {
+                    return;
+                }
+                if let Some(target) = self.positions.get(target).copied() {
+                    self.send_ants(command.player, ants, target);
+                }
+            }
+        }
+    }
+
+    /// Gives those of `ants` that belong to `player` a path to `target`.
+    fn send_ants(&mut self, player: PlayerId, ants: &[Entity], target: FixedVec2) {
+        let Some(goal) = self.terrain.tile_at(target) else {
+            return;
+        };
+        for ant in ants {
+            if self.ants.get(*ant).map(|ant| ant.colony) != Some(player) {
+                continue;
+            }
+            let Some(start) = self
+                .positions
+                .get(*ant)
+                .and_then(|p| self.terrain.tile_at(*p))
+            else {
+                continue;
+            };
+            if let Some(waypoints) = find_path(&self.terrain, start, goal) {
+                self.paths.insert(*ant, Path { waypoints, next: 0 });
+            }
+        }
+    }
+
+    /// Advances the simulation by one tick after applying `commands` in order.
+    pub fn step(&mut self, commands: &[Command]) {
+        for command in commands {
+            self.apply(command);
+        }
+        for colony in self.colonies.iter_mut() {
+            colony.pheromones.step();
+        }
+        self.refresh_navigation();
+        let mut food_index = SpatialGrid::new(self.world_size(), SPATIAL_CELL_SIZE);
+        food_index.rebuild(self.located(&self.food));
+        let mut ant_index = SpatialGrid::new(self.world_size(), SPATIAL_CELL_SIZE);
+        ant_index.rebuild(self.located(&self.ants));
+        let ants: Vec<Entity> = self.ants.iter().map(|(entity, _)| entity).collect();
+        for ant in ants {
+            self.update_ant(ant, &food_index, &ant_index);
+        }
+        self.tick += 1;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(self.clone())
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.clone_from(&snapshot.0);
+    }
+
+    /// Brings the nest flow fields up to date with the terrain and the nests.
+    /// Fields of nests that stayed put are only recomputed if the terrain
+    /// changed around the tiles that reach them, see [`FlowField::refresh`].
+    fn refresh_navigation(&mut self) {
+        let nests: Vec<Entity> = self.nests.iter().map(|(entity, _)| entity).collect();
+        let current = self.navigation.terrain_version == Some(self.terrain.version())
+            && self
+                .navigation
+                .nests
+                .iter()
+                .map(|(entity, _)| *entity)
+                .eq(nests.iter().copied());
+        if current {
+            return;
+        }
+        let mut previous = std::mem::take(&mut self.navigation.nests);
+        let fields = nests
+            .into_iter()
+            .filter_map(|nest| {
+                let tile = self.terrain.tile_at(*self.positions.get(nest)?)?;
+                let kept = previous
+                    .iter()
+                    .position(|(entity, field)| *entity == nest && field.goal() == tile);
+                let field = match kept {
+                    Some(index) => {
+                        let (_, mut field) = previous.swap_remove(index);
+                        field.refresh(&self.terrain);
+                        field
+                    }
+                    None => FlowField::new(&self.terrain, tile),
+                };
+                Some((nest, field))
+            })
+            .collect();
+        self.navigation.nests = fields;
+        self.navigation.terrain_version = Some(self.terrain.version());
+    }
+
+    /// Moves the ant along its path, returns the direction to the next
+    /// waypoint or `None` once it arrived.
+    fn follow_path(&mut self, entity: Entity, position: FixedVec2) -> Option<Angle> {
+        let path = self.paths.get_mut(entity)?;
+        while let Some((x, y)) = path.waypoints.get(path.next).copied() {
+            let waypoint = Terrain::tile_center(x, y);
+            if position.distance_squared(waypoint) > WAYPOINT_RADIUS * WAYPOINT_RADIUS {
+                return Some((waypoint - position).angle());
+            }
+            path.next += 1;
+        }
+        self.paths.remove(entity);
+        None
+    }
+
+    /// Entities of `table` paired with their positions, in index order.
+    fn located<'a, T>(
+        &'a self,
+        table: &'a ComponentTable<T>,
+    ) -> impl Iterator<Item = (Entity, FixedVec2)> + 'a {
+        table
+            .iter()
+            .filter_map(|(entity, _)| Some((entity, *self.positions.get(entity)?)))
+    }
+
+    /// Ants see food and other ants through indices built at the start of the
+    /// tick, so the order ants are updated in does not change what they sense.
+    fn update_ant(&mut self, entity: Entity, food_index: &SpatialGrid, ant_index: &SpatialGrid) {
+        let (Some(mut ant), Some(mut position)) = (
+            self.ants.get(entity).copied(),
+            self.positions.get(entity).copied(),
+        ) else {
+            return;
+        };
+        let priority = self
+            .colony(ant.colony)
+            .map_or(Priority::Gather, |colony| colony.priority);
+
+        let mut target = None;
+        let on_path = self.paths.contains(entity);
+        if on_path {
+            target = self.follow_path(entity, position);
+        }
+        match ant.state {
+            _ if on_path => {}
+            AntState::Foraging => {
+                if let Some((food, food_position)) = self.nearest_food(position, food_index) {
+                    if position.distance_squared(food_position) <= PICKUP_RADIUS * PICKUP_RADIUS {
+                        self.take_food(food);
+                        ant.carrying = 1;
+                        ant.state = AntState::Returning;
+                        ant.heading += Angle::HALF;
+                    } else {
+                        target = Some((food_position - position).angle());
+                    }
+                } else if priority == Priority::Gather || self.rng.chance(Fixed::HALF) {
+                    target =
+                        self.follow_trail(ant.colony, Channel::FoodTrail, position, ant.heading);
+                }
+            }
+            AntState::Returning => match self.positions.get(ant.nest).copied() {
+                Some(nest_position) => {
+                    if position.distance_squared(nest_position) <= NEST_RADIUS * NEST_RADIUS {
+                        if let Some(nest) = self.nests.get_mut(ant.nest) {
+                            nest.food += ant.carrying;
+                        }
+                        ant.carrying = 0;
+                        ant.state = AntState::Foraging;
+                        ant.heading += Angle::HALF;
+                    } else {
+                        target = self
+                            .follow_trail(ant.colony, Channel::HomeTrail, position, ant.heading)
+                            .or_else(|| {
+                                let direction = self.navigation.direction(ant.nest, position)?;
+                                Some(direction.angle())
+                            })
+                            .or(Some((nest_position - position).angle()));
+                    }
+                }
+                None => {
+                    ant.carrying = 0;
+                    ant.state = AntState::Foraging;
+                }
+            },
+        }
+
+        let mut crowd = FixedVec2::ZERO;
+        let mut crowd_size = 0;
+        let mut rivals = 0;
+        ant_index.for_each_in_radius(
+            position,
+            NEIGHBOUR_RADIUS,
+            |other, other_position| match self.ants.get(other) {
+                Some(_) if other == entity => {}
+                Some(other) if other.colony == ant.colony => {
+                    crowd += other_position - position;
+                    crowd_size += 1;
+                }
+                Some(_) => rivals += 1,
+                None => {}
+            },
+        );
+        if rivals > 0 {
+            self.lay_pheromone(ant.colony, Channel::Danger, position, DANGER_DEPOSIT);
+        }
+        if crowd_size > CROWD_LIMIT && target.is_none() && crowd != FixedVec2::ZERO {
+            target = Some((-crowd).angle());
+        }
+        if let Some(colony) = self.colony(ant.colony) {
+            let danger = colony
+                .pheromones
+                .gradient(Channel::Danger, position, ANTENNA_DISTANCE);
+            if danger != FixedVec2::ZERO {
+                target = Some((-danger).angle());
+            }
+        }
+        if let Some(target) = target {
+            let turn = ant.heading.delta(target).clamp(-ANT_MAX_TURN, ANT_MAX_TURN);
+            ant.heading += Angle::from_bits(turn as u16);
+        }
+        let wander = match priority {
+            Priority::Gather | Priority::Dig => WANDER,
+            Priority::Explore => WANDER * 3,
+        };
+        ant.heading += Angle::from_bits(self.rng.range(-wander, wander + 1) as u16);
+
+        let next = position + FixedVec2::from_angle(ant.heading) * ANT_SPEED;
+        match self.terrain.tile_at(next) {
+            Some((x, y)) if self.terrain.get(x, y).is_passable() => position = next,
+            Some((x, y))
+                if priority == Priority::Dig
+                    && matches!(self.terrain.get(x, y), Tile::Soil { .. }) =>
+            {
+                self.terrain.dig(x, y);
+            }
+            _ => ant.heading += Angle::HALF,
+        }
+
+        let channel = match ant.state {
+            AntState::Foraging => Channel::HomeTrail,
+            AntState::Returning => Channel::FoodTrail,
+        };
+        self.lay_pheromone(ant.colony, channel, position, PHEROMONE_DEPOSIT);
+
+        self.ants.insert(entity, ant);
+        self.positions.insert(entity, position);
+    }
+
+    /// Closest food source within sensing range, ties go to the lowest index.
+    fn nearest_food(
+        &self,
+        position: FixedVec2,
+        food_index: &SpatialGrid,
+    ) -> Option<(Entity, FixedVec2)> {
+        let mut nearest: Option<(Entity, FixedVec2)> = None;
+        let mut nearest_distance = Fixed::MAX;
+        food_index.for_each_in_radius(position, SENSE_RADIUS, |entity, food_position| {
+            if !self.food.contains(entity) {
+                return;
+            }
+            let distance = position.distance_squared(food_position);
+            let closer = match nearest {
+                Some((current, _)) if distance == nearest_distance => {
+                    entity.index() < current.index()
+                }
+                _ => distance < nearest_distance,
+            };
+            if closer {
+                nearest = Some((entity, food_position));
+                nearest_distance = distance;
+            }
+        });
+        nearest
+    }
+
+    fn take_food(&mut self, entity: Entity) {
+        let Some(food) = self.food.get_mut(entity) else {
+            return;
+        };
+        food.amount = food.amount.saturating_sub(1);
+        if food.amount == 0 {
+            self.despawn(entity);
+        }
+    }
+
+    /// Direction of the strongest trail the ant's antennae pick up.
+    fn follow_trail(
+        &self,
+        colony: PlayerId,
+        channel: Channel,
+        position: FixedVec2,
+        heading: Angle,
+    ) -> Option<Angle> {
+        self.colony(colony)?.pheromones.strongest_direction(
+            channel,
+            position,
+            heading,
+            ANTENNA_SPREAD,
+            ANTENNA_DISTANCE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_state() {
+        let mut a = GameState::new_match(1234, &[0, 1]);
+        let mut b = GameState::new_match(1234, &[0, 1]);
+        let commands = [Command {
+            player: 1,
+            kind: CommandKind::SetPriority(Priority::Explore),
+        }];
+        for tick in 0..300 {
+            let commands: &[Command] = if tick == 10 { &commands } else { &[] };
+            a.step(commands);
+            b.step(commands);
+        }
+        assert_eq!(a.snapshot(), b.snapshot());
+        assert_eq!(a.tick(), 300);
+        assert_ne!(
+            GameState::new_match(4321, &[0, 1]),
+            GameState::new_match(1234, &[0, 1])
+        );
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut state = GameState::new_match(99, &[0]);
+        let snapshot = state.snapshot();
+
+        for _ in 0..20 {
+            state.step(&[]);
+        }
+        assert_ne!(state.snapshot(), snapshot);
+
+        state.restore(&Snapshot::from_bytes(&snapshot.to_bytes()).unwrap());
+        assert_eq!(state.snapshot(), snapshot);
+        assert_eq!(state.tick(), 0);
+    }
+
+    #[test]
+    fn test_ants_bring_food_home() {
+        let mut state = GameState::new(7);
+        let nest = state.add_colony(0, FixedVec2::from_int(100, 100));
+        for x in [-4, 4] {
+            for y in [-4, 4] {
+                state.add_food(FixedVec2::from_int(100 + x, 100 + y), 50);
+            }
+        }
+        for _ in 0..1000 {
+            state.step(&[]);
+        }
+        assert!(state.nests().get(nest).unwrap().food > STARTING_FOOD);
+        assert!(!state.colony(0).unwrap().pheromones.is_empty());
+    }
+
+    #[test]
+    fn test_spawn_ants_command() {
+        let mut state = GameState::new(3);
+        let nest = state.add_colony(0, FixedVec2::from_int(10, 10));
+        let spawn = |player| Command {
+            player,
+            kind: CommandKind::SpawnAnts { nest, count: 3 },
+        };
+        state.step(&[spawn(1)]);
+        assert_eq!(state.ants().len(), STARTING_ANTS as usize);
+        state.step(&[spawn(0)]);
+        assert_eq!(state.ants().len(), STARTING_ANTS as usize + 3);
+        assert_eq!(
+            state.nests().get(nest).unwrap().food,
+            STARTING_FOOD - 3 * ANT_COST
+        );
+    }
+
+    #[test]
+    fn test_pheromones_evaporate() {
+        let mut state = GameState::new(5);
+        state.add_colony(0, FixedVec2::from_int(100, 100));
+        for (entity, _) in state.ants.clone().iter() {
+            state.despawn(entity);
+        }
+        let position = FixedVec2::from_int(5, 5);
+        state.lay_pheromone(0, Channel::FoodTrail, position, PHEROMONE_DEPOSIT);
+        let grid = &state.colony(0).unwrap().pheromones;
+        let (x, y) = grid.cell(position);
+        assert_eq!(grid.get(Channel::FoodTrail, x, y), PHEROMONE_DEPOSIT);
+        state.step(&[]);
+        let grid = &state.colony(0).unwrap().pheromones;
+        assert!(grid.get(Channel::FoodTrail, x, y) < PHEROMONE_DEPOSIT);
+        assert!(grid.get(Channel::FoodTrail, x + 1, y) > 0);
+        for _ in 0..2000 {
+            state.step(&[]);
+        }
+        assert!(state.colony(0).unwrap().pheromones.is_empty());
+    }
+
+    const WALLED: &str = "\
+................
+................
+.......#........
+.......#........
+.......#........
+.......#........
+.......#........
+.......#........
+.......#........
+.......#........
+.......%........
+.......%........
+";
+
+    #[test]
+    fn test_move_command_goes_around_rock() {
+        let mut state = GameState::with_terrain(11, Terrain::from_text(WALLED).unwrap());
+        state.add_colony(0, FixedVec2::from_int(3, 5));
+        let ants: Vec<Entity> = state.ants().iter().map(|(entity, _)| entity).collect();
+        let target = FixedVec2::from_int(12, 5);
+        state.step(&[Command {
+            player: 0,
+            kind: CommandKind::Move {
+                ants: ants.clone(),
+                target,
+            },
+        }]);
+        assert_eq!(state.paths().len(), ants.len());
+        for _ in 0..400 {
+            state.step(&[]);
+            for (_, position) in state.positions().iter() {
+                let (x, y) = state.terrain().tile_at(*position).unwrap();
+                assert!(state.terrain().get(x, y).is_passable());
+            }
+        }
+        assert!(state.paths().is_empty());
+    }
+
+    #[test]
+    fn test_gather_and_attack_need_valid_targets() {
+        let mut state = GameState::new(5);
+        let own = state.add_colony(0, FixedVec2::from_int(20, 20));
+        let rival = state.add_colony(1, FixedVec2::from_int(60, 20));
+        let food = state.add_food(FixedVec2::from_int(20, 40), 10);
+        let ants: Vec<Entity> = state
+            .ants()
+            .iter()
+            .filter(|(_, ant)| ant.colony == 0)
+            .map(|(entity, _)| entity)
+            .collect();
+        let command = |kind| Command { player: 0, kind };
+
+        state.apply(&command(CommandKind::Attack {
+            ants: ants.clone(),
+            target: own,
+        }));
+        state.apply(&command(CommandKind::Gather {
+            ants: ants.clone(),
+            food: rival,
+        }));
+        assert!(state.paths().is_empty());
+
+        state.apply(&command(CommandKind::Attack {
+            ants: ants.clone(),
+            target: rival,
+        }));
+        assert_eq!(state.paths().len(), ants.len());
+        state.apply(&command(CommandKind::Gather {
+            ants: ants.clone(),
+            food,
+        }));
+        let goal = state
+            .terrain()
+            .tile_at(FixedVec2::from_int(20, 40))
+            .unwrap();
+        for (_, path) in state.paths().iter() {
+            assert_eq!(path.waypoints.last(), Some(&goal));
+        }
+    }
+
+    #[test]
+    fn test_dig_priority_tunnels_through_soil() {
+        let mut state = GameState::with_terrain(4, Terrain::from_text(WALLED).unwrap());
+        state.add_colony(0, FixedVec2::from_int(5, 11));
+        let set_priority = |priority| Command {
+            player: 0,
+            kind: CommandKind::SetPriority(priority),
+        };
+        state.step(&[set_priority(Priority::Gather)]);
+        for _ in 0..300 {
+            state.step(&[]);
+        }
+        assert_eq!(state.terrain().version(), 0);
+
+        state.step(&[set_priority(Priority::Dig)]);
+        for _ in 0..600 {
+            state.step(&[]);
+        }
+        assert!(state.terrain().version() > 0);
+        // fields kept across digs match freshly built ones
+        for (_, field) in &state.navigation.nests {
+            assert_eq!(*field, FlowField::new(&state.terrain, field.goal()));
+        }
+    }
+
+    #[test]
+    fn test_from_map() {
+        let map = Map::from_text(
+            "ant_engine map 1\nname = Test\nsize = 6 3\nnest = 0 1 1\nnest = 1 4 1\ntiles\n..*...\n......\n...#..\n",
+        )
+        .unwrap();
+        let state = GameState::from_map(1, &map, &[3]);
+        assert_eq!(state.terrain(), map.terrain());
+        assert_eq!(state.colonies().len(), 1);
+        assert_eq!(state.colonies()[0].player, 3);
+        assert_eq!(state.nests().len(), 1);
+        assert_eq!(state.food().len(), 1);
+    }
+}
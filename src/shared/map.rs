@@ -0,0 +1,410 @@
+//! Map files chosen by the host when opening a lobby.
+//!
+//! A map is a text file with a header of `key = value` lines followed by the
+//! terrain in the format of [`Terrain::from_text`]:
+//!
+//! ```text
+//! ant_engine map 1
+//! name = Meadow
+//! author = rofdo
+//! description = Open field with a few rocks
+//! size = 8 4
+//! nest = 0 1 1
+//! nest = 1 6 2
+//! tiles
+//! ........
+//! .....#..
+//! ..*.....
+//! ........
+//! ```
+//!
+//! `nest = <slot> <x> <y>` places a nest for the player in that slot, a slot
+//! may have several. Food deposits are the `*` tiles of the terrain.
+//! Every peer compares [`Map::hash`] before a match starts, so they all
+//! simulate on the identical map.
+
+use crate::shared::protocols::PlayerId;
+use crate::shared::terrain::{Terrain, TerrainError};
+use std::fmt;
+use std::path::Path;
+
+/// Version written by [`Map::to_text`], the only one [`Map::from_text`] reads.
+pub const MAP_VERSION: u32 = 1;
+/// Where the server and the client look for maps by name.
+pub const MAP_DIRECTORY: &str = "assets/maps";
+pub const MAP_EXTENSION: &str = "map";
+
+const MAGIC: &str = "ant_engine map";
+const TILES: &str = "tiles";
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    MissingVersion,
+    UnsupportedVersion(u32),
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    MissingKey(&'static str),
+    Terrain(TerrainError),
+    SizeMismatch {
+        header: (u32, u32),
+        tiles: (u32, u32),
+    },
+    NoNests,
+    EmptySlot(usize),
+    BlockedNest {
+        slot: usize,
+        x: u32,
+        y: u32,
+    },
+    InvalidName(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "failed to read map: {}", e),
+            MapError::MissingVersion => write!(f, "not a map file, expected '{} <version>'", MAGIC),
+            MapError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "map version {} is not supported, expected {}",
+                    version, MAP_VERSION
+                )
+            }
+            MapError::InvalidLine { line, text } => write!(f, "invalid line {}: {:?}", line, text),
+            MapError::UnknownKey { line, key } => {
+                write!(f, "unknown key {:?} on line {}", key, line)
+            }
+            MapError::MissingKey(key) => write!(f, "missing key {:?}", key),
+            MapError::Terrain(e) => write!(f, "invalid tiles: {}", e),
+            MapError::SizeMismatch { header, tiles } => write!(
+                f,
+                "header says {}x{} but the tiles are {}x{}",
+                header.0, header.1, tiles.0, tiles.1
+            ),
+            MapError::NoNests => write!(f, "map has no nests"),
+            MapError::EmptySlot(slot) => write!(f, "player slot {} has no nest", slot),
+            MapError::BlockedNest { slot, x, y } => {
+                write!(
+                    f,
+                    "nest of slot {} at {} {} is not on passable ground",
+                    slot, x, y
+                )
+            }
+            MapError::InvalidName(name) => write!(f, "invalid map name {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<std::io::Error> for MapError {
+    fn from(e: std::io::Error) -> MapError {
+        MapError::Io(e)
+    }
+}
+
+impl From<TerrainError> for MapError {
+    fn from(e: TerrainError) -> MapError {
+        MapError::Terrain(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Map {
+    name: String,
+    author: String,
+    description: String,
+    nests: Vec<Vec<(u32, u32)>>,
+    terrain: Terrain,
+}
+
+impl Map {
+    pub fn from_text(text: &str) -> Result<Map, MapError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let version = lines
+            .next()
+            .and_then(|(_, line)| line.trim().strip_prefix(MAGIC))
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or(MapError::MissingVersion)?;
+        if version != MAP_VERSION {
+            return Err(MapError::UnsupportedVersion(version));
+        }
+
+        let mut name = None;
+        let mut author = String::new();
+        let mut description = String::new();
+        let mut size = None;
+        let mut nests: Vec<Vec<(u32, u32)>> = Vec::new();
+        let mut tiles = None;
+        for (line, text) in lines.by_ref() {
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            if text == TILES {
+                tiles = Some(line);
+                break;
+            }
+            let invalid = || MapError::InvalidLine {
+                line,
+                text: text.to_string(),
+            };
+            let (key, value) = text.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = Some(value.to_string()),
+                "author" => author = value.to_string(),
+                "description" => description = value.to_string(),
+                "size" => match parse_numbers(value) {
+                    Some([width, height]) => size = Some((width, height)),
+                    None => return Err(invalid()),
+                },
+                "nest" => match parse_numbers(value) {
+                    Some([slot, x, y]) => {
+                        // slots become player ids
+                        if slot >= PlayerId::MAX as u32 {
+                            return Err(invalid());
+                        }
+                        let slot = slot as usize;
+                        if nests.len() <= slot {
+                            nests.resize(slot + 1, Vec::new());
+                        }
+                        nests[slot].push((x, y));
+                    }
+                    None => return Err(invalid()),
+                },
+                key => {
+                    return Err(MapError::UnknownKey {
+                        line,
+                        key: key.to_string(),
+                    })
+                }
+            }
+        }
+        let name = name.ok_or(MapError::MissingKey("name"))?;
+        let size = size.ok_or(MapError::MissingKey("size"))?;
+        tiles.ok_or(MapError::MissingKey(TILES))?;
+
+        let rows: Vec<&str> = lines.map(|(_, line)| line).collect();
+        let terrain = Terrain::from_text(&rows.join("\n"))?;
+        let map = Map {
+            name,
+            author,
+            description,
+            nests,
+            terrain,
+        };
+        map.validate(size)?;
+        Ok(map)
+    }
+
+    fn validate(&self, size: (u32, u32)) -> Result<(), MapError> {
+        let tiles = (self.terrain.width(), self.terrain.height());
+        if size != tiles {
+            return Err(MapError::SizeMismatch {
+                header: size,
+                tiles,
+            });
+        }
+        if self.nests.is_empty() {
+            return Err(MapError::NoNests);
+        }
+        for (slot, nests) in self.nests.iter().enumerate() {
+            if nests.is_empty() {
+                return Err(MapError::EmptySlot(slot));
+            }
+            for &(x, y) in nests {
+                if !self.terrain.is_passable(x as i32, y as i32) {
+                    return Err(MapError::BlockedNest { slot, x, y });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Canonical form of the map, parsing it gives back an equal map.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", MAGIC, MAP_VERSION);
+        text += &format!("name = {}\n", self.name);
+        if !self.author.is_empty() {
+            text += &format!("author = {}\n", self.author);
+        }
+        if !self.description.is_empty() {
+            text += &format!("description = {}\n", self.description);
+        }
+        text += &format!(
+            "size = {} {}\n",
+            self.terrain.width(),
+            self.terrain.height()
+        );
+        for (slot, nests) in self.nests.iter().enumerate() {
+            for (x, y) in nests {
+                text += &format!("nest = {} {} {}\n", slot, x, y);
+            }
+        }
+        text += TILES;
+        text.push('\n');
+        text += &self.terrain.to_text();
+        text
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Map, MapError> {
+        Map::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads `<name>.map` from `directory`. Names are plain file stems, so a
+    /// lobby request can not reach outside of the map directory.
+    pub fn load_named(directory: impl AsRef<Path>, name: &str) -> Result<Map, MapError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(MapError::InvalidName(name.to_string()));
+        }
+        Map::load(directory.as_ref().join(name).with_extension(MAP_EXTENSION))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    /// Nests of every player slot, indexed by slot.
+    pub fn nests(&self) -> &[Vec<(u32, u32)>] {
+        &self.nests
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.nests.len()
+    }
+
+    /// FNV-1a of the canonical text, so formatting differences in the file
+    /// do not matter but any change to the content does.
+    pub fn hash(&self) -> u64 {
+        self.to_text()
+            .bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
+fn parse_numbers<const N: usize>(value: &str) -> Option<[u32; N]> {
+    let mut numbers = [0; N];
+    let mut parts = value.split_whitespace();
+    for number in numbers.iter_mut() {
+        *number = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+ant_engine map 1
+name = Test
+size = 8 4
+nest = 0 1 1
+nest = 1 6 2
+nest = 1 6 3
+tiles
+........
+.....#..
+..*.....
+........
+";
+
+    #[test]
+    fn test_parse() {
+        let map = Map::from_text(MAP).unwrap();
+        assert_eq!(map.name(), "Test");
+        assert_eq!(map.max_players(), 2);
+        assert_eq!(map.nests()[1], vec![(6, 2), (6, 3)]);
+        assert_eq!(map.terrain().food_deposits(), &[(2, 2)]);
+        assert_eq!(map.to_text(), MAP);
+        assert_eq!(Map::from_text(&map.to_text()).unwrap(), map);
+    }
+
+    #[test]
+    fn test_hash() {
+        let map = Map::from_text(MAP).unwrap();
+        let spaced = MAP.replace("size = 8 4", "\n  size=8   4");
+        assert_eq!(Map::from_text(&spaced).unwrap().hash(), map.hash());
+        let moved = MAP.replace("nest = 0 1 1", "nest = 0 1 2");
+        assert_ne!(Map::from_text(&moved).unwrap().hash(), map.hash());
+    }
+
+    #[test]
+    fn test_validation() {
+        let error = |text: &str| Map::from_text(text).unwrap_err();
+        assert!(matches!(error(""), MapError::MissingVersion));
+        assert!(matches!(
+            error(&MAP.replace("map 1", "map 2")),
+            MapError::UnsupportedVersion(2)
+        ));
+        assert!(matches!(
+            error(&MAP.replace("size = 8 4", "size = 8 5")),
+            MapError::SizeMismatch { .. }
+        ));
+        assert!(matches!(
+            error(&MAP.replace("nest = 1 6 2", "nest = 1 5 1")),
+            MapError::BlockedNest {
+                slot: 1,
+                x: 5,
+                y: 1
+            }
+        ));
+        assert!(matches!(
+            error(&MAP.replace("nest = 0 1 1", "nest = 2 1 1")),
+            MapError::EmptySlot(0)
+        ));
+        assert!(matches!(
+            error(&MAP.replace("nest = 1 6 2", "nest = 4294967295 6 2")),
+            MapError::InvalidLine { line: 5, .. }
+        ));
+        assert!(matches!(
+            error(&MAP.replace("name", "title")),
+            MapError::UnknownKey { line: 2, .. }
+        ));
+        assert!(matches!(
+            Map::load_named(MAP_DIRECTORY, "../secret"),
+            Err(MapError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn test_bundled_maps_load() {
+        let mut found = 0;
+        for entry in std::fs::read_dir(MAP_DIRECTORY).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == MAP_EXTENSION) {
+                let map = Map::load(&path).unwrap();
+                assert!(map.max_players() >= 2, "{}", path.display());
+                found += 1;
+            }
+        }
+        assert!(found > 0);
+    }
+}
@@ -1 +1,13 @@
+pub mod components;
+pub mod config;
+pub mod ecs;
+pub mod fixed;
+pub mod game;
+pub mod map;
+pub mod pathfinding;
+pub mod pheromone;
 pub mod protocols;
+pub mod rng;
+pub mod spatial;
+pub mod terrain;
+pub mod timestep;
@@ -0,0 +1,325 @@
+//! Deterministic pathfinding on the [`Terrain`].
+//!
+//! Costs are integers, 10 for a straight step and 14 for a diagonal one, and
+//! all ties are broken by tile index, so every peer finds the same path.
+//! Diagonal steps may not cut past impassable corners.
+
+use crate::shared::fixed::FixedVec2;
+use crate::shared::terrain::Terrain;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+const UNREACHABLE: u32 = u32::MAX;
+
+/// Neighbour offsets in the order they are tried.
+const NEIGHBOURS: [(i32, i32, u32); 8] = [
+    (1, 0, STRAIGHT),
+    (0, 1, STRAIGHT),
+    (-1, 0, STRAIGHT),
+    (0, -1, STRAIGHT),
+    (1, 1, DIAGONAL),
+    (-1, 1, DIAGONAL),
+    (-1, -1, DIAGONAL),
+    (1, -1, DIAGONAL),
+];
+
+fn can_step(passable: impl Fn(i32, i32) -> bool, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+    passable(x + dx, y + dy) && (dx == 0 || dy == 0 || (passable(x + dx, y) && passable(x, y + dy)))
+}
+
+fn octile_distance(from: (u32, u32), to: (u32, u32)) -> u32 {
+    let dx = from.0.abs_diff(to.0);
+    let dy = from.1.abs_diff(to.1);
+    STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy)
+}
+
+/// A* from `start` to `goal`. The path includes both ends, `None` if the goal
+/// can not be reached or either end is impassable.
+pub fn find_path(
+    terrain: &Terrain,
+    start: (u32, u32),
+    goal: (u32, u32),
+) -> Option<Vec<(u32, u32)>> {
+    if !terrain.is_passable(start.0 as i32, start.1 as i32)
+        || !terrain.is_passable(goal.0 as i32, goal.1 as i32)
+    {
+        return None;
+    }
+    let width = terrain.width();
+    let index = |(x, y): (u32, u32)| (y * width + x) as usize;
+    let cells = (terrain.width() * terrain.height()) as usize;
+    let mut cost = vec![UNREACHABLE; cells];
+    let mut came_from = vec![u32::MAX; cells];
+    let mut open = BinaryHeap::new();
+
+    cost[index(start)] = 0;
+    open.push(Reverse((
+        octile_distance(start, goal),
+        0,
+        index(start) as u32,
+    )));
+    while let Some(Reverse((_, current_cost, current))) = open.pop() {
+        if current_cost > cost[current as usize] {
+            continue;
+        }
+        let (x, y) = (current % width, current / width);
+        if (x, y) == goal {
+            let mut path = vec![goal];
+            let mut node = current;
+            while node != index(start) as u32 {
+                node = came_from[node as usize];
+                path.push((node % width, node / width));
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for (dx, dy, step) in NEIGHBOURS {
+            if !can_step(|x, y| terrain.is_passable(x, y), x as i32, y as i32, dx, dy) {
+                continue;
+            }
+            let next = ((x as i32 + dx) as u32, (y as i32 + dy) as u32);
+            let next_cost = current_cost + step;
+            if next_cost < cost[index(next)] {
+                cost[index(next)] = next_cost;
+                came_from[index(next)] = current;
+                open.push(Reverse((
+                    next_cost + octile_distance(next, goal),
+                    next_cost,
+                    index(next) as u32,
+                )));
+            }
+        }
+    }
+    None
+}
+
+/// Distance to a goal for every tile, computed once and shared by any number
+/// of ants heading to the same place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowField {
+    width: u32,
+    height: u32,
+    goal: (u32, u32),
+    distance: Vec<u32>,
+    passable: Vec<bool>,
+}
+
+impl FlowField {
+    pub fn new(terrain: &Terrain, goal: (u32, u32)) -> FlowField {
+        let (width, height) = (terrain.width(), terrain.height());
+        let mut distance = vec![UNREACHABLE; (width * height) as usize];
+        let mut open = BinaryHeap::new();
+        if terrain.is_passable(goal.0 as i32, goal.1 as i32) {
+            let index = goal.1 * width + goal.0;
+            distance[index as usize] = 0;
+            open.push(Reverse((0, index)));
+        }
+        while let Some(Reverse((current_distance, current))) = open.pop() {
+            if current_distance > distance[current as usize] {
+                continue;
+            }
+            let (x, y) = ((current % width) as i32, (current / width) as i32);
+            for (dx, dy, step) in NEIGHBOURS {
+                // steps are symmetric, so walking from the neighbour to here is
+                // allowed exactly when walking from here to the neighbour is
+                if !can_step(|x, y| terrain.is_passable(x, y), x, y, dx, dy) {
+                    continue;
+                }
+                let next = (y + dy) as u32 * width + (x + dx) as u32;
+                let next_distance = current_distance + step;
+                if next_distance < distance[next as usize] {
+                    distance[next as usize] = next_distance;
+                    open.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+        let passable = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| terrain.is_passable(x as i32, y as i32))
+            .collect();
+        FlowField {
+            width,
+            height,
+            goal,
+            distance,
+            passable,
+        }
+    }
+
+    pub fn goal(&self) -> (u32, u32) {
+        self.goal
+    }
+
+    /// Catches up with changes to the terrain. The distances are only
+    /// recomputed if a tile that became passable or impassable is the goal
+    /// or next to a tile that reaches it, nothing else can change a path.
+    /// Returns whether they were.
+    pub fn refresh(&mut self, terrain: &Terrain) -> bool {
+        let mut changed = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) as usize;
+                if self.passable[index] != terrain.is_passable(x as i32, y as i32) {
+                    changed.push((x, y));
+                }
+            }
+        }
+        let touched = changed.iter().any(|&(x, y)| {
+            (x, y) == self.goal
+                || NEIGHBOURS.iter().any(|(dx, dy, _)| {
+                    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                    nx >= 0 && ny >= 0 && self.distance(nx as u32, ny as u32).is_some()
+                })
+        });
+        if touched {
+            *self = FlowField::new(terrain, self.goal);
+            return true;
+        }
+        for (x, y) in changed {
+            let index = (y * self.width + x) as usize;
+            self.passable[index] = !self.passable[index];
+        }
+        false
+    }
+
+    /// Path cost from the tile to the goal, `None` if it can not reach it.
+    pub fn distance(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let distance = self.distance[(y * self.width + x) as usize];
+        (distance != UNREACHABLE).then_some(distance)
+    }
+
+    /// Step towards the goal from the given tile, `None` at the goal or if the
+    /// goal can not be reached.
+    pub fn step(&self, x: u32, y: u32) -> Option<(i32, i32)> {
+        let current = self.distance(x, y)?;
+        let passable = |x: i32, y: i32| {
+            x >= 0
+                && y >= 0
+                && (x as u32) < self.width
+                && (y as u32) < self.height
+                && self.passable[(y as u32 * self.width + x as u32) as usize]
+        };
+        NEIGHBOURS
+            .into_iter()
+            .filter(|(dx, dy, _)| can_step(passable, x as i32, y as i32, *dx, *dy))
+            .find(|(dx, dy, step)| {
+                let (nx, ny) = ((x as i32 + dx) as u32, (y as i32 + dy) as u32);
+                self.distance(nx, ny).map(|distance| distance + step) == Some(current)
+            })
+            .map(|(dx, dy, _)| (dx, dy))
+    }
+
+    /// Direction to walk in from a world position, see [`FlowField::step`].
+    pub fn direction(&self, position: FixedVec2) -> Option<FixedVec2> {
+        let (x, y) = (position.x.to_int(), position.y.to_int());
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let (dx, dy) = self.step(x as u32, y as u32)?;
+        let target = Terrain::tile_center((x + dx) as u32, (y + dy) as u32);
+        Some(target - position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::terrain::Tile;
+
+    const MAZE: &str = "\
+.......
+.#####.
+.#...#.
+.#.#.#.
+...#...
+";
+
+    #[test]
+    fn test_find_path() {
+        let terrain = Terrain::from_text(MAZE).unwrap();
+        let path = find_path(&terrain, (2, 2), (4, 2)).unwrap();
+        assert_eq!(path.first(), Some(&(2, 2)));
+        assert_eq!(path.last(), Some(&(4, 2)));
+        assert_eq!(path, vec![(2, 2), (3, 2), (4, 2)]);
+
+        let path = find_path(&terrain, (2, 3), (6, 0)).unwrap();
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0.abs_diff(b.0) <= 1 && a.1.abs_diff(b.1) <= 1);
+            assert!(terrain.is_passable(b.0 as i32, b.1 as i32));
+        }
+        assert_eq!(path, find_path(&terrain, (2, 3), (6, 0)).unwrap());
+        assert_eq!(find_path(&terrain, (2, 3), (1, 1)), None);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let terrain = Terrain::from_text("..#..\n..#..\n").unwrap();
+        assert_eq!(find_path(&terrain, (0, 0), (4, 1)), None);
+        let field = FlowField::new(&terrain, (4, 1));
+        assert_eq!(field.distance(0, 0), None);
+        assert_eq!(field.step(0, 0), None);
+    }
+
+    #[test]
+    fn test_flow_field_refresh() {
+        let mut terrain = Terrain::from_text("..#..\n..#..\n..#..\n").unwrap();
+        let mut field = FlowField::new(&terrain, (0, 0));
+
+        // the right side can't reach the goal either way
+        terrain.set(4, 2, Tile::Rock);
+        assert!(!field.refresh(&terrain));
+        assert_eq!(field, FlowField::new(&terrain, (0, 0)));
+
+        // opening the wall connects it
+        terrain.set(2, 1, Tile::Tunnel);
+        assert!(field.refresh(&terrain));
+        assert_eq!(field, FlowField::new(&terrain, (0, 0)));
+        assert_eq!(field.distance(4, 1), Some(44));
+        assert!(!field.refresh(&terrain));
+    }
+
+    #[test]
+    fn test_flow_field() {
+        let terrain = Terrain::from_text(MAZE).unwrap();
+        let field = FlowField::new(&terrain, (6, 0));
+        assert_eq!(field.distance(6, 0), Some(0));
+        assert_eq!(field.step(6, 0), None);
+        // off the map, even where the row-major index would wrap into it
+        assert_eq!(field.distance(field.width, 0), None);
+        assert_eq!(field.distance(0, field.height), None);
+        assert_eq!(field.step(field.width, 0), None);
+
+        // following the field walks the same distance A* finds
+        let (mut x, mut y) = (2, 3);
+        let mut walked = 0;
+        while let Some((dx, dy)) = field.step(x, y) {
+            walked += if dx != 0 && dy != 0 {
+                DIAGONAL
+            } else {
+                STRAIGHT
+            };
+            x = (x as i32 + dx) as u32;
+            y = (y as i32 + dy) as u32;
+        }
+        assert_eq!((x, y), (6, 0));
+        assert_eq!(Some(walked), field.distance(2, 3));
+        let path = find_path(&terrain, (2, 3), (6, 0)).unwrap();
+        let cost: u32 = path
+            .windows(2)
+            .map(|p| {
+                if p[0].0 != p[1].0 && p[0].1 != p[1].1 {
+                    DIAGONAL
+                } else {
+                    STRAIGHT
+                }
+            })
+            .sum();
+        assert_eq!(cost, walked);
+    }
+}
@@ -0,0 +1,428 @@
+//! Pheromone field of a colony.
+//!
+//! Intensities are stored as `u16` per cell and channel, and diffusion and
+//! evaporation only use integer arithmetic so every peer ends up with the same
+//! field. Grids serialize run-length encoded since most cells are empty.
+
+use crate::shared::fixed::{Angle, Fixed, FixedVec2};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Laid by ants carrying food, leads to food.
+    FoodTrail,
+    /// Laid by foraging ants, leads home.
+    HomeTrail,
+    /// Laid by ants that ran into enemies.
+    Danger,
+}
+
+impl Channel {
+    pub const COUNT: usize = 3;
+    pub const ALL: [Channel; Channel::COUNT] =
+        [Channel::FoodTrail, Channel::HomeTrail, Channel::Danger];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Share of a cell exchanged with its neighbours per tick, out of 256.
+    fn diffusion(self) -> u32 {
+        match self {
+            Channel::FoodTrail | Channel::HomeTrail => 8,
+            Channel::Danger => 64,
+        }
+    }
+
+    /// Share of a cell lost per tick, out of 256.
+    fn evaporation(self) -> u32 {
+        match self {
+            Channel::FoodTrail | Channel::HomeTrail => 2,
+            Channel::Danger => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PheromoneGrid {
+    width: u32,
+    height: u32,
+    cell_size: Fixed,
+    channels: [Vec<u16>; Channel::COUNT],
+}
+
+impl PheromoneGrid {
+    /// Grid covering `world_size` with square cells of `cell_size`.
+    pub fn new(world_size: FixedVec2, cell_size: Fixed) -> PheromoneGrid {
+        let width = (world_size.x / cell_size).ceil().to_int().max(1) as u32;
+        let height = (world_size.y / cell_size).ceil().to_int().max(1) as u32;
+        let cells = (width * height) as usize;
+        PheromoneGrid {
+            width,
+            height,
+            cell_size,
+            channels: [vec![0; cells], vec![0; cells], vec![0; cells]],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn cell_size(&self) -> Fixed {
+        self.cell_size
+    }
+
+    /// Row-major intensities of one channel, ready to upload as an
+    /// `R16_UNORM` texture of `width` by `height`.
+    pub fn channel(&self, channel: Channel) -> &[u16] {
+        &self.channels[channel.index()]
+    }
+
+    /// All channels interleaved per cell as `R16G16B16A16_UNORM` texels, alpha
+    /// is always zero.
+    pub fn texels(&self) -> Vec<[u16; 4]> {
+        let [food, home, danger] = &self.channels;
+        food.iter()
+            .zip(home.iter())
+            .zip(danger.iter())
+            .map(|((food, home), danger)| [*food, *home, *danger, 0])
+            .collect()
+    }
+
+    /// Cell containing `position`, clamped to the grid.
+    pub fn cell(&self, position: FixedVec2) -> (u32, u32) {
+        let x = (position.x / self.cell_size).to_int();
+        let y = (position.y / self.cell_size).to_int();
+        (
+            x.clamp(0, self.width as i32 - 1) as u32,
+            y.clamp(0, self.height as i32 - 1) as u32,
+        )
+    }
+
+    pub fn get(&self, channel: Channel, x: u32, y: u32) -> u16 {
+        self.channels[channel.index()][(y * self.width + x) as usize]
+    }
+
+    fn get_clamped(&self, channel: Channel, x: i32, y: i32) -> u16 {
+        let x = x.clamp(0, self.width as i32 - 1) as u32;
+        let y = y.clamp(0, self.height as i32 - 1) as u32;
+        self.get(channel, x, y)
+    }
+
+    /// Adds `amount` to the cell containing `position`, saturating.
+    pub fn deposit(&mut self, channel: Channel, position: FixedVec2, amount: u16) {
+        let (x, y) = self.cell(position);
+        let cell = &mut self.channels[channel.index()][(y * self.width + x) as usize];
+        *cell = cell.saturating_add(amount);
+    }
+
+    /// Bilinear sample between cell centres.
+    pub fn sample(&self, channel: Channel, position: FixedVec2) -> Fixed {
+        let fx = position.x / self.cell_size - Fixed::HALF;
+        let fy = position.y / self.cell_size - Fixed::HALF;
+        let (x, y) = (fx.to_int(), fy.to_int());
+        let (tx, ty) = (fx.frac(), fy.frac());
+        let value = |dx, dy| Fixed::from_int(self.get_clamped(channel, x + dx, y + dy) as i32);
+        let top = value(0, 0).lerp(value(1, 0), tx);
+        let bottom = value(0, 1).lerp(value(1, 1), tx);
+        top.lerp(bottom, ty)
+    }
+
+    /// Central difference of the field over `distance` in both axes, points
+    /// towards higher intensity.
+    pub fn gradient(&self, channel: Channel, position: FixedVec2, distance: Fixed) -> FixedVec2 {
+        let dx = FixedVec2::new(distance, Fixed::ZERO);
+        let dy = FixedVec2::new(Fixed::ZERO, distance);
+        FixedVec2::new(
+            self.sample(channel, position + dx) - self.sample(channel, position - dx),
+            self.sample(channel, position + dy) - self.sample(channel, position - dy),
+        )
+    }
+
+    /// Samples left, ahead and right of `heading` at `distance` like an ant's
+    /// antennae and returns the direction of the strongest one.
+    pub fn strongest_direction(
+        &self,
+        channel: Channel,
+        position: FixedVec2,
+        heading: Angle,
+        spread: Angle,
+        distance: Fixed,
+    ) -> Option<Angle> {
+        let mut best = None;
+        let mut best_value = Fixed::ZERO;
+        for direction in [heading - spread, heading, heading + spread] {
+            let value = self.sample(
+                channel,
+                position + FixedVec2::from_angle(direction) * distance,
+            );
+            if value > best_value {
+                best = Some(direction);
+                best_value = value;
+            }
+        }
+        best
+    }
+
+    /// Diffuses every channel into its four neighbours, then evaporates it.
+    pub fn step(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        for channel in Channel::ALL {
+            let diffusion = channel.diffusion();
+            let evaporation = channel.evaporation();
+            let current = &self.channels[channel.index()];
+            if current.iter().all(|value| *value == 0) {
+                continue;
+            }
+            let mut next = vec![0u16; current.len()];
+            for y in 0..height {
+                let row = &current[y * width..(y + 1) * width];
+                let above = &current[y.saturating_sub(1) * width..][..width];
+                let below = &current[(y + 1).min(height - 1) * width..][..width];
+                for x in 0..width {
+                    let center = row[x] as u32;
+                    let neighbours = row[x.saturating_sub(1)] as u32
+                        + row[(x + 1).min(width - 1)] as u32
+                        + above[x] as u32
+                        + below[x] as u32;
+                    let diffused = (center * (256 - diffusion) + neighbours * diffusion / 4) / 256;
+                    let evaporated = diffused - (diffused * evaporation).div_ceil(256);
+                    next[y * width + x] = evaporated as u16;
+                }
+            }
+            self.channels[channel.index()] = next;
+        }
+    }
+
+    /// True if every cell of every channel is zero.
+    pub fn is_empty(&self) -> bool {
+        self.channels
+            .iter()
+            .all(|channel| channel.iter().all(|value| *value == 0))
+    }
+
+    /// Changes since `base`, which must have the same dimensions.
+    pub fn delta(&self, base: &PheromoneGrid) -> GridDelta {
+        assert_eq!((self.width, self.height), (base.width, base.height));
+        GridDelta {
+            channels: std::array::from_fn(|i| {
+                let difference: Vec<u16> = self.channels[i]
+                    .iter()
+                    .zip(base.channels[i].iter())
+                    .map(|(value, base)| value.wrapping_sub(*base))
+                    .collect();
+                run_length_encode(&difference)
+            }),
+        }
+    }
+
+    /// Applies a delta that was taken with this grid as the base.
+    /// Leaves the grid untouched if the delta doesn't fit.
+    pub fn apply_delta(&mut self, delta: &GridDelta) -> Result<(), String> {
+        let differences = self
+            .channels
+            .iter()
+            .zip(delta.channels.iter())
+            .map(|(channel, runs)| run_length_decode(runs, channel.len()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (channel, difference) in self.channels.iter_mut().zip(differences) {
+            for (value, difference) in channel.iter_mut().zip(difference) {
+                *value = value.wrapping_add(difference);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run-length encoded difference between two grids, see
+/// [`PheromoneGrid::delta`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GridDelta {
+    channels: [Vec<(u32, u16)>; Channel::COUNT],
+}
+
+fn run_length_encode(values: &[u16]) -> Vec<(u32, u16)> {
+    let mut runs: Vec<(u32, u16)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((length, last)) if last == value => *length += 1,
+            _ => runs.push((1, *value)),
+        }
+    }
+    runs
+}
+
+fn run_length_decode(runs: &[(u32, u16)], len: usize) -> Result<Vec<u16>, String> {
+    let mut values = Vec::with_capacity(len);
+    for (length, value) in runs {
+        if values.len() + *length as usize > len {
+            return Err("pheromone runs exceed grid size".into());
+        }
+        values.extend(std::iter::repeat_n(*value, *length as usize));
+    }
+    if values.len() != len {
+        return Err("pheromone runs do not cover the grid".into());
+    }
+    Ok(values)
+}
+
+/// Most cells a decoded grid may have, far more than any map needs.
+const MAX_CELLS: usize = 1 << 24;
+
+#[derive(Serialize, Deserialize)]
+struct EncodedGrid {
+    width: u32,
+    height: u32,
+    cell_size: Fixed,
+    channels: [Vec<(u32, u16)>; Channel::COUNT],
+}
+
+impl Serialize for PheromoneGrid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EncodedGrid {
+            width: self.width,
+            height: self.height,
+            cell_size: self.cell_size,
+            channels: std::array::from_fn(|i| run_length_encode(&self.channels[i])),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PheromoneGrid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = EncodedGrid::deserialize(deserializer)?;
+        if encoded.width == 0 || encoded.height == 0 {
+            return Err(D::Error::custom("pheromone grid has no cells"));
+        }
+        if encoded.cell_size <= Fixed::ZERO {
+            return Err(D::Error::custom("pheromone cell size must be positive"));
+        }
+        let cells = (encoded.width as usize)
+            .checked_mul(encoded.height as usize)
+            .filter(|cells| *cells <= MAX_CELLS)
+            .ok_or_else(|| D::Error::custom("pheromone grid is too large"))?;
+        let [food, home, danger] = encoded.channels;
+        Ok(PheromoneGrid {
+            width: encoded.width,
+            height: encoded.height,
+            cell_size: encoded.cell_size,
+            channels: [
+                run_length_decode(&food, cells).map_err(D::Error::custom)?,
+                run_length_decode(&home, cells).map_err(D::Error::custom)?,
+                run_length_decode(&danger, cells).map_err(D::Error::custom)?,
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> PheromoneGrid {
+        PheromoneGrid::new(FixedVec2::from_int(64, 32), Fixed::from_int(2))
+    }
+
+    #[test]
+    fn test_diffusion_and_evaporation() {
+        let mut grid = grid();
+        assert_eq!((grid.width(), grid.height()), (32, 16));
+        let position = FixedVec2::from_int(21, 11);
+        grid.deposit(Channel::FoodTrail, position, 60000);
+        let (x, y) = grid.cell(position);
+        assert_eq!((x, y), (10, 5));
+
+        grid.step();
+        let center = grid.get(Channel::FoodTrail, x, y);
+        assert!(center < 60000);
+        assert!(grid.get(Channel::FoodTrail, x + 1, y) > 0);
+        assert_eq!(grid.get(Channel::FoodTrail, x + 2, y), 0);
+        assert_eq!(grid.get(Channel::HomeTrail, x + 1, y), 0);
+
+        for _ in 0..5000 {
+            grid.step();
+        }
+        assert!(grid.is_empty());
+    }
+
+    #[test]
+    fn test_gradient_and_sampling() {
+        let mut grid = grid();
+        grid.deposit(Channel::HomeTrail, FixedVec2::from_int(41, 11), 50000);
+        for _ in 0..10 {
+            grid.step();
+        }
+        let position = FixedVec2::from_int(35, 11);
+        let gradient = grid.gradient(Channel::HomeTrail, position, Fixed::from_int(2));
+        assert!(gradient.x > Fixed::ZERO);
+        assert_eq!(gradient.y, Fixed::ZERO);
+        assert!(
+            grid.sample(Channel::HomeTrail, FixedVec2::from_int(39, 11))
+                > grid.sample(Channel::HomeTrail, position)
+        );
+        let direction = grid.strongest_direction(
+            Channel::HomeTrail,
+            position,
+            Angle::QUARTER,
+            Angle::from_degrees(45),
+            Fixed::from_int(4),
+        );
+        assert_eq!(direction, Some(Angle::QUARTER - Angle::from_degrees(45)));
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut grid = grid();
+        let empty = bincode::serialize(&grid).unwrap();
+        assert!(empty.len() < 100);
+
+        let base = grid.clone();
+        grid.deposit(Channel::Danger, FixedVec2::from_int(3, 3), 1000);
+        grid.deposit(Channel::FoodTrail, FixedVec2::from_int(50, 20), 2000);
+        grid.step();
+        let restored: PheromoneGrid =
+            bincode::deserialize(&bincode::serialize(&grid).unwrap()).unwrap();
+        assert_eq!(restored, grid);
+
+        let delta = grid.delta(&base);
+        let mut patched = base.clone();
+        patched.apply_delta(&delta).unwrap();
+        assert_eq!(patched, grid);
+        assert_eq!(grid.texels().len(), 32 * 16);
+    }
+
+    #[test]
+    fn test_invalid_encoding() {
+        let decode = |width, height, cell_size| {
+            let encoded = EncodedGrid {
+                width,
+                height,
+                cell_size,
+                channels: std::array::from_fn(|_| vec![(width.wrapping_mul(height), 0)]),
+            };
+            bincode::deserialize::<PheromoneGrid>(&bincode::serialize(&encoded).unwrap())
+        };
+        assert!(decode(4, 2, Fixed::from_int(1)).is_ok());
+        assert!(decode(0, 2, Fixed::from_int(1)).is_err());
+        assert!(decode(4, 0, Fixed::from_int(1)).is_err());
+        assert!(decode(4, 2, Fixed::ZERO).is_err());
+        assert!(decode(1 << 16, 1 << 16, Fixed::from_int(1)).is_err());
+
+        // the broken last channel must not leave the first ones patched
+        let mut grid = grid();
+        let mut changed = grid.clone();
+        changed.deposit(Channel::FoodTrail, FixedVec2::from_int(3, 3), 1000);
+        let mut delta = changed.delta(&grid);
+        delta.channels[Channel::Danger.index()].clear();
+        let before = grid.clone();
+        assert!(grid.apply_delta(&delta).is_err());
+        assert_eq!(grid, before);
+    }
+}
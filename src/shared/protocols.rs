@@ -1,14 +1,101 @@
+use crate::shared::game::Command;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum DistributorClientMessages {
     AskForLobbies,
-    OpenLobby,
+    /// Opens a lobby playing the map with this name from the map directory.
+    OpenLobby {
+        map: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum DistributorServerMessages {
     Lobbies(Vec<SocketAddr>),
     LobbyOpened(SocketAddr),
+    LobbyRefused(String),
+}
+
+pub type PlayerId = u8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PlayerInfo {
+    pub id: PlayerId,
+    pub name: String,
+    /// Set with [`LobbyClientMessages::Ready`], the host can only start the
+    /// match once everyone is ready.
+    pub ready: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum LobbyClientMessages {
+    Join {
+        name: String,
+    },
+    /// Hash of the client's copy of the lobby's map.
+    HasMap {
+        hash: u64,
+    },
+    Ready(bool),
+    StartMatch,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum LobbyServerMessages {
+    Joined(PlayerId),
+    Refused(String),
+    /// Name of the lobby's map in the map directory, clients answer with
+    /// [`LobbyClientMessages::HasMap`].
+    Map {
+        name: String,
+        hash: u64,
+    },
+    Roster(Vec<PlayerInfo>),
+    MatchStarting {
+        seed: u64,
+        map_hash: u64,
+        roster: Vec<PlayerInfo>,
+        /// Udp port of the match's game server, on the lobby's host.
+        game_port: u16,
+        /// Server tick the match starts after, the first tick with commands
+        /// is the one after it.
+        start_tick: u64,
+        /// Secret of the receiving player, proving to the game server who
+        /// sends with [`GameClientMessages::Join`].
+        token: u64,
+    },
+}
+
+/// Ticks the game server keeps for [`GameClientMessages::Resend`]. A client
+/// missing an older tick can not catch up any more.
+pub const TICK_HISTORY: u64 = 1024;
+
+/// Sent over udp to the game server of a running match. Everything but
+/// [`GameClientMessages::Join`] is ignored from unregistered addresses.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum GameClientMessages {
+    /// Registers the sender as `player` for tick broadcasts, with the token
+    /// from [`LobbyServerMessages::MatchStarting`].
+    Join { player: PlayerId, token: u64 },
+    /// Keeps the sender registered.
+    KeepAlive,
+    /// The player is the one the sender joined as, whatever it says.
+    Command(Command),
+    /// Answered with [`GameServerMessages::Pong`], also keeps the sender
+    /// registered.
+    Ping(u64),
+    /// Asks for the ticks from `first` to `last` again after they were
+    /// lost, answered with a [`GameServerMessages::Tick`] each.
+    Resend { first: u64, last: u64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum GameServerMessages {
+    /// Commands every peer applies when stepping to `tick`. Only commands
+    /// that were broadcast like this happened.
+    Tick { tick: u64, commands: Vec<Command> },
+    /// Echoes the number of a [`GameClientMessages::Ping`].
+    Pong(u64),
 }
@@ -0,0 +1,205 @@
+//! Uniform grid spatial index for neighbour queries.
+//!
+//! The index is rebuilt from scratch every tick. Entries are bucketed by cell
+//! with a stable sort, so queries visit cells in row-major order and entities
+//! inside a cell in the order they were inserted, which keeps results
+//! identical on every peer.
+
+use crate::shared::ecs::Entity;
+use crate::shared::fixed::{Fixed, FixedVec2};
+
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    cell_size: Fixed,
+    width: u32,
+    height: u32,
+    cell_start: Vec<u32>,
+    entries: Vec<(u32, Entity, FixedVec2)>,
+}
+
+impl SpatialGrid {
+    /// Grid covering `world_size`. Positions outside of it are put into the
+    /// nearest edge cell.
+    pub fn new(world_size: FixedVec2, cell_size: Fixed) -> SpatialGrid {
+        let width = (world_size.x / cell_size).ceil().to_int().max(1) as u32;
+        let height = (world_size.y / cell_size).ceil().to_int().max(1) as u32;
+        SpatialGrid {
+            cell_size,
+            width,
+            height,
+            cell_start: vec![0; (width * height) as usize + 1],
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn cell_coordinates(&self, position: FixedVec2) -> (u32, u32) {
+        let x = (position.x / self.cell_size).to_int();
+        let y = (position.y / self.cell_size).to_int();
+        (
+            x.clamp(0, self.width as i32 - 1) as u32,
+            y.clamp(0, self.height as i32 - 1) as u32,
+        )
+    }
+
+    /// Replaces the contents of the index, keeping its allocations.
+    pub fn rebuild(&mut self, items: impl IntoIterator<Item = (Entity, FixedVec2)>) {
+        self.entries.clear();
+        for (entity, position) in items {
+            let (x, y) = self.cell_coordinates(position);
+            self.entries.push((y * self.width + x, entity, position));
+        }
+        self.entries.sort_by_key(|(cell, _, _)| *cell);
+
+        self.cell_start.fill(0);
+        for (cell, _, _) in self.entries.iter() {
+            self.cell_start[*cell as usize + 1] += 1;
+        }
+        for i in 1..self.cell_start.len() {
+            self.cell_start[i] += self.cell_start[i - 1];
+        }
+    }
+
+    /// Calls `f` for every entry inside the rectangle, borders included. The
+    /// rectangle is empty if `min` exceeds `max` on either axis.
+    pub fn for_each_in_rect(
+        &self,
+        min: FixedVec2,
+        max: FixedVec2,
+        mut f: impl FnMut(Entity, FixedVec2),
+    ) {
+        if min.x > max.x || min.y > max.y {
+            return;
+        }
+        let (min_x, min_y) = self.cell_coordinates(min);
+        let (max_x, max_y) = self.cell_coordinates(max);
+        for y in min_y..=max_y {
+            let first = (y * self.width + min_x) as usize;
+            let last = (y * self.width + max_x) as usize;
+            let range = self.cell_start[first] as usize..self.cell_start[last + 1] as usize;
+            for (_, entity, position) in &self.entries[range] {
+                if position.x >= min.x
+                    && position.x <= max.x
+                    && position.y >= min.y
+                    && position.y <= max.y
+                {
+                    f(*entity, *position);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` for every entry within `radius` of `center`.
+    pub fn for_each_in_radius(
+        &self,
+        center: FixedVec2,
+        radius: Fixed,
+        mut f: impl FnMut(Entity, FixedVec2),
+    ) {
+        let extent = FixedVec2::new(radius, radius);
+        let radius_squared = radius * radius;
+        self.for_each_in_rect(center - extent, center + extent, |entity, position| {
+            if center.distance_squared(position) <= radius_squared {
+                f(entity, position);
+            }
+        });
+    }
+
+    pub fn query_rect(&self, min: FixedVec2, max: FixedVec2) -> Vec<Entity> {
+        let mut result = Vec::new();
+        self.for_each_in_rect(min, max, |entity, _| result.push(entity));
+        result
+    }
+
+    pub fn query_radius(&self, center: FixedVec2, radius: Fixed) -> Vec<Entity> {
+        let mut result = Vec::new();
+        self.for_each_in_radius(center, radius, |entity, _| result.push(entity));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ecs::Entities;
+    use crate::shared::rng::Rng;
+
+    fn random_points(count: usize) -> Vec<(Entity, FixedVec2)> {
+        let mut entities = Entities::new();
+        let mut rng = Rng::new(31);
+        (0..count)
+            .map(|_| {
+                let x = rng.fixed_range(Fixed::from_int(-4), Fixed::from_int(68));
+                let y = rng.fixed_range(Fixed::from_int(-4), Fixed::from_int(68));
+                (entities.spawn(), FixedVec2::new(x, y))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let points = random_points(2000);
+        let mut grid = SpatialGrid::new(FixedVec2::from_int(64, 64), Fixed::from_int(4));
+        grid.rebuild(points.iter().copied());
+        assert_eq!(grid.len(), 2000);
+
+        let mut rng = Rng::new(5);
+        for _ in 0..50 {
+            let center = FixedVec2::new(
+                rng.fixed_range(Fixed::ZERO, Fixed::from_int(64)),
+                rng.fixed_range(Fixed::ZERO, Fixed::from_int(64)),
+            );
+            let radius = rng.fixed_range(Fixed::ZERO, Fixed::from_int(10));
+            let mut found = grid.query_radius(center, radius);
+            found.sort();
+            let expected: Vec<Entity> = points
+                .iter()
+                .filter(|(_, p)| center.distance_squared(*p) <= radius * radius)
+                .map(|(e, _)| *e)
+                .collect();
+            assert_eq!(found, expected);
+
+            let max = center + FixedVec2::from_int(7, 3);
+            let mut found = grid.query_rect(center, max);
+            found.sort();
+            let expected: Vec<Entity> = points
+                .iter()
+                .filter(|(_, p)| p.x >= center.x && p.x <= max.x && p.y >= center.y && p.y <= max.y)
+                .map(|(e, _)| *e)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_empty_rect() {
+        let mut grid = SpatialGrid::new(FixedVec2::from_int(64, 64), Fixed::from_int(4));
+        grid.rebuild(random_points(100));
+        let (a, b) = (FixedVec2::from_int(40, 10), FixedVec2::from_int(10, 40));
+        assert!(grid.query_rect(a, b).is_empty());
+        assert!(grid.query_rect(b, a).is_empty());
+        assert!(grid.query_radius(a, -Fixed::ONE).is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_is_deterministic() {
+        let points = random_points(500);
+        let mut a = SpatialGrid::new(FixedVec2::from_int(64, 64), Fixed::from_int(4));
+        let mut b = SpatialGrid::new(FixedVec2::from_int(64, 64), Fixed::from_int(4));
+        a.rebuild(points.iter().copied());
+        b.rebuild(random_points(10));
+        b.rebuild(points.iter().copied());
+        let center = FixedVec2::from_int(32, 32);
+        assert_eq!(
+            a.query_radius(center, Fixed::from_int(20)),
+            b.query_radius(center, Fixed::from_int(20))
+        );
+    }
+}
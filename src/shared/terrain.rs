@@ -0,0 +1,343 @@
+//! Tile based terrain of the world.
+//!
+//! One tile covers one world unit. Terrain can be written as text, one
+//! character per tile and one line per row starting at `y = 0`:
+//!
+//! | char | tile                        |
+//! |------|-----------------------------|
+//! | `.`  | ground                      |
+//! | `#`  | rock, never passable        |
+//! | `%`  | soil, passable once dug out |
+//! | `=`  | tunnel                      |
+//! | `*`  | ground with a food deposit  |
+
+use crate::shared::fixed::{Fixed, FixedVec2};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+/// Number of digs it takes to turn fresh soil into a tunnel.
+pub const SOIL_HARDNESS: u8 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tile {
+    Ground,
+    Rock,
+    Soil { hardness: u8 },
+    Tunnel,
+}
+
+impl Tile {
+    pub fn is_passable(self) -> bool {
+        matches!(self, Tile::Ground | Tile::Tunnel)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerrainError {
+    Empty,
+    RaggedRow {
+        row: usize,
+        width: usize,
+    },
+    UnknownTile {
+        row: usize,
+        column: usize,
+        found: char,
+    },
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerrainError::Empty => write!(f, "terrain has no tiles"),
+            TerrainError::RaggedRow { row, width } => {
+                write!(f, "row {} does not have the expected width {}", row, width)
+            }
+            TerrainError::UnknownTile { row, column, found } => {
+                write!(
+                    f,
+                    "unknown tile {:?} at row {} column {}",
+                    found, row, column
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Terrain {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    food_deposits: Vec<(u32, u32)>,
+    version: u64,
+}
+
+#[derive(Deserialize)]
+struct EncodedTerrain {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    food_deposits: Vec<(u32, u32)>,
+    version: u64,
+}
+
+impl<'de> Deserialize<'de> for Terrain {
+    /// Rejects tiles that don't cover the map exactly and food deposits off
+    /// the map, tile lookups index without further checks.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = EncodedTerrain::deserialize(deserializer)?;
+        let cells = encoded.width.checked_mul(encoded.height);
+        if cells.is_none_or(|cells| cells as usize != encoded.tiles.len()) {
+            return Err(D::Error::custom(format!(
+                "{} tiles do not cover a {}x{} terrain",
+                encoded.tiles.len(),
+                encoded.width,
+                encoded.height
+            )));
+        }
+        let off_map = encoded
+            .food_deposits
+            .iter()
+            .find(|(x, y)| *x >= encoded.width || *y >= encoded.height);
+        if let Some((x, y)) = off_map {
+            return Err(D::Error::custom(format!(
+                "food deposit at ({}, {}) is off the map",
+                x, y
+            )));
+        }
+        Ok(Terrain {
+            width: encoded.width,
+            height: encoded.height,
+            tiles: encoded.tiles,
+            food_deposits: encoded.food_deposits,
+            version: encoded.version,
+        })
+    }
+}
+
+impl Terrain {
+    /// Flat ground without obstacles.
+    pub fn open(width: u32, height: u32) -> Terrain {
+        Terrain {
+            width,
+            height,
+            tiles: vec![Tile::Ground; (width * height) as usize],
+            food_deposits: Vec::new(),
+            version: 0,
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<Terrain, TerrainError> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let width = rows.first().ok_or(TerrainError::Empty)?.chars().count();
+        let mut tiles = Vec::with_capacity(width * rows.len());
+        let mut food_deposits = Vec::new();
+        for (row, line) in rows.iter().enumerate() {
+            if line.chars().count() != width {
+                return Err(TerrainError::RaggedRow { row, width });
+            }
+            for (column, c) in line.chars().enumerate() {
+                let tile = match c {
+                    '.' => Tile::Ground,
+                    '#' => Tile::Rock,
+                    '%' => Tile::Soil {
+                        hardness: SOIL_HARDNESS,
+                    },
+                    '=' => Tile::Tunnel,
+                    '*' => {
+                        food_deposits.push((column as u32, row as u32));
+                        Tile::Ground
+                    }
+                    found => return Err(TerrainError::UnknownTile { row, column, found }),
+                };
+                tiles.push(tile);
+            }
+        }
+        Ok(Terrain {
+            width: width as u32,
+            height: rows.len() as u32,
+            tiles,
+            food_deposits,
+            version: 0,
+        })
+    }
+
+    /// Inverse of [`Terrain::from_text`], partially dug soil is written as soil.
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(((self.width + 1) * self.height) as usize);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = match self.tiles[self.index(x, y)] {
+                    Tile::Ground if self.food_deposits.contains(&(x, y)) => '*',
+                    Tile::Ground => '.',
+                    Tile::Rock => '#',
+                    Tile::Soil { .. } => '%',
+                    Tile::Tunnel => '=',
+                };
+                text.push(c);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn size(&self) -> FixedVec2 {
+        FixedVec2::from_int(self.width as i32, self.height as i32)
+    }
+
+    /// Tiles where food sources are placed at match start.
+    pub fn food_deposits(&self) -> &[(u32, u32)] {
+        &self.food_deposits
+    }
+
+    /// Bumped on every change, so derived data like flow fields knows when
+    /// to recompute.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Tile {
+        self.tiles[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile: Tile) {
+        let index = self.index(x, y);
+        if self.tiles[index] != tile {
+            self.tiles[index] = tile;
+            self.version += 1;
+        }
+    }
+
+    /// Outside of the map counts as impassable.
+    pub fn is_passable(&self, x: i32, y: i32) -> bool {
+        self.contains(x, y) && self.get(x as u32, y as u32).is_passable()
+    }
+
+    /// Tile containing a world position, if it is on the map.
+    pub fn tile_at(&self, position: FixedVec2) -> Option<(u32, u32)> {
+        let (x, y) = (position.x.to_int(), position.y.to_int());
+        self.contains(x, y).then_some((x as u32, y as u32))
+    }
+
+    pub fn tile_center(x: u32, y: u32) -> FixedVec2 {
+        FixedVec2::from_int(x as i32, y as i32) + FixedVec2::new(Fixed::HALF, Fixed::HALF)
+    }
+
+    /// Digs into soil, returns true once the tile has become a tunnel.
+    pub fn dig(&mut self, x: u32, y: u32) -> bool {
+        match self.get(x, y) {
+            Tile::Soil { hardness } if hardness > 1 => {
+                self.set(
+                    x,
+                    y,
+                    Tile::Soil {
+                        hardness: hardness - 1,
+                    },
+                );
+                false
+            }
+            Tile::Soil { .. } => {
+                self.set(x, y, Tile::Tunnel);
+                true
+            }
+            tile => tile == Tile::Tunnel,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+.....
+.#%*.
+..=..
+";
+
+    #[test]
+    fn test_text_roundtrip() {
+        let terrain = Terrain::from_text(MAP).unwrap();
+        assert_eq!((terrain.width(), terrain.height()), (5, 3));
+        assert_eq!(terrain.get(1, 1), Tile::Rock);
+        assert_eq!(terrain.get(2, 2), Tile::Tunnel);
+        assert_eq!(terrain.food_deposits(), &[(3, 1)]);
+        assert!(!terrain.is_passable(-1, 0));
+        assert!(!terrain.is_passable(2, 1));
+        assert_eq!(terrain.to_text(), MAP);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Terrain::from_text("\n"), Err(TerrainError::Empty));
+        assert_eq!(
+            Terrain::from_text("...\n..\n"),
+            Err(TerrainError::RaggedRow { row: 1, width: 3 })
+        );
+        assert_eq!(
+            Terrain::from_text("..x\n"),
+            Err(TerrainError::UnknownTile {
+                row: 0,
+                column: 2,
+                found: 'x'
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialization() {
+        let terrain = Terrain::from_text(MAP).unwrap();
+        let bytes = bincode::serialize(&terrain).unwrap();
+        assert_eq!(bincode::deserialize::<Terrain>(&bytes).unwrap(), terrain);
+
+        let decode = |terrain: &Terrain| {
+            bincode::deserialize::<Terrain>(&bincode::serialize(terrain).unwrap())
+        };
+        let mut short = terrain.clone();
+        short.tiles.pop();
+        assert!(decode(&short).is_err());
+        let mut overflowing = terrain.clone();
+        overflowing.width = u32::MAX;
+        assert!(decode(&overflowing).is_err());
+        let mut off_map = terrain.clone();
+        off_map.food_deposits.push((5, 0));
+        assert!(decode(&off_map).is_err());
+    }
+
+    #[test]
+    fn test_dig() {
+        let mut terrain = Terrain::from_text(MAP).unwrap();
+        let version = terrain.version();
+        for _ in 1..SOIL_HARDNESS {
+            assert!(!terrain.dig(2, 1));
+        }
+        assert!(terrain.dig(2, 1));
+        assert!(terrain.is_passable(2, 1));
+        assert!(!terrain.dig(1, 1));
+        assert!(terrain.version() > version);
+    }
+}
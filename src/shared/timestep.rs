@@ -0,0 +1,222 @@
+//! Fixed-timestep scheduling for the simulation.
+//!
+//! Real time is accumulated and converted into whole ticks, so the
+//! simulation advances at the same rate no matter how often the caller
+//! polls. After a stall at most `max_catch_up` ticks are run at once and the
+//! rest of the backlog is dropped, which slows the game down instead of
+//! freezing it while it tries to catch up.
+
+use crate::shared::config::Config;
+use log::warn;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TICK_RATE: u32 = 20;
+pub const DEFAULT_MAX_CATCH_UP: u32 = 5;
+/// Highest accepted tick rate, faster ones would round the tick duration
+/// down to nothing.
+pub const MAX_TICK_RATE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestepSettings {
+    /// Ticks per second.
+    pub tick_rate: u32,
+    /// Most ticks run by a single [`FixedTimestep::advance`].
+    pub max_catch_up: u32,
+}
+
+impl Default for TimestepSettings {
+    fn default() -> TimestepSettings {
+        TimestepSettings {
+            tick_rate: DEFAULT_TICK_RATE,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+        }
+    }
+}
+
+impl TimestepSettings {
+    /// Reads `simulation.tick_rate` and `simulation.max_catch_up`.
+    pub fn from_config(config: &Config) -> TimestepSettings {
+        let default = TimestepSettings::default();
+        let tick_rate = config.get_or("simulation.tick_rate", default.tick_rate);
+        let clamped = tick_rate.clamp(1, MAX_TICK_RATE);
+        if clamped != tick_rate {
+            warn!(
+                "simulation.tick_rate {} is not within 1 to {}, using {}",
+                tick_rate, MAX_TICK_RATE, clamped
+            );
+        }
+        TimestepSettings {
+            tick_rate: clamped,
+            max_catch_up: config
+                .get_or("simulation.max_catch_up", default.max_catch_up)
+                .max(1),
+        }
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// Ticks handed out by [`FixedTimestep::advance`].
+    pub ticks: u64,
+    /// Ticks dropped because they exceeded the catch-up cap.
+    pub dropped: u64,
+    /// Calls to `advance` that had to run more than one tick.
+    pub catch_ups: u64,
+    /// Time spent in the last tick, see [`FixedTimestep::record_tick`].
+    pub last_tick_time: Duration,
+    pub max_tick_time: Duration,
+    total_tick_time: Duration,
+    recorded: u64,
+}
+
+impl TickStats {
+    pub fn average_tick_time(&self) -> Duration {
+        if self.recorded == 0 {
+            return Duration::ZERO;
+        }
+        self.total_tick_time / self.recorded as u32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    settings: TimestepSettings,
+    tick_duration: Duration,
+    accumulator: Duration,
+    last: Option<Instant>,
+    stats: TickStats,
+}
+
+impl FixedTimestep {
+    pub fn new(settings: TimestepSettings) -> FixedTimestep {
+        FixedTimestep {
+            tick_duration: settings.tick_duration(),
+            settings,
+            accumulator: Duration::ZERO,
+            last: None,
+            stats: TickStats::default(),
+        }
+    }
+
+    pub fn settings(&self) -> TimestepSettings {
+        self.settings
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// Adds the time since the last call and returns how many ticks to run.
+    /// The first call only starts the clock.
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        if let Some(last) = self.last {
+            self.accumulator += now.saturating_duration_since(last);
+        }
+        self.last = Some(now);
+
+        let (accumulated, tick) = (self.accumulator.as_nanos(), self.tick_duration.as_nanos());
+        let due = (accumulated / tick) as u64;
+        let ticks = due.min(self.settings.max_catch_up as u64);
+        // whatever is left after the cap is dropped together with the ticks
+        self.accumulator = Duration::from_nanos((accumulated % tick) as u64);
+        self.stats.dropped += due - ticks;
+        self.stats.ticks += ticks;
+        if ticks > 1 {
+            self.stats.catch_ups += 1;
+        }
+        ticks as u32
+    }
+
+    /// How far the time is between the last tick and the next one, in
+    /// `[0, 1)`. Renderers interpolate between the last two states with it.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+
+    /// Time left until `advance` will return a tick again.
+    pub fn time_until_next_tick(&self, now: Instant) -> Duration {
+        let elapsed = match self.last {
+            Some(last) => self.accumulator + now.saturating_duration_since(last),
+            None => Duration::ZERO,
+        };
+        self.tick_duration.saturating_sub(elapsed)
+    }
+
+    /// Records how long running a tick took, for the statistics.
+    pub fn record_tick(&mut self, duration: Duration) {
+        self.stats.last_tick_time = duration;
+        self.stats.max_tick_time = self.stats.max_tick_time.max(duration);
+        self.stats.total_tick_time += duration;
+        self.stats.recorded += 1;
+    }
+
+    pub fn stats(&self) -> &TickStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestep() -> (FixedTimestep, Instant) {
+        let mut timestep = FixedTimestep::new(TimestepSettings {
+            tick_rate: 10,
+            max_catch_up: 3,
+        });
+        let start = Instant::now();
+        assert_eq!(timestep.advance(start), 0);
+        (timestep, start)
+    }
+
+    #[test]
+    fn test_ticks_at_fixed_rate() {
+        let (mut timestep, start) = timestep();
+        let mut ticks = 0;
+        // polling every 16ms for a second
+        for frame in 1..=62 {
+            ticks += timestep.advance(start + Duration::from_millis(16 * frame));
+            assert!((0.0..1.0).contains(&timestep.alpha()));
+        }
+        assert_eq!(ticks, 9);
+        assert!((timestep.alpha() - 0.92).abs() < 1e-3);
+        assert_eq!(
+            timestep.time_until_next_tick(start + Duration::from_millis(992)),
+            Duration::from_millis(8)
+        );
+        assert_eq!(timestep.stats().catch_ups, 0);
+    }
+
+    #[test]
+    fn test_catch_up_is_capped() {
+        let (mut timestep, start) = timestep();
+        assert_eq!(timestep.advance(start + Duration::from_millis(250)), 2);
+        assert_eq!(timestep.advance(start + Duration::from_millis(1280)), 3);
+        assert_eq!(timestep.stats().dropped, 7);
+        assert_eq!(timestep.stats().catch_ups, 2);
+        assert!((timestep.alpha() - 0.8).abs() < 1e-3);
+        assert_eq!(timestep.advance(start + Duration::from_millis(1300)), 1);
+    }
+
+    #[test]
+    fn test_settings_from_config() {
+        let config = Config::from_text("[simulation]\ntick_rate = 60\nmax_catch_up = 0").unwrap();
+        let settings = TimestepSettings::from_config(&config);
+        assert_eq!(settings.tick_rate, 60);
+        assert_eq!(settings.max_catch_up, 1);
+        for (tick_rate, expected) in [(0, 1), (2_000_000_000, MAX_TICK_RATE)] {
+            let config = Config::from_text(&format!("[simulation]\ntick_rate = {}", tick_rate));
+            let settings = TimestepSettings::from_config(&config.unwrap());
+            assert_eq!(settings.tick_rate, expected);
+            assert!(settings.tick_duration() > Duration::ZERO);
+        }
+        assert_eq!(
+            TimestepSettings::from_config(&Config::new()),
+            TimestepSettings::default()
+        );
+    }
+}